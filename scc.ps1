@@ -35,10 +35,6 @@ if (!$PSScriptRoot) {
     Write-Error "Could not find ddshow executable on the current path"
     Exit 1
 
-} elseif ($Command -Eq "regen" -And -Not (Get-Command -Name "python" -ErrorAction Stop)) {
-    Write-Error "Could not find python executable on the current path"
-    Exit 1
-
 } elseif ($Command -Ne "run" -And $Rebuild) {
     Write-Error "Can only pass -Rebuild with the 'run' subcommand"
     Exit 1
@@ -51,7 +47,11 @@ if (!$PSScriptRoot) {
 $ReleaseFlags = if ($Release) { "--release" } else { "" }
 $OutFolder = if ($Release) { "release" } else { "debug" }
 $Prefix = if ($Transformer) { "scc_transformer" } else { "scc" }
-$DataFile = if ($DataFile) { $DataFile } else { "$PSScriptRoot/scc.dat" }
+# Resolve the data file against the caller's location, since `Regen-Input` runs the
+# generator from inside the crate directory
+$DataFile = if ($DataFile) {
+    $ExecutionContext.SessionState.Path.GetUnresolvedProviderPathFromPSPath($DataFile)
+} else { "$PSScriptRoot/scc.dat" }
 $CodeDir = "$PSScriptRoot\$Prefix"
 $RustDir = "$CodeDir\$Prefix`_ddlog"
 $TraceDir = "$CodeDir\traces"
@@ -59,14 +59,18 @@ $Executable = "$RustDir\target\$OutFolder\$Prefix`_cli.exe"
 $DDlogFile = "$Prefix.dl"
 
 function Regen-Input {
-    Write-Host "Generating scc.dat..."
+    Write-Host "Generating $DataFile..."
 
-    # Run the python script to regenerate the graph data
-    python "$PSScriptRoot/graph.py"
+    # Run the workload generator to regenerate the graph data, the defaults
+    # match the old networkx `gnm_random_graph(1000, 10000)` workload
+    Push-Location -Path "$PSScriptRoot\scc\scc_ddlog"
+    cargo run --bin scc_gen $ReleaseFlags -- --nodes 1000 --edges 10000 --output $DataFile | Write-Host
+    $ExitCode = $LastExitCode
+    Pop-Location
 
-    if ($LastExitCode -ne 0) {
-        Write-Error "failed to run the regeneration script, exiting"
-        Exit $LastExitCode
+    if ($ExitCode -ne 0) {
+        Write-Error "failed to run the workload generator, exiting"
+        Exit $ExitCode
     }
 }
 
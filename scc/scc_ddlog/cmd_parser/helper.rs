@@ -0,0 +1,263 @@
+//! Line editor support for the interactive shell: tab completion of commands and relation and
+//! index names, and highlighting of input that doesn't parse.
+
+use crate::parse::CommandParser;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::Helper;
+use std::borrow::Cow;
+
+/// Keywords that start a command.
+pub const COMMANDS: &[&str] = &[
+    "checkpoint",
+    "clear",
+    "commit",
+    "count",
+    "count_index",
+    "delete",
+    "delete_key",
+    "dump",
+    "dump_csv",
+    "dump_index",
+    "echo",
+    "exit",
+    "help",
+    "insert",
+    "insert_or_update",
+    "load_csv",
+    "log_level",
+    "modify",
+    "mssleep",
+    "profile",
+    "query",
+    "query_index",
+    "query_index_prefix",
+    "query_index_range",
+    "rollback",
+    "rollback_to",
+    "savepoint",
+    "shutdown",
+    "start",
+    "timestamp",
+    "what_if",
+];
+
+/// Commands whose argument is a relation.
+const RELATION_COMMANDS: &[&str] = &[
+    "clear",
+    "count",
+    "delete",
+    "delete_key",
+    "dump",
+    "dump_csv",
+    "help",
+    "insert",
+    "insert_or_update",
+    "load_csv",
+    "modify",
+    "query",
+];
+
+/// Commands whose argument is an index.
+const INDEX_COMMANDS: &[&str] = &[
+    "count_index",
+    "dump_index",
+    "query_index",
+    "query_index_prefix",
+    "query_index_range",
+];
+
+/// Escape sequences that show input in red.
+const INVALID_START: &str = "\x1b[31m";
+const INVALID_END: &str = "\x1b[0m";
+
+/// `rustyline` helper for the command shell.
+#[derive(Debug, Default)]
+pub struct CommandHelper {
+    relations: Vec<String>,
+    indexes: Vec<String>,
+    /// Incomplete command typed on previous lines, which the current line continues.
+    pending: String,
+}
+
+impl CommandHelper {
+    pub fn new<R, I>(relations: R, indexes: I) -> Self
+    where
+        R: IntoIterator,
+        R::Item: Into<String>,
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        CommandHelper {
+            relations: relations.into_iter().map(Into::into).collect(),
+            indexes: indexes.into_iter().map(Into::into).collect(),
+            pending: String::new(),
+        }
+    }
+
+    /// Set the incomplete command that precedes the next line.
+    pub fn set_pending(&mut self, pending: &str) {
+        self.pending.clear();
+        self.pending.push_str(pending);
+    }
+
+    /// Completions of the word that ends at `pos` in `line`, and the position that word starts
+    /// at.  Commands are completed at the start of a command, and relation or index names after
+    /// the commands that take them.
+    pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<&str>) {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+
+        let context = format!("{}{}", self.pending, &line[..start]);
+        let words: Vec<&str> = current_command(&context).split_whitespace().collect();
+        let names: Vec<&str> = match words.as_slice() {
+            [] => COMMANDS.to_vec(),
+            [command] if RELATION_COMMANDS.contains(command) => {
+                self.relations.iter().map(String::as_str).collect()
+            }
+            [command] if INDEX_COMMANDS.contains(command) => {
+                self.indexes.iter().map(String::as_str).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        let candidates = names
+            .into_iter()
+            .filter(|name| name.starts_with(word))
+            .collect();
+        (start, candidates)
+    }
+
+    /// Whether `line`, following the pending input, contains a syntax error.  Input that ends in
+    /// the middle of a command is not an error.
+    pub fn is_invalid(&self, line: &str) -> bool {
+        CommandParser::default()
+            .push(&format!("{}{}", self.pending, line))
+            .iter()
+            .any(Result::is_err)
+    }
+}
+
+/// The text of the last command in `text`, i.e., what follows the last `;` or `,` that is not
+/// inside a string, record, or comment.
+fn current_command(text: &str) -> &str {
+    let mut start = 0;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut in_comment = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_comment {
+            if c == '\n' {
+                in_comment = false;
+                start = i + 1;
+            }
+        } else if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => (),
+            }
+        } else {
+            match c {
+                '"' => in_string = true,
+                '#' if depth == 0 => in_comment = true,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                ';' | ',' if depth == 0 => start = i + 1,
+                _ => (),
+            }
+        }
+    }
+    &text[start..]
+}
+
+impl Completer for CommandHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = self.candidates(line, pos);
+        let pairs = candidates
+            .into_iter()
+            .map(|name| Pair {
+                display: name.to_string(),
+                replacement: name.to_string(),
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for CommandHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CommandHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if self.is_invalid(line) {
+            Cow::Owned(format!("{}{}{}", INVALID_START, line, INVALID_END))
+        } else {
+            Cow::Borrowed(line)
+        }
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // The line may become valid or invalid with every character typed.
+        true
+    }
+}
+
+impl Validator for CommandHelper {}
+
+impl Helper for CommandHelper {}
+
+#[test]
+fn test_candidates() {
+    let mut helper = CommandHelper::new(
+        vec!["Connected", "Edge", "StronglyConnected"],
+        vec!["Edge_by_src"],
+    );
+
+    assert_eq!(helper.candidates("com", 3), (0, vec!["commit"]));
+    assert_eq!(
+        helper.candidates("start; query_index_r", 20),
+        (7, vec!["query_index_range"])
+    );
+    assert_eq!(
+        helper.candidates("insert Edge(1, 2), insert ", 26),
+        (26, vec!["Connected", "Edge", "StronglyConnected"])
+    );
+    assert_eq!(helper.candidates("dump S", 6), (5, vec!["StronglyConnected"]));
+    assert_eq!(helper.candidates("dump_index E", 12), (11, vec!["Edge_by_src"]));
+    // Record fields and arguments after the first one are not completed.
+    assert_eq!(helper.candidates("insert Edge(E", 13), (12, vec![]));
+    assert_eq!(helper.candidates("echo \"a; insert E", 17), (16, vec![]));
+
+    helper.set_pending("start;\ninsert Edge(1, 2),\n");
+    assert_eq!(helper.candidates("del", 3), (0, vec!["delete", "delete_key"]));
+}
+
+#[test]
+fn test_is_invalid() {
+    let mut helper = CommandHelper::default();
+
+    assert!(!helper.is_invalid("insert Edge(1, 2);"));
+    assert!(!helper.is_invalid("ins"));
+    assert!(!helper.is_invalid("insert Edge(1,"));
+    assert!(helper.is_invalid("insret Edge(1, 2);"));
+    assert!(helper.is_invalid("commit!"));
+
+    helper.set_pending("insert Edge(1,\n");
+    assert!(!helper.is_invalid("2);"));
+    assert!(helper.is_invalid("2]);"));
+}
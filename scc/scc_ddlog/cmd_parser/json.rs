@@ -0,0 +1,191 @@
+//! Parser for commands encoded as JSON objects, one per line, e.g.,
+//! `{"op":"insert","relation":"Edge","value":{"src":1,"dest":2}}`.
+//!
+//! The `op` field names the command: `start`, `commit`, `rollback`, `savepoint`, `rollback_to`,
+//! `clear`, `dump`, `insert`, `insert_or_update`, `delete`, `delete_key`, `exit`, `shutdown`
+//! or `what_if`, whose `updates` field is an array of update ops.  The other fields hold the
+//! command's arguments, named as in `{"op":"commit","dump_changes":true,"timeout":"5s"}`.
+//!
+//! Every object maps onto the same `Command` the text syntax produces.  Updates are never
+//! marked as the last one in a batch, so consecutive updates are applied together by the
+//! next non-update command (typically `commit`).
+
+use differential_datalog::record::{Record, RelIdentifier, UpdCmd};
+use num::BigInt;
+use ordered_float::OrderedFloat;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+#[cfg(test)]
+use std::time::Duration;
+
+use crate::{parse_duration, Command};
+
+/// Parse a single JSON command.
+pub fn parse_json_command(line: &str) -> Result<Command, String> {
+    let value: Value = serde_json::from_str(line).map_err(|e| format!("invalid JSON: {}", e))?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| "expected a JSON object".to_string())?;
+    json_command(object)
+}
+
+fn json_command(object: &Map<String, Value>) -> Result<Command, String> {
+    let op = str_field(object, "op")?;
+
+    match op {
+        "start" => Ok(Command::Start),
+        "commit" => {
+            let dump_changes = match object.get("dump_changes") {
+                None => false,
+                Some(Value::Bool(dump_changes)) => *dump_changes,
+                Some(_) => return Err("'dump_changes' must be a boolean".to_string()),
+            };
+            let timeout = match object.get("timeout") {
+                None => None,
+                Some(_) => Some(
+                    parse_duration(str_field(object, "timeout")?)
+                        .ok_or_else(|| "'timeout' must be a duration such as \"5s\"".to_string())?,
+                ),
+            };
+            Ok(Command::Commit(dump_changes, timeout))
+        }
+        "rollback" => Ok(Command::Rollback),
+        "exit" => Ok(Command::Exit),
+        "shutdown" => Ok(Command::Shutdown),
+        "savepoint" => Ok(Command::Savepoint(str_field(object, "name")?.to_string())),
+        "rollback_to" => Ok(Command::RollbackTo(str_field(object, "name")?.to_string())),
+        "clear" => Ok(Command::Clear(str_field(object, "relation")?.to_string())),
+        "dump" => match object.get("relation") {
+            None => Ok(Command::Dump(None)),
+            Some(_) => Ok(Command::Dump(Some(
+                str_field(object, "relation")?.to_string(),
+            ))),
+        },
+        "insert" => update(object, "value", UpdCmd::Insert),
+        "insert_or_update" => update(object, "value", UpdCmd::InsertOrUpdate),
+        "delete" => update(object, "value", UpdCmd::Delete),
+        "delete_key" => update(object, "key", UpdCmd::DeleteKey),
+        "what_if" => object
+            .get("updates")
+            .and_then(Value::as_array)
+            .ok_or_else(|| "'updates' must be an array of update ops".to_string())?
+            .iter()
+            .map(|update| match update.as_object().map(json_command) {
+                Some(Ok(Command::Update(update, _))) => Ok(update),
+                Some(Err(e)) => Err(e),
+                _ => Err("'updates' must be an array of update ops".to_string()),
+            })
+            .collect::<Result<_, _>>()
+            .map(Command::WhatIf),
+        op => Err(format!("unknown op '{}'", op)),
+    }
+}
+
+fn update(
+    object: &Map<String, Value>,
+    field: &str,
+    cmd: fn(RelIdentifier, Record) -> UpdCmd,
+) -> Result<Command, String> {
+    let relation = str_field(object, "relation")?;
+    let value = object
+        .get(field)
+        .ok_or_else(|| format!("missing '{}'", field))?;
+
+    Ok(Command::Update(
+        cmd(
+            RelIdentifier::RelName(Cow::from(relation.to_string())),
+            json_to_record(value),
+        ),
+        false,
+    ))
+}
+
+fn str_field<'a>(object: &'a Map<String, Value>, field: &str) -> Result<&'a str, String> {
+    match object.get(field) {
+        Some(Value::String(s)) => Ok(s),
+        Some(_) => Err(format!("'{}' must be a string", field)),
+        None => Err(format!("missing '{}'", field)),
+    }
+}
+
+/// Scalars become the matching `Record`s, so that they can be converted to primitive types.
+/// Anything else is passed on as `Record::Serialized`, which the generated types deserialize
+/// with `serde`.
+fn json_to_record(value: &Value) -> Record {
+    match value {
+        Value::Bool(b) => Record::Bool(*b),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Record::Int(BigInt::from(i))
+            } else if let Some(u) = n.as_u64() {
+                Record::Int(BigInt::from(u))
+            } else {
+                Record::Double(OrderedFloat(n.as_f64().unwrap_or_default()))
+            }
+        }
+        Value::String(s) => Record::String(s.clone()),
+        value => Record::Serialized(Cow::from("json"), value.to_string()),
+    }
+}
+
+#[test]
+fn test_json_command() {
+    assert_eq!(parse_json_command(r#"{"op":"start"}"#), Ok(Command::Start));
+    assert_eq!(
+        parse_json_command(r#"{"op":"shutdown"}"#),
+        Ok(Command::Shutdown)
+    );
+    assert_eq!(
+        parse_json_command(r#"{"op":"commit","dump_changes":true}"#),
+        Ok(Command::Commit(true, None))
+    );
+    assert_eq!(
+        parse_json_command(r#"{"op":"commit","timeout":"5s"}"#),
+        Ok(Command::Commit(false, Some(Duration::from_secs(5))))
+    );
+    assert!(parse_json_command(r#"{"op":"commit","timeout":5}"#).is_err());
+    assert_eq!(
+        parse_json_command(r#"{"op":"clear","relation":"Edge"}"#),
+        Ok(Command::Clear("Edge".to_string()))
+    );
+    assert_eq!(
+        parse_json_command(r#"{"op":"rollback_to","name":"sp"}"#),
+        Ok(Command::RollbackTo("sp".to_string()))
+    );
+    assert_eq!(
+        parse_json_command(r#"{"op":"insert","relation":"Edge","value":{"src":1,"dest":2}}"#),
+        Ok(Command::Update(
+            UpdCmd::Insert(
+                RelIdentifier::RelName(Cow::from("Edge")),
+                Record::Serialized(Cow::from("json"), r#"{"dest":2,"src":1}"#.to_string())
+            ),
+            false
+        ))
+    );
+    assert_eq!(
+        parse_json_command(r#"{"op":"delete_key","relation":"Node","key":5}"#),
+        Ok(Command::Update(
+            UpdCmd::DeleteKey(
+                RelIdentifier::RelName(Cow::from("Node")),
+                Record::Int(BigInt::from(5))
+            ),
+            false
+        ))
+    );
+    assert_eq!(
+        parse_json_command(r#"{"op":"insert","value":1}"#),
+        Err("missing 'relation'".to_string())
+    );
+    assert_eq!(
+        parse_json_command(
+            r#"{"op":"what_if","updates":[{"op":"delete_key","relation":"Node","key":5}]}"#
+        ),
+        Ok(Command::WhatIf(vec![UpdCmd::DeleteKey(
+            RelIdentifier::RelName(Cow::from("Node")),
+            Record::Int(BigInt::from(5))
+        )]))
+    );
+    assert!(parse_json_command(r#"{"op":"what_if","updates":[{"op":"commit"}]}"#).is_err());
+    assert!(parse_json_command(r#"{"op":"frobnicate"}"#).is_err());
+    assert!(parse_json_command("insert Edge(1, 2);").is_err());
+}
@@ -1,13 +1,20 @@
 #![warn(missing_debug_implementations)]
 
+mod helper;
+mod json;
 mod parse;
+mod query;
 
 use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
 
+pub use helper::*;
+pub use json::*;
 pub use parse::*;
+pub use query::*;
 
+use differential_datalog::DDlogInventory;
 use nom::*;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
@@ -16,25 +23,60 @@ const HISTORY_FILE: &str = "cmd_parser_history.txt";
 
 // We handle stdin differently depending on whether it is a user terminal or a pipe.
 enum Input {
-    Tty(Editor<()>),
+    Tty(Box<Editor<CommandHelper>>),
     Pipe(BufReader<io::Stdin>),
 }
 
+/// Syntax of the commands read from stdio.
+#[derive(Copy, Debug, PartialEq, Eq, Clone)]
+pub enum InputFormat {
+    /// DDlog command syntax, e.g., `insert Edge(1, 2);`.
+    Text,
+    /// One JSON object per line, see `parse_json_command`.
+    Json,
+}
+
 /// Parse commands from stdio.
 pub fn interact<F>(cb: F) -> Result<(), String>
+where
+    F: Fn(Command, bool) -> (Result<(), String>, bool),
+{
+    interact_with_format(InputFormat::Text, cb)
+}
+
+/// Parse commands in the given format from stdio.
+pub fn interact_with_format<F>(format: InputFormat, cb: F) -> Result<(), String>
+where
+    F: Fn(Command, bool) -> (Result<(), String>, bool),
+{
+    interact_with_inventory(format, None, cb)
+}
+
+/// Parse commands in the given format from stdio, completing the names of the relations and
+/// indexes in `inventory` when reading from a terminal.
+pub fn interact_with_inventory<F>(
+    format: InputFormat,
+    inventory: Option<&dyn DDlogInventory>,
+    cb: F,
+) -> Result<(), String>
 where
     F: Fn(Command, bool) -> (Result<(), String>, bool),
 {
     let mut buf: Vec<u8> = Vec::new();
+    let mut lineno: usize = 0;
 
     let istty = unsafe {
         // libc::STDIN_FILENO
         libc::isatty(0)
     } != 0;
     let mut input = if istty {
-        let mut rl = Editor::<()>::new();
+        let helper = inventory.map_or_else(CommandHelper::default, |inventory| {
+            CommandHelper::new(inventory.table_names(), inventory.index_names())
+        });
+        let mut rl = Editor::<CommandHelper>::new();
+        rl.set_helper(Some(helper));
         let _ = rl.load_history(HISTORY_FILE);
-        Input::Tty(rl)
+        Input::Tty(Box::new(rl))
     } else {
         Input::Pipe(BufReader::new(io::stdin()))
     };
@@ -42,10 +84,13 @@ where
     loop {
         let line = match &mut input {
             Input::Tty(rl) => {
+                if let Some(helper) = rl.helper_mut() {
+                    helper.set_pending(&String::from_utf8_lossy(&buf));
+                }
                 let readline = rl.readline(">> ");
                 match readline {
                     Ok(mut line) => {
-                        rl.add_history_entry(line.as_ref());
+                        rl.add_history_entry(line.as_str());
                         //println!("Line: {}", line);
                         // If `line` happens to be a comment, it must contain an `\n`, so that the
                         // parser can recognize its end.
@@ -58,11 +103,11 @@ where
                     }
                     Err(ReadlineError::Eof) => {
                         println!("CTRL-D");
-                        save_history(&rl);
+                        save_history(rl);
                         return Ok(());
                     }
                     Err(err) => {
-                        save_history(&rl);
+                        save_history(rl);
                         return Err(format!("Readline failure: {}", err));
                     }
                 }
@@ -82,6 +127,30 @@ where
                 line
             }
         };
+        lineno += 1;
+
+        if format == InputFormat::Json {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (result, cont) = match parse_json_command(line) {
+                Ok(cmd) => cb(cmd, istty),
+                Err(e) => {
+                    let err = format!("Invalid input at line {}: {}", lineno, e);
+                    if !istty {
+                        return Err(err);
+                    }
+                    eprintln!("{}", err);
+                    (Ok(()), true)
+                }
+            };
+            if !cont {
+                return result.map_err(|e| format!("line {}: {}", lineno, e));
+            }
+            continue;
+        }
 
         buf.extend_from_slice(line.as_bytes());
 
@@ -118,7 +187,7 @@ where
         }
     }
 
-    fn save_history(rl: &Editor<()>) {
+    fn save_history(rl: &mut Editor<CommandHelper>) {
         rl.save_history(HISTORY_FILE).unwrap()
     }
 }
@@ -0,0 +1,177 @@
+//! Appending the changes to an output relation to a CSV file after every commit.
+
+use crate::{ddval::DDValue, program::RelId, DDlogInventory, DeltaMap};
+use std::io::{self, Write};
+
+/// Appends the changes to an output relation to a CSV writer, one row per changed value with a
+/// trailing `weight` column (see `HDDlog::csv_deltas`).  The changes of every commit are passed
+/// to `write_changes`.
+pub struct CsvDeltaWriter<W> {
+    relid: RelId,
+    writer: W,
+    inventory: Box<dyn DDlogInventory + Send + Sync>,
+    header_written: bool,
+}
+
+impl<W: Write> CsvDeltaWriter<W> {
+    /// Start writing the changes to relation `relid` to `writer`, beginning with the header row
+    /// (see `DeltaMap::format_rel_csv`).
+    pub fn new(
+        relid: RelId,
+        mut writer: W,
+        inventory: Box<dyn DDlogInventory + Send + Sync>,
+    ) -> io::Result<Self> {
+        let header_written = DeltaMap::<DDValue>::new().format_rel_csv(
+            relid,
+            &mut writer,
+            &*inventory,
+            true,
+            true,
+        )?;
+        Ok(Self {
+            relid,
+            writer,
+            inventory,
+            header_written,
+        })
+    }
+
+    /// The relation whose changes are written.
+    pub fn relid(&self) -> RelId {
+        self.relid
+    }
+
+    /// Append the changes to the relation in `changes`, the changes made by a commit (see
+    /// `HDDlog::transaction_commit_dump_changes`), and flush them.
+    pub fn write_changes(&mut self, changes: &DeltaMap<DDValue>) -> io::Result<()> {
+        let header = changes.format_rel_csv(
+            self.relid,
+            &mut self.writer,
+            &*self.inventory,
+            !self.header_written,
+            true,
+        )?;
+        self.header_written |= header;
+        self.writer.flush()
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ddval::DDValConvert,
+        program::{ArrId, IdxId},
+        record::{FromRecord, Record, RelIdentifier},
+        DDlogError,
+    };
+    use fnv::FnvHashMap;
+    use std::any::TypeId;
+    #[cfg(feature = "c_api")]
+    use std::ffi::CStr;
+
+    /// Inventory of a program with a single relation `Count` of type `u64`.
+    #[derive(Clone)]
+    struct CountInventory;
+
+    impl DDlogInventory for CountInventory {
+        fn get_table_id(&self, _tname: &str) -> Result<RelId, DDlogError> {
+            Ok(0)
+        }
+
+        fn get_table_name(&self, _tid: RelId) -> Result<&'static str, DDlogError> {
+            Ok("Count")
+        }
+
+        fn get_table_original_name(&self, _tname: &str) -> Result<&'static str, DDlogError> {
+            Ok("Count")
+        }
+
+        #[cfg(feature = "c_api")]
+        fn get_table_original_cname(&self, _tname: &str) -> Result<&'static CStr, DDlogError> {
+            unimplemented!()
+        }
+
+        #[cfg(feature = "c_api")]
+        fn get_table_cname(&self, _tid: RelId) -> Result<&'static CStr, DDlogError> {
+            unimplemented!()
+        }
+
+        fn get_index_id(&self, iname: &str) -> Result<IdxId, DDlogError> {
+            Err(DDlogError::UnknownIndex(iname.to_string()))
+        }
+
+        fn get_index_name(&self, iid: IdxId) -> Result<&'static str, DDlogError> {
+            Err(DDlogError::UnknownIndex(iid.to_string()))
+        }
+
+        #[cfg(feature = "c_api")]
+        fn get_index_cname(&self, _iid: IdxId) -> Result<&'static CStr, DDlogError> {
+            unimplemented!()
+        }
+
+        fn table_names(&self) -> Vec<&'static str> {
+            vec!["Count"]
+        }
+
+        fn index_names(&self) -> Vec<&'static str> {
+            Vec::new()
+        }
+
+        fn get_table_schema(&self, _tid: RelId) -> Result<String, DDlogError> {
+            Ok("output relation Count[u64]".to_string())
+        }
+
+        fn input_relation_ids(&self) -> &'static FnvHashMap<RelId, &'static str> {
+            unimplemented!()
+        }
+
+        fn index_from_record(&self, index: IdxId, _key: &Record) -> Result<DDValue, DDlogError> {
+            Err(DDlogError::UnknownIndex(index.to_string()))
+        }
+
+        fn relation_type_id(&self, _relation: RelId) -> Option<TypeId> {
+            Some(TypeId::of::<u64>())
+        }
+
+        fn relation_value_from_record(
+            &self,
+            _relation: &RelIdentifier,
+            value: &Record,
+        ) -> Result<(RelId, DDValue), DDlogError> {
+            u64::from_record(value)
+                .map(|v| (0, v.into_ddvalue()))
+                .map_err(DDlogError::TypeMismatch)
+        }
+
+        fn relation_key_from_record(
+            &self,
+            relation: &RelIdentifier,
+            _key: &Record,
+        ) -> Result<(RelId, DDValue), DDlogError> {
+            Err(DDlogError::UnknownRelation(relation.to_string()))
+        }
+
+        fn index_to_arrangement_id(&self, _index: IdxId) -> Option<ArrId> {
+            None
+        }
+    }
+
+    #[test]
+    fn header_comes_before_any_changes() {
+        let mut writer = CsvDeltaWriter::new(0, Vec::new(), Box::new(CountInventory)).unwrap();
+        assert_eq!(writer.writer, b"value,weight\n");
+
+        let mut changes = DeltaMap::new();
+        writer.write_changes(&changes).unwrap();
+        changes.update(0, &5u64.into_ddvalue(), 1);
+        changes.update(0, &3u64.into_ddvalue(), -1);
+        writer.write_changes(&changes).unwrap();
+        assert_eq!(writer.into_inner(), b"value,weight\n3,-1\n5,1\n");
+    }
+}
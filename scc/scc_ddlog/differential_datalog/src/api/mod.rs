@@ -1,4 +1,7 @@
 mod c_api;
+pub mod csv_deltas;
+pub mod snapshot;
+pub mod subscription;
 pub mod update_handler;
 
 #[cfg(feature = "c_api")]
@@ -6,32 +9,54 @@ pub use c_api::*;
 
 use crate::flatbuf::FlatbufConverter;
 use crate::{
-    api::update_handler::{
-        ChainedUpdateHandler, DeltaUpdateHandler, IMTUpdateHandler, ThreadUpdateHandler,
-        UpdateHandler, ValMapUpdateHandler,
+    api::{
+        csv_deltas::CsvDeltaWriter,
+        snapshot::{Snapshot, SnapshotUpdateHandler},
+        subscription::{SlowConsumerPolicy, SubscriptionUpdateHandler},
+        update_handler::{
+            ChainedUpdateHandler, DeltaUpdateHandler, DivertingUpdateHandler, IMTUpdateHandler,
+            MTChainedUpdateHandler, ThreadUpdateHandler, UpdateHandler, ValMapUpdateHandler,
+        },
     },
+    checkpoint::read_checkpoint,
     ddlog::D3logLocalizer,
     ddval::DDValue,
-    program::{config::Config, IdxId, Program, RelId, RelationCallback, RunningProgram, Update},
-    record::{IntoRecord, Record, UpdCmd},
-    replay, CommandRecorder, D3log, D3logLocationId, DDlog, DDlogDump, DDlogDynamic,
-    DDlogInventory, DDlogProfiling, DeltaMap,
+    error::DDlogError,
+    program::{
+        config::Config, ArrangementCursor, ArrangementPage, CancellationToken, IdxId, KeyConverter,
+        KeyQuery, Program, RelId, RelationCallback, RunningProgram, Update, WorkerFailure,
+    },
+    record::{
+        record_from_columns, records_from_columns, IntoRecord, Record, RelIdentifier, UpdCmd,
+    },
+    replay,
+    wal::{TransactionLog, WriteAheadLog},
+    CommandRecorder, D3log, D3logLocationId, DDlog, DDlogDump, DDlogDynamic, DDlogInventory,
+    DDlogProfiling, DeltaMap,
 };
+use crossbeam_channel::Receiver;
+use serde::de::DeserializeOwned;
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, BTreeSet},
-    ffi::CString,
+    ffi::{CString, OsString},
     fmt,
-    fs::File,
-    io::{self, Write},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
     mem,
     os::raw::c_char,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 type BoxedInventory = Box<dyn DDlogInventory + Send + Sync + 'static>;
 type BoxedLocalizer = Box<dyn D3logLocalizer + Send + Sync + 'static>;
 type BoxedFlatbufConverter = Box<dyn FlatbufConverter + Send + Sync + 'static>;
 
+/// Number of CSV rows passed to `apply_updates` at a time by `HDDlog::load_csv`.
+const CSV_BATCH_SIZE: usize = 10_000;
+
 // TODO: Move HDDlog into the differential_datalog crate.
 pub struct HDDlog {
     pub prog: Mutex<RunningProgram>,
@@ -45,6 +70,18 @@ pub struct HDDlog {
     /// When set, all commands sent to the program are recorded in
     /// the specified `.dat` file so that they can be replayed later.
     pub command_recorder: Option<CommandRecorder<File, BoxedInventory>>,
+    /// When set, every committed transaction is appended to this write-ahead log before it
+    /// takes effect (see `recover_wal`).
+    pub wal: Option<Mutex<TransactionLog<BoxedInventory>>>,
+    /// Consumers of the changes to output relations registered with `subscribe`.
+    pub subscriptions: SubscriptionUpdateHandler,
+    /// Maintains the snapshots returned by `snapshot`, unless `do_store` is false.
+    pub snapshots: Option<SnapshotUpdateHandler>,
+    /// Cancels commits of `prog`, without locking it.
+    cancellation: CancellationToken,
+    /// Diverts the changes to output relations made by `evaluate_hypothetical` away from
+    /// `update_handler`'s other handlers.
+    hypothetical: DivertingUpdateHandler,
 }
 
 /* Internals */
@@ -57,14 +94,21 @@ impl HDDlog {
         inventory: BoxedInventory,
         d3log_localizer: BoxedLocalizer,
         flatbuf_converter: BoxedFlatbufConverter,
-    ) -> Result<(Self, DeltaMap<DDValue>), String> {
+    ) -> Result<(Self, DeltaMap<DDValue>), DDlogError> {
         let db: Arc<Mutex<DeltaMap<DDValue>>> = Arc::new(Mutex::new(DeltaMap::new()));
         let db2 = db.clone();
 
         let deltadb: Arc<Mutex<Option<DeltaMap<_>>>> = Arc::new(Mutex::new(Some(DeltaMap::new())));
         let deltadb2 = deltadb.clone();
 
-        let handler: Box<dyn IMTUpdateHandler> = {
+        let subscriptions = SubscriptionUpdateHandler::new();
+        let snapshots = if do_store {
+            Some(SnapshotUpdateHandler::new())
+        } else {
+            None
+        };
+
+        let hypothetical = {
             let handler_generator = move || {
                 // Always use delta handler, which costs nothing unless it is
                 // actually used
@@ -81,8 +125,18 @@ impl HDDlog {
                 }
             };
 
-            Box::new(ThreadUpdateHandler::new(handler_generator))
+            // Subscribers are served from the committing thread rather than the handler
+            // thread, so that a blocked subscriber delays only the commit.
+            let mut handlers: Vec<Arc<dyn IMTUpdateHandler>> = vec![
+                Arc::new(ThreadUpdateHandler::new(handler_generator)),
+                Arc::new(subscriptions.clone()),
+            ];
+            if let Some(snapshots) = &snapshots {
+                handlers.push(Arc::new(snapshots.clone()));
+            }
+            DivertingUpdateHandler::new(Arc::new(MTChainedUpdateHandler::new(handlers)))
         };
+        let handler: Box<dyn IMTUpdateHandler> = Box::new(hypothetical.clone());
 
         let program = init_ddlog(handler.mt_update_cb());
 
@@ -93,6 +147,7 @@ impl HDDlog {
 
         // Extract state after initial transaction
         let init_state = deltadb.lock().unwrap().take().unwrap();
+        let cancellation = prog.cancellation_token();
 
         let program = Self {
             prog: Mutex::new(prog),
@@ -104,6 +159,11 @@ impl HDDlog {
             d3log_localizer,
             flatbuf_converter,
             command_recorder: None,
+            wal: None,
+            subscriptions,
+            snapshots,
+            cancellation,
+            hypothetical,
         };
 
         Ok((program, init_state))
@@ -134,18 +194,43 @@ impl HDDlog {
         }
     }
 
+    /// Annotate every commit in the file written by `record_commands` with the changes to
+    /// output relations that it produced (see `CommandRecorder::set_record_changes`).  Commits
+    /// are then recorded as `commit dump_changes`.  Has no effect unless commands are being
+    /// recorded.
+    pub fn record_changes(&mut self, enable: bool) {
+        if let Some(ref mut recorder) = self.command_recorder {
+            recorder.set_record_changes(enable);
+        }
+    }
+
+    /// Stamp every transaction in the file written by `record_commands` with the time at which
+    /// it was started (see `CommandRecorder::set_record_timestamps`).  Has no effect unless
+    /// commands are being recorded.
+    pub fn record_timestamps(&mut self, enable: bool) {
+        if let Some(ref mut recorder) = self.command_recorder {
+            recorder.set_record_timestamps(enable);
+        }
+    }
+
     /// Apply a set of updates directly from the flatbuffer
     /// representation
     #[cfg_attr(feature = "flatbuf", doc(hidden))]
-    pub fn apply_updates_from_flatbuf(&self, buf: &[u8]) -> Result<(), String> {
-        let updates = self.flatbuf_converter.updates_from_buffer(buf)?;
+    pub fn apply_updates_from_flatbuf(&self, buf: &[u8]) -> Result<(), DDlogError> {
+        let updates = self
+            .flatbuf_converter
+            .updates_from_buffer(buf)
+            .map_err(DDlogError::TypeMismatch)?;
         self.apply_updates(&mut updates.into_iter())
     }
 
     /// Similar to `query_index`, but extracts query from a flatbuffer.
     #[cfg_attr(feature = "flatbuf", doc(hidden))]
-    pub fn query_index_from_flatbuf(&self, buf: &[u8]) -> Result<BTreeSet<DDValue>, String> {
-        let (index_id, key) = self.flatbuf_converter.query_index_from_buffer(buf)?;
+    pub fn query_index_from_flatbuf(&self, buf: &[u8]) -> Result<BTreeSet<DDValue>, DDlogError> {
+        let (index_id, key) = self
+            .flatbuf_converter
+            .query_index_from_buffer(buf)
+            .map_err(DDlogError::TypeMismatch)?;
         self.query_index(index_id, key)
     }
 
@@ -166,7 +251,7 @@ impl HDDlog {
     where
         F: FnOnce(
             &CommandRecorder<File, Box<dyn DDlogInventory + Send + Sync>>,
-        ) -> Result<T, String>,
+        ) -> Result<T, DDlogError>,
     {
         if let Some(ref r) = self.command_recorder {
             let _ = cmd(r).map_err(|e| {
@@ -178,9 +263,554 @@ impl HDDlog {
         }
     }
 
-    pub fn convert_update_command(&self, command: &UpdCmd) -> Result<Update<DDValue>, String> {
+    fn log_transaction<F>(&self, cmd: F) -> Result<(), DDlogError>
+    where
+        F: FnOnce(&mut TransactionLog<BoxedInventory>) -> Result<(), DDlogError>,
+    {
+        match self.wal {
+            Some(ref wal) => cmd(&mut wal.lock().unwrap()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn convert_update_command(&self, command: &UpdCmd) -> Result<Update<DDValue>, DDlogError> {
         command.to_update(&self.inventory)
     }
+
+    /// Converts records into keys of index `index`.
+    fn index_key_converter(&self, index: IdxId) -> KeyConverter {
+        let inventory = self.inventory.clone();
+        KeyConverter(Arc::new(move |key: &Record| {
+            inventory.index_from_record(index, key).ok()
+        }))
+    }
+
+    /// Make sure that the value of `update` has the type of its relation.
+    fn check_update_type(&self, update: &Update<DDValue>) -> Result<(), DDlogError> {
+        let relation_type = self
+            .inventory
+            .relation_type_id(update.relid())
+            .ok_or_else(|| DDlogError::UnknownRelation(update.relid().to_string()))?;
+
+        if let Some(value) = update.get_value() {
+            if relation_type != value.type_id() {
+                return Err(DDlogError::TypeMismatch(format!(
+                    "attempted to insert a value of type {:?} into relation {}, whose value type is {:?}",
+                    value.type_id(),
+                    self.inventory.get_table_name(update.relid())?,
+                    relation_type
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Insert every row of a CSV file into input relation `relation`.  Must be called inside a
+    /// transaction.
+    ///
+    /// If `header` is true, the first row names the record fields that the columns map onto;
+    /// otherwise columns map onto fields by position.  Columns are converted according to the
+    /// relation's type (see `record::record_from_columns`), or guessed from their contents for
+    /// relations whose type can't be described (see `record::records_from_columns`).  Returns
+    /// the number of inserted rows.
+    pub fn load_csv<R: io::Read>(
+        &self,
+        relation: &str,
+        reader: R,
+        header: bool,
+    ) -> Result<usize, DDlogError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(header)
+            .flexible(true)
+            .from_reader(reader);
+        let names: Option<Vec<String>> = if header {
+            let names = reader
+                .headers()
+                .map_err(|e| DDlogError::csv_read("failed to read CSV header", e))?;
+            Some(names.iter().map(|name| name.trim().to_string()).collect())
+        } else {
+            None
+        };
+
+        let relation_ident = RelIdentifier::RelName(Cow::from(relation.to_string()));
+        let template = self
+            .inventory
+            .get_table_id(relation)
+            .ok()
+            .and_then(|relid| self.inventory.relation_template(relid));
+        let mut batch = Vec::with_capacity(CSV_BATCH_SIZE);
+        let mut rows = 0;
+
+        for row in reader.records() {
+            let row = row.map_err(|e| DDlogError::csv_read("failed to read CSV file", e))?;
+            let line = row.position().map(|pos| pos.line()).unwrap_or_default();
+            let columns: Vec<&str> = row.iter().collect();
+
+            let mut error = None;
+            let candidates = match &template {
+                Some(template) => match record_from_columns(template, names.as_deref(), &columns) {
+                    Ok(record) => vec![record],
+                    Err(e) => {
+                        error = Some(DDlogError::TypeMismatch(e));
+                        Vec::new()
+                    }
+                },
+                None => records_from_columns(relation, names.as_deref(), &columns),
+            };
+            let converted = candidates.iter().find_map(|record| {
+                match self
+                    .inventory
+                    .relation_value_from_record(&relation_ident, record)
+                {
+                    Ok(converted) => Some(converted),
+                    Err(e) => {
+                        error.get_or_insert(e);
+                        None
+                    }
+                }
+            });
+            let (relid, v) = converted.ok_or_else(|| match error {
+                // The relation doesn't exist, or isn't an input relation.
+                Some(e @ DDlogError::UnknownRelation(_)) => e,
+                e => DDlogError::TypeMismatch(format!(
+                    "line {}: cannot convert row to {}: {}",
+                    line,
+                    relation,
+                    e.map(|e| e.to_string()).unwrap_or_default()
+                )),
+            })?;
+
+            batch.push(Update::Insert { relid, v });
+            rows += 1;
+            if batch.len() == CSV_BATCH_SIZE {
+                self.apply_updates(&mut batch.drain(..))?;
+            }
+        }
+
+        self.apply_updates(&mut batch.drain(..))?;
+        Ok(rows)
+    }
+
+    /// Save the contents of all input relations to checkpoint file `path` (see
+    /// `checkpoint`).  The checkpoint is written to a temporary file, which is synced to disk
+    /// and then renamed to `path`, so that `path` always holds a complete checkpoint.  Fails
+    /// if a transaction is in progress.
+    pub fn checkpoint<P: AsRef<Path>>(&self, path: P) -> Result<(), DDlogError> {
+        let path = path.as_ref();
+        let mut tmp_path = OsString::from(path);
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let file = File::create(&tmp_path)
+            .map_err(|e| DDlogError::io(format!("failed to create '{}'", tmp_path.display()), e))?;
+        let file = self
+            .prog
+            .lock()
+            .unwrap()
+            .checkpoint(BufWriter::new(file))?
+            .into_inner()
+            .map_err(|e| {
+                DDlogError::io(
+                    format!("failed to write '{}'", tmp_path.display()),
+                    e.into_error(),
+                )
+            })?;
+        file.sync_all()
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .map_err(|e| {
+                DDlogError::io(format!("failed to save checkpoint '{}'", path.display()), e)
+            })
+    }
+
+    /// Load the input relation contents saved by `checkpoint` in a single transaction and
+    /// return the resulting changes to output relations.  Intended to be called on startup,
+    /// before any other updates.  `U` is the program's `UpdateSerializer` type.
+    pub fn restore<U, P>(&self, path: P) -> Result<DeltaMap<DDValue>, DDlogError>
+    where
+        U: DeserializeOwned + Into<Update<DDValue>>,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| {
+            DDlogError::io(format!("failed to open checkpoint '{}'", path.display()), e)
+        })?;
+        let updates = read_checkpoint::<U, _>(BufReader::new(file)).map_err(|e| match e {
+            DDlogError::Io { context, source } => DDlogError::io(
+                format!("failed to restore '{}': {}", path.display(), context),
+                source,
+            ),
+            e => DDlogError::Other(format!("failed to restore '{}': {}", path.display(), e)),
+        })?;
+
+        self.transaction_start()?;
+        if let Err(e) = self.apply_updates(&mut updates.into_iter()) {
+            let _ = self.transaction_rollback();
+            return Err(e);
+        }
+        self.transaction_commit_dump_changes()
+    }
+
+    /// Recover the state saved in the write-ahead log in directory `dir` and log every
+    /// transaction committed from now on to it (see `wal`).  Intended to be called on startup,
+    /// before any other updates.
+    ///
+    /// The latest checkpoint in the log is restored (see `restore`), and the transactions
+    /// logged after it, except those whose commit failed, are passed to `replay` in the `.dat` format, to be parsed and executed
+    /// by the caller, typically with `cmd_parser`.  `U` is the program's `UpdateSerializer`
+    /// type.
+    pub fn recover_wal<U, P, F>(&mut self, dir: P, mut replay: F) -> Result<(), DDlogError>
+    where
+        U: DeserializeOwned + Into<Update<DDValue>>,
+        P: AsRef<Path>,
+        F: FnMut(&Self, &str) -> Result<(), DDlogError>,
+    {
+        if self.wal.is_some() {
+            return Err(DDlogError::other("write-ahead log is already open"));
+        }
+
+        let (log, recovery) = WriteAheadLog::open(dir)?;
+        if let Some(checkpoint) = recovery.checkpoint {
+            self.restore::<U, _>(checkpoint)?;
+        }
+        for (idx, transaction) in recovery.transactions.iter().enumerate() {
+            replay(self, transaction).map_err(|e| {
+                DDlogError::Other(format!(
+                    "failed to replay transaction {} of the write-ahead log: {}",
+                    idx, e
+                ))
+            })?;
+        }
+
+        self.wal = Some(Mutex::new(TransactionLog::new(log, self.inventory.clone())));
+        Ok(())
+    }
+
+    /// Save a checkpoint to the write-ahead log, which supersedes the transactions logged so
+    /// far.  Fails if no log is open or a transaction is in progress.
+    pub fn checkpoint_wal(&self) -> Result<(), DDlogError> {
+        let wal = self
+            .wal
+            .as_ref()
+            .ok_or_else(|| DDlogError::other("no write-ahead log is open"))?;
+        wal.lock().unwrap().checkpoint(|path| self.checkpoint(path))
+    }
+
+    /// The failure of a worker thread, if one failed.  Until the dataflow is restarted with
+    /// `restart`, all transactions and queries fail.
+    pub fn worker_failure(&self) -> Option<WorkerFailure> {
+        self.prog.lock().unwrap().worker_failure()
+    }
+
+    /// Whether a transaction is in progress (see `RunningProgram::transaction_in_progress`).
+    pub fn transaction_in_progress(&self) -> bool {
+        self.prog.lock().unwrap().transaction_in_progress()
+    }
+
+    /// Restart the dataflow after a worker failure from the contents of the input relations as
+    /// of the last commit (see `RunningProgram::restart`).  The transaction in progress, if
+    /// any, is discarded.
+    ///
+    /// The outputs of the restarted dataflow are the ones of the last commit, which snapshots
+    /// and subscribers already hold, so they see no changes.  If a write-ahead log is open, a
+    /// checkpoint is saved to it, since the log may contain the transaction whose commit
+    /// failed.
+    pub fn restart(&self) -> Result<(), DDlogError> {
+        let mut prog = self.prog.lock().unwrap();
+        if !prog.needs_restart() {
+            return Err(DDlogError::other("restart: the dataflow is running"));
+        }
+
+        let res = self.restart_prog(&mut prog, Vec::new());
+        drop(prog);
+
+        if let Some(wal) = &self.wal {
+            wal.lock().unwrap().transaction_rollback();
+        }
+        res?;
+
+        if self.wal.is_some() {
+            self.checkpoint_wal()?;
+        }
+        Ok(())
+    }
+
+    /// Restart the dataflow of `prog` (see `RunningProgram::restart`), then commit `updates`,
+    /// if any, without changing the outputs seen by snapshots and subscribers.
+    fn restart_prog(
+        &self,
+        prog: &mut RunningProgram,
+        updates: Vec<Update<DDValue>>,
+    ) -> Result<(), DDlogError> {
+        // Stop the remaining workers first, so that the outputs they retract while stopping
+        // don't reach the update handlers during the restart.
+        let _ = prog.stop();
+
+        // Stored outputs are rebuilt from scratch, while snapshots and subscribers discard the
+        // recomputed outputs.
+        *self.deltadb.lock().unwrap() = None;
+        if let Some(db) = &self.db {
+            *db.lock().unwrap() = DeltaMap::new();
+        }
+        self.update_handler.before_commit();
+        let res = prog.restart().and_then(|_| {
+            if updates.is_empty() {
+                return Ok(());
+            }
+            prog.transaction_start()?;
+            prog.apply_updates(updates.into_iter(), |_| Ok(()))?;
+            prog.transaction_commit_uncancellable()
+        });
+        self.update_handler.after_commit(false);
+        res
+    }
+
+    /// Token that cancels commits from other threads, including the thread blocked in a commit
+    /// (see `transaction_commit_with_deadline`).
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Commit the transaction, aborting the commit if it doesn't complete within `timeout` or
+    /// is cancelled (see `cancellation_token`).  An aborted commit rolls back the transaction,
+    /// restarts the dataflow as `restart` does, and fails with `DDlogError::Timeout` or
+    /// `DDlogError::Cancelled`.  Snapshots and subscribers never see its changes.
+    pub fn transaction_commit_with_deadline(&self, timeout: Duration) -> Result<(), DDlogError> {
+        self.commit(Some(timeout), false).map(|_| ())
+    }
+
+    /// Like `transaction_commit_with_deadline`, but returns the changes to output relations,
+    /// as `transaction_commit_dump_changes` does.
+    pub fn transaction_commit_dump_changes_with_deadline(
+        &self,
+        timeout: Duration,
+    ) -> Result<DeltaMap<DDValue>, DDlogError> {
+        self.commit(Some(timeout), true)
+            .map(|changes| changes.unwrap_or_else(DeltaMap::new))
+    }
+
+    /// Commit the transaction within `timeout`, if any, returning the changes to output
+    /// relations if `dump_changes` is true.
+    fn commit(
+        &self,
+        timeout: Option<Duration>,
+        dump_changes: bool,
+    ) -> Result<Option<DeltaMap<DDValue>>, DDlogError> {
+        // Recording the changes of a commit requires collecting them.
+        let dump_changes =
+            dump_changes || matches!(&self.command_recorder, Some(r) if r.records_changes());
+
+        self.record_command(|r| r.record_commit(dump_changes, timeout));
+        self.log_transaction(|log| log.transaction_commit())?;
+        if dump_changes {
+            *self.deltadb.lock().unwrap() = Some(DeltaMap::new());
+        }
+
+        self.update_handler.before_commit();
+        let res = {
+            let mut prog = self.prog.lock().unwrap();
+            match timeout {
+                Some(timeout) => prog.transaction_commit_with_deadline(timeout),
+                None => prog.transaction_commit(),
+            }
+        };
+        match res {
+            Ok(()) => {
+                self.update_handler.after_commit(true);
+                if !dump_changes {
+                    return Ok(None);
+                }
+
+                let delta = self.deltadb.lock().unwrap().take().unwrap();
+                self.record_command(|r| r.record_commit_changes(&delta));
+                Ok(Some(delta))
+            }
+
+            Err(e) => {
+                self.update_handler.after_commit(false);
+                // The transaction was logged before the commit, so recovery would replay it.
+                if let Err(log_error) = self.log_transaction(|log| log.transaction_abort()) {
+                    self.eprintln(&format!(
+                        "failed to abort the transaction in the write-ahead log: {}",
+                        log_error
+                    ));
+                }
+                if let DDlogError::Timeout(_) | DDlogError::Cancelled(_) = e {
+                    // Aborting the commit stopped the dataflow.
+                    self.restart()?;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Create a savepoint named `name` in the transaction in progress, which `rollback_to` can
+    /// later return to (see `RunningProgram::savepoint`).
+    pub fn savepoint(&self, name: &str) -> Result<(), DDlogError> {
+        self.record_command(|r| r.record_savepoint(name));
+        self.prog.lock().unwrap().savepoint(name)?;
+        self.log_transaction(|log| log.savepoint(name))
+    }
+
+    /// Undo the updates applied in the transaction in progress since savepoint `name` was
+    /// created, keeping the ones applied before (see `RunningProgram::rollback_to`).
+    pub fn rollback_to(&self, name: &str) -> Result<(), DDlogError> {
+        self.record_command(|r| r.record_rollback_to(name));
+        let res = self.prog.lock().unwrap().rollback_to(name);
+        match res {
+            Ok(()) => self.log_transaction(|log| log.rollback_to(name)),
+            // Nothing was undone.
+            Err(e @ DDlogError::UnknownSavepoint(_)) => Err(e),
+            Err(e) => {
+                if let Some(ref wal) = self.wal {
+                    wal.lock().unwrap().fail();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Evaluate `updates` hypothetically: commit them, then restore the previous contents of
+    /// input relations in a second commit, and return the changes to output relations made by
+    /// the first commit.  Stored outputs, snapshots, subscribers and the other update handlers
+    /// see neither commit.  Fails if a transaction is in progress.
+    ///
+    /// The commits can't be cancelled (see `cancellation_token`), as cancelling the second one
+    /// would leave the updates in place.  If the updates are committed but undoing them fails,
+    /// e.g., because a worker fails, the dataflow is restarted with the updates undone, and the
+    /// call fails with `DDlogError::HypotheticalUndo`.
+    pub fn evaluate_hypothetical(
+        &self,
+        updates: &mut dyn Iterator<Item = Update<DDValue>>,
+    ) -> Result<DeltaMap<DDValue>, DDlogError> {
+        let updates: Vec<_> = updates.collect();
+
+        let mut prog = self.prog.lock().unwrap();
+        match prog.transaction_start() {
+            Err(DDlogError::TransactionInProgress(_)) => {
+                return Err(DDlogError::TransactionInProgress("evaluate_hypothetical"))
+            }
+            res => res?,
+        }
+
+        self.hypothetical.divert();
+        let res = self.commit_hypothetical(&mut prog, updates.clone());
+        self.hypothetical.resume();
+        let changes = match res {
+            Ok(changes) => changes,
+            Err((e, None)) => return Err(e),
+            Err((e, Some(undo))) => {
+                let restored = self.restart_prog(&mut prog, undo).is_ok();
+                return Err(DDlogError::HypotheticalUndo {
+                    cause: Box::new(e),
+                    restored,
+                });
+            }
+        };
+        drop(prog);
+
+        self.record_command(|r| r.record_what_if(&updates));
+        Ok(changes)
+    }
+
+    /// Commit `updates` in the transaction started by `evaluate_hypothetical`, then undo them.
+    /// If the updates were committed but not undone, fails with the updates that undo them.
+    #[allow(clippy::type_complexity)]
+    fn commit_hypothetical(
+        &self,
+        prog: &mut RunningProgram,
+        updates: Vec<Update<DDValue>>,
+    ) -> Result<DeltaMap<DDValue>, (DDlogError, Option<Vec<Update<DDValue>>>)> {
+        if let Err(e) = prog.apply_updates(updates.into_iter(), |u| self.check_update_type(u)) {
+            return Err((prog.transaction_rollback().err().unwrap_or(e), None));
+        }
+        let undo = prog.transaction_undo_updates().map_err(|e| (e, None))?;
+        prog.transaction_commit_uncancellable()
+            .map_err(|e| (e, None))?;
+        let changes = self.hypothetical.take_diverted();
+
+        prog.transaction_start()
+            .and_then(|_| prog.apply_updates(undo.iter().cloned(), |_| Ok(())))
+            .and_then(|_| prog.transaction_commit_uncancellable())
+            .map(|_| changes)
+            .map_err(|e| (e, Some(undo)))
+    }
+
+    /// Immutable view of all output relations as of the last committed transaction.  Unlike
+    /// `dump_table`, it doesn't wait for a commit in progress, and the returned snapshot can be
+    /// read on any thread while further transactions are committed.
+    pub fn snapshot(&self) -> Result<Snapshot, DDlogError> {
+        self.snapshots
+            .as_ref()
+            .map(SnapshotUpdateHandler::snapshot)
+            .ok_or_else(|| {
+                DDlogError::other(
+                    "cannot take snapshot: ddlog_run() was invoked with do_store flag set to false",
+                )
+            })
+    }
+
+    /// Subscribe to the changes of output `relations` whose values pass `filter`.  After every
+    /// commit that changes such values, the changes are sent to the returned channel, which
+    /// buffers up to `capacity` commits; `policy` decides what happens when it is full.
+    /// Dropping the `Receiver` unsubscribes.
+    pub fn subscribe<F>(
+        &self,
+        relations: &[RelId],
+        filter: F,
+        capacity: usize,
+        policy: SlowConsumerPolicy,
+    ) -> Result<Receiver<DeltaMap<DDValue>>, DDlogError>
+    where
+        F: Fn(RelId, &DDValue) -> bool + Send + Sync + 'static,
+    {
+        for &relid in relations {
+            self.inventory.get_table_name(relid)?;
+            if self.inventory.input_relation_ids().contains_key(&relid) {
+                return Err(DDlogError::Other(format!(
+                    "cannot subscribe to input relation {}",
+                    self.inventory.get_table_name(relid)?
+                )));
+            }
+        }
+
+        Ok(self.subscriptions.subscribe(
+            relations.iter().copied().collect(),
+            Arc::new(filter),
+            capacity,
+            policy,
+        ))
+    }
+
+    /// Write the current contents of output relation `table` as CSV, with values flattened
+    /// into columns (see `record::flatten_record`) and a header row.  See `csv_deltas` for
+    /// writing the changes made by every commit instead.
+    pub fn dump_csv(&self, table: RelId, writer: &mut dyn Write) -> Result<(), DDlogError> {
+        let db = self.db.as_ref().ok_or_else(|| {
+            DDlogError::other(
+                "cannot dump table: ddlog_run() was invoked with do_store flag set to false",
+            )
+        })?;
+
+        db.lock()
+            .unwrap()
+            .format_rel_csv(table, writer, &*self.inventory, true, false)
+            .map(|_| ())
+            .map_err(|e| DDlogError::io("failed to write CSV", e))
+    }
+
+    /// Write the header row for the changes to relation `table` to `writer` and return a
+    /// `CsvDeltaWriter` that appends the changes made by every following commit, as returned
+    /// by `transaction_commit_dump_changes`, with an extra `weight` column.
+    pub fn csv_deltas<W: Write>(
+        &self,
+        table: RelId,
+        writer: W,
+    ) -> Result<CsvDeltaWriter<W>, DDlogError> {
+        self.inventory.get_table_name(table)?;
+        CsvDeltaWriter::new(table, writer, self.inventory.clone())
+            .map_err(|e| DDlogError::io("failed to write CSV", e))
+    }
 }
 
 impl DDlogDump for HDDlog {
@@ -242,34 +872,33 @@ impl DDlogDump for HDDlog {
         &self,
         table: RelId,
         cb: Option<&dyn Fn(&Record, isize) -> bool>,
-    ) -> Result<(), String> {
+    ) -> Result<(), DDlogError> {
         self.record_command(|r| r.dump_table(table, None));
         if let Some(ref db) = self.db {
             HDDlog::db_dump_table(&mut db.lock().unwrap(), table, cb);
             Ok(())
         } else {
-            Err(
-                "cannot dump table: ddlog_run() was invoked with do_store flag set to false"
-                    .to_string(),
-            )
+            Err(DDlogError::other(
+                "cannot dump table: ddlog_run() was invoked with do_store flag set to false",
+            ))
         }
     }
 }
 
 impl DDlogProfiling for HDDlog {
-    fn enable_cpu_profiling(&self, enable: bool) -> Result<(), String> {
+    fn enable_cpu_profiling(&self, enable: bool) -> Result<(), DDlogError> {
         self.record_command(|r| r.enable_cpu_profiling(enable));
         self.prog.lock().unwrap().enable_cpu_profiling(enable);
         Ok(())
     }
 
-    fn enable_timely_profiling(&self, enable: bool) -> Result<(), String> {
+    fn enable_timely_profiling(&self, enable: bool) -> Result<(), DDlogError> {
         self.record_command(|r| r.enable_timely_profiling(enable));
         self.prog.lock().unwrap().enable_timely_profiling(enable);
         Ok(())
     }
 
-    fn profile(&self) -> Result<String, String> {
+    fn profile(&self) -> Result<String, DDlogError> {
         self.record_command(|r| r.profile());
         let rprog = self.prog.lock().unwrap();
         let profile = rprog
@@ -283,31 +912,19 @@ impl DDlogProfiling for HDDlog {
 }
 
 impl DDlogDynamic for HDDlog {
-    fn transaction_start(&self) -> Result<(), String> {
+    fn transaction_start(&self) -> Result<(), DDlogError> {
         self.record_command(|r| r.transaction_start());
-        self.prog.lock().unwrap().transaction_start()
+        self.prog.lock().unwrap().transaction_start()?;
+        self.log_transaction(|log| log.transaction_start())
     }
 
-    fn transaction_commit(&self) -> Result<(), String> {
-        self.record_command(|r| r.transaction_commit());
-        self.update_handler.before_commit();
-
-        match self.prog.lock().unwrap().transaction_commit() {
-            Ok(()) => {
-                self.update_handler.after_commit(true);
-                Ok(())
-            }
-
-            Err(e) => {
-                self.update_handler.after_commit(false);
-                Err(e)
-            }
-        }
+    fn transaction_commit(&self) -> Result<(), DDlogError> {
+        self.commit(None, false).map(|_| ())
     }
 
     fn transaction_commit_dump_changes_dynamic(
         &self,
-    ) -> Result<BTreeMap<RelId, Vec<(Record, isize)>>, String> {
+    ) -> Result<BTreeMap<RelId, Vec<(Record, isize)>>, DDlogError> {
         self.record_command(|r| r.transaction_commit_dump_changes_dynamic());
         Ok(self
             .transaction_commit_dump_changes()?
@@ -322,19 +939,35 @@ impl DDlogDynamic for HDDlog {
             .collect())
     }
 
-    fn transaction_rollback(&self) -> Result<(), String> {
+    fn transaction_rollback(&self) -> Result<(), DDlogError> {
         self.record_command(|r| r.transaction_rollback());
-        self.prog.lock().unwrap().transaction_rollback()
+        self.prog.lock().unwrap().transaction_rollback()?;
+        if let Some(ref wal) = self.wal {
+            wal.lock().unwrap().transaction_rollback();
+        }
+        Ok(())
     }
 
-    fn clear_relation(&self, table: RelId) -> Result<(), String> {
+    fn clear_relation(&self, table: RelId) -> Result<(), DDlogError> {
         self.record_command(|r| r.clear_relation(table));
-        self.prog.lock().unwrap().clear_relation(table)
+        let res = self.prog.lock().unwrap().clear_relation(table);
+        match res {
+            Ok(()) => self.log_transaction(|log| log.clear_relation(table)),
+            Err(e) => {
+                if let Some(ref wal) = self.wal {
+                    wal.lock().unwrap().fail();
+                }
+                Err(e)
+            }
+        }
     }
 
-    fn apply_updates_dynamic(&self, upds: &mut dyn Iterator<Item = UpdCmd>) -> Result<(), String> {
+    fn apply_updates_dynamic(
+        &self,
+        upds: &mut dyn Iterator<Item = UpdCmd>,
+    ) -> Result<(), DDlogError> {
         let mut conversion_err = false;
-        let mut msg: Option<String> = None;
+        let mut msg: Option<DDlogError> = None;
 
         // Iterate through all updates, but only feed them to `apply_updates` until we reach
         // the first invalid command.
@@ -348,7 +981,13 @@ impl DDlogDynamic for HDDlog {
                     Ok(u) => Some(u),
                     Err(e) => {
                         conversion_err = true;
-                        msg = Some(format!("invalid command {:?}: {}", update, e));
+                        msg = Some(match e {
+                            e @ DDlogError::UnknownRelation(_) => e,
+                            e => DDlogError::TypeMismatch(format!(
+                                "invalid command {:?}: {}",
+                                update, e
+                            )),
+                        });
                         None
                     }
                 }
@@ -373,7 +1012,7 @@ impl DDlogDynamic for HDDlog {
         }
     }
 
-    fn query_index_dynamic(&self, index: IdxId, key: &Record) -> Result<Vec<Record>, String> {
+    fn query_index_dynamic(&self, index: IdxId, key: &Record) -> Result<Vec<Record>, DDlogError> {
         self.record_command(|r| r.query_index_dynamic(index, key));
 
         let key = self.inventory.index_from_record(index, key)?;
@@ -386,7 +1025,65 @@ impl DDlogDynamic for HDDlog {
         Ok(results)
     }
 
-    fn dump_index_dynamic(&self, index: IdxId) -> Result<Vec<Record>, String> {
+    fn query_index_range_dynamic(
+        &self,
+        index: IdxId,
+        lo: Option<&Record>,
+        hi: Option<&Record>,
+    ) -> Result<Vec<Record>, DDlogError> {
+        self.record_command(|r| r.query_index_range_dynamic(index, lo, hi));
+
+        let lo = lo
+            .map(|lo| self.inventory.index_from_record(index, lo))
+            .transpose()?;
+        let hi = hi
+            .map(|hi| self.inventory.index_from_record(index, hi))
+            .transpose()?;
+        let arrangement_id = self
+            .inventory
+            .index_to_arrangement_id(index)
+            .ok_or_else(|| DDlogError::UnknownIndex(index.to_string()))?;
+
+        let results = self
+            .prog
+            .lock()
+            .unwrap()
+            .query_arrangement_range(arrangement_id, lo, hi)?
+            .into_iter()
+            .map(DDValue::into_record)
+            .collect();
+
+        Ok(results)
+    }
+
+    fn query_index_prefix_dynamic(
+        &self,
+        index: IdxId,
+        prefix: &[Record],
+    ) -> Result<Vec<Record>, DDlogError> {
+        self.record_command(|r| r.query_index_prefix_dynamic(index, prefix));
+        let arrangement_id = self
+            .inventory
+            .index_to_arrangement_id(index)
+            .ok_or_else(|| DDlogError::UnknownIndex(index.to_string()))?;
+
+        let results = self
+            .prog
+            .lock()
+            .unwrap()
+            .query_arrangement_prefix(
+                arrangement_id,
+                prefix.to_vec(),
+                self.index_key_converter(index),
+            )?
+            .into_iter()
+            .map(DDValue::into_record)
+            .collect();
+
+        Ok(results)
+    }
+
+    fn dump_index_dynamic(&self, index: IdxId) -> Result<Vec<Record>, DDlogError> {
         self.record_command(|r| r.dump_index_dynamic(index));
         Ok(self
             .dump_index(index)?
@@ -395,57 +1092,41 @@ impl DDlogDynamic for HDDlog {
             .collect())
     }
 
-    fn stop(&self) -> Result<(), String> {
+    fn stop(&self) -> Result<(), DDlogError> {
         self.prog.lock().unwrap().stop()
     }
 }
 
 impl DDlog for HDDlog {
-    fn transaction_commit_dump_changes(&self) -> Result<DeltaMap<DDValue>, String> {
-        self.record_command(|r| r.transaction_commit_dump_changes());
-        *self.deltadb.lock().unwrap() = Some(DeltaMap::new());
-
-        self.update_handler.before_commit();
-        match self.prog.lock().unwrap().transaction_commit() {
-            Ok(()) => {
-                self.update_handler.after_commit(true);
-                let mut delta = self.deltadb.lock().unwrap();
-                Ok(delta.take().unwrap())
-            }
-
-            Err(e) => {
-                self.update_handler.after_commit(false);
-                Err(e)
-            }
-        }
+    fn transaction_commit_dump_changes(&self) -> Result<DeltaMap<DDValue>, DDlogError> {
+        self.commit(None, true)
+            .map(|changes| changes.unwrap_or_else(DeltaMap::new))
     }
 
-    fn apply_updates(&self, upds: &mut dyn Iterator<Item = Update<DDValue>>) -> Result<(), String> {
+    fn apply_updates(
+        &self,
+        upds: &mut dyn Iterator<Item = Update<DDValue>>,
+    ) -> Result<(), DDlogError> {
         // Make sure that the updates being inserted have the correct value types for their
         // relation
-        let inspect_update = |update: &Update<DDValue>| {
-            let relation_type = self
-                .inventory
-                .relation_type_id(update.relid())
-                .ok_or_else(|| format!("unknown relation id {}", update.relid()))?;
-
-            if let Some(value) = update.get_value() {
-                if relation_type != value.type_id() {
-                    return Err(format!("attempted to insert the incorrect type {:?} into relation {:?} whose value type is {:?}", value.type_id(), relation_type, relation_type));
-                }
-            }
+        let inspect_update = |update: &Update<DDValue>| self.check_update_type(update);
 
-            Ok(())
-        };
-
-        if self.command_recorder.is_some() {
+        if self.command_recorder.is_some() || self.wal.is_some() {
             let update_vec: Vec<_> = upds.collect();
             self.record_command(|r| r.apply_updates(&mut update_vec.iter().cloned()));
+            self.log_transaction(|log| log.apply_updates(&update_vec))?;
 
-            self.prog
+            let res = self
+                .prog
                 .lock()
                 .unwrap()
-                .apply_updates(&mut update_vec.into_iter(), inspect_update)
+                .apply_updates(&mut update_vec.into_iter(), inspect_update);
+            if let (Err(_), Some(wal)) = (&res, &self.wal) {
+                // Some of the updates may have taken effect, so the transaction can't be
+                // replayed faithfully.
+                wal.lock().unwrap().fail();
+            }
+            res
         } else {
             self.prog
                 .lock()
@@ -454,12 +1135,12 @@ impl DDlog for HDDlog {
         }
     }
 
-    fn query_index(&self, index: IdxId, key: DDValue) -> Result<BTreeSet<DDValue>, String> {
+    fn query_index(&self, index: IdxId, key: DDValue) -> Result<BTreeSet<DDValue>, DDlogError> {
         self.record_command(|r| r.query_index(index, key.clone()));
         let arrangement_id = self
             .inventory
             .index_to_arrangement_id(index)
-            .ok_or_else(|| format!("unknown index {}", index))?;
+            .ok_or_else(|| DDlogError::UnknownIndex(index.to_string()))?;
 
         self.prog
             .lock()
@@ -467,15 +1148,101 @@ impl DDlog for HDDlog {
             .query_arrangement(arrangement_id, key)
     }
 
-    fn dump_index(&self, index: IdxId) -> Result<BTreeSet<DDValue>, String> {
+    fn query_index_range(
+        &self,
+        index: IdxId,
+        lo: Option<DDValue>,
+        hi: Option<DDValue>,
+    ) -> Result<BTreeSet<DDValue>, DDlogError> {
+        self.record_command(|r| r.query_index_range(index, lo.clone(), hi.clone()));
+        let arrangement_id = self
+            .inventory
+            .index_to_arrangement_id(index)
+            .ok_or_else(|| DDlogError::UnknownIndex(index.to_string()))?;
+
+        self.prog
+            .lock()
+            .unwrap()
+            .query_arrangement_range(arrangement_id, lo, hi)
+    }
+
+    fn query_index_prefix(
+        &self,
+        index: IdxId,
+        prefix: &[Record],
+    ) -> Result<BTreeSet<DDValue>, DDlogError> {
+        self.record_command(|r| r.query_index_prefix(index, prefix));
+        let arrangement_id = self
+            .inventory
+            .index_to_arrangement_id(index)
+            .ok_or_else(|| DDlogError::UnknownIndex(index.to_string()))?;
+
+        self.prog.lock().unwrap().query_arrangement_prefix(
+            arrangement_id,
+            prefix.to_vec(),
+            self.index_key_converter(index),
+        )
+    }
+
+    fn dump_index(&self, index: IdxId) -> Result<BTreeSet<DDValue>, DDlogError> {
         self.record_command(|r| r.dump_index(index));
         let arrangement_id = self
             .inventory
             .index_to_arrangement_id(index)
-            .ok_or_else(|| format!("unknown index {}", index))?;
+            .ok_or_else(|| DDlogError::UnknownIndex(index.to_string()))?;
 
         self.prog.lock().unwrap().dump_arrangement(arrangement_id)
     }
+
+    fn count_relation(&self, table: RelId) -> Result<usize, DDlogError> {
+        self.record_command(|r| r.count_relation(table));
+        self.inventory.get_table_name(table)?;
+
+        if self.inventory.input_relation_ids().contains_key(&table) {
+            return self.prog.lock().unwrap().input_relation_size(table);
+        }
+        self.db
+            .as_ref()
+            .map(|db| db.lock().unwrap().try_get_rel(table).map_or(0, |rel| rel.len()))
+            .ok_or_else(|| {
+                DDlogError::Other(format!(
+                    "cannot count relation {}: ddlog_run() was invoked with do_store flag set to false",
+                    table
+                ))
+            })
+    }
+
+    fn count_index(&self, index: IdxId, key: Option<DDValue>) -> Result<usize, DDlogError> {
+        self.record_command(|r| r.count_index(index, key.clone()));
+        let arrangement_id = self
+            .inventory
+            .index_to_arrangement_id(index)
+            .ok_or_else(|| DDlogError::UnknownIndex(index.to_string()))?;
+
+        let query = key.map_or(KeyQuery::All, KeyQuery::Key);
+        self.prog
+            .lock()
+            .unwrap()
+            .count_arrangement(arrangement_id, query)
+    }
+
+    fn dump_index_page(
+        &self,
+        index: IdxId,
+        after: Option<ArrangementCursor>,
+        limit: usize,
+    ) -> Result<ArrangementPage, DDlogError> {
+        self.record_command(|r| r.dump_index_page(index, after.clone(), limit));
+        let arrangement_id = self
+            .inventory
+            .index_to_arrangement_id(index)
+            .ok_or_else(|| DDlogError::UnknownIndex(index.to_string()))?;
+
+        self.prog
+            .lock()
+            .unwrap()
+            .dump_arrangement_page(arrangement_id, after, limit)
+    }
 }
 
 impl D3log for HDDlog {
@@ -499,6 +1266,9 @@ impl fmt::Debug for HDDlog {
             .field("inventory", &(&*self.inventory as *const _))
             .field("d3log_localizer", &(&*self.d3log_localizer as *const _))
             .field("command_recorder", &self.command_recorder)
+            .field("wal", &self.wal)
+            .field("subscriptions", &self.subscriptions)
+            .field("snapshots", &self.snapshots)
             .finish()
     }
 }
@@ -0,0 +1,215 @@
+//! Point-in-time views of output relations that can be read while transactions commit.
+//!
+//! `SnapshotUpdateHandler` collects the changes made by every successful commit without
+//! blocking readers.  A new `Snapshot` is only built when one is requested after a commit: it
+//! copies the relations that changed since the previous snapshot and shares the others with it.
+
+use crate::{
+    api::update_handler::{MTUpdateHandler, SingleThreadedRelationCallback, UpdateHandler},
+    ddval::DDValue,
+    program::{RelId, RelationCallback},
+    DeltaMap,
+};
+use std::{
+    collections::btree_map::{BTreeMap, Entry},
+    fmt::{self, Debug, Formatter},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Immutable view of the contents of all output relations as of a committed transaction.
+/// Cloning a snapshot is cheap.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    relations: Arc<BTreeMap<RelId, Arc<BTreeMap<DDValue, isize>>>>,
+}
+
+impl Snapshot {
+    /// Contents of relation `relid` with their weights, or `None` if the relation has never
+    /// been non-empty.
+    pub fn relation(&self, relid: RelId) -> Option<&BTreeMap<DDValue, isize>> {
+        self.relations.get(&relid).map(|values| &**values)
+    }
+
+    /// Iterate over the relations that have ever been non-empty.
+    pub fn relations(&self) -> impl Iterator<Item = (RelId, &BTreeMap<DDValue, isize>)> {
+        self.relations
+            .iter()
+            .map(|(relid, values)| (*relid, &**values))
+    }
+
+    pub fn contains(&self, relid: RelId, v: &DDValue) -> bool {
+        self.relation(relid)
+            .is_some_and(|values| values.contains_key(v))
+    }
+
+    /// The snapshot with `changes` applied.
+    fn apply(&self, changes: DeltaMap<DDValue>) -> Self {
+        let mut relations = (*self.relations).clone();
+        for (relid, delta) in changes {
+            if delta.is_empty() {
+                continue;
+            }
+
+            // Copies the relation unless no other snapshot shares it.
+            let values = Arc::make_mut(relations.entry(relid).or_default());
+            for (v, w) in delta {
+                match values.entry(v) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(w);
+                    }
+                    Entry::Occupied(mut entry) => {
+                        *entry.get_mut() += w;
+                        if *entry.get() == 0 {
+                            entry.remove();
+                        }
+                    }
+                }
+            }
+        }
+
+        Self {
+            relations: Arc::new(relations),
+        }
+    }
+}
+
+/// Multi-threaded `UpdateHandler` that maintains `Snapshot`s of output relations.
+#[derive(Clone)]
+pub struct SnapshotUpdateHandler {
+    /// Set between `before_commit()` and `after_commit()`.
+    active: Arc<AtomicBool>,
+    /// Changes made by the commit in progress.
+    pending: Arc<Mutex<DeltaMap<DDValue>>>,
+    /// The latest snapshot that was built, and the changes committed since.
+    latest: Arc<Mutex<(Snapshot, DeltaMap<DDValue>)>>,
+}
+
+impl Debug for SnapshotUpdateHandler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SnapshotUpdateHandler")
+            .field("active", &self.active)
+            .finish()
+    }
+}
+
+impl Default for SnapshotUpdateHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SnapshotUpdateHandler {
+    pub fn new() -> Self {
+        Self {
+            active: Arc::new(AtomicBool::new(false)),
+            pending: Arc::new(Mutex::new(DeltaMap::new())),
+            latest: Arc::new(Mutex::new((Snapshot::default(), DeltaMap::new()))),
+        }
+    }
+
+    /// Snapshot of output relations as of the last committed transaction.  Never waits for a
+    /// commit in progress.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut latest = self.latest.lock().unwrap();
+        let (snapshot, changes) = &mut *latest;
+        if !changes.is_empty() {
+            *snapshot = snapshot.apply(mem::replace(changes, DeltaMap::new()));
+        }
+
+        snapshot.clone()
+    }
+
+    fn record(&self, relid: RelId, v: &DDValue, w: isize) {
+        // `update_cb` is also invoked during rollback and stop operations; ignore those.
+        if self.active.load(Ordering::Acquire) {
+            self.pending.lock().unwrap().update(relid, v, w);
+        }
+    }
+}
+
+impl UpdateHandler for SnapshotUpdateHandler {
+    fn update_cb(&self) -> Arc<dyn SingleThreadedRelationCallback> {
+        let handler = self.clone();
+        Arc::new(move |relid, v, w| handler.record(relid, v, w))
+    }
+
+    fn before_commit(&self) {
+        self.active.store(true, Ordering::Release);
+    }
+
+    fn after_commit(&self, success: bool) {
+        self.active.store(false, Ordering::Release);
+
+        let pending = mem::replace(&mut *self.pending.lock().unwrap(), DeltaMap::new());
+        if success {
+            let changes = &mut self.latest.lock().unwrap().1;
+            for (relid, delta) in pending {
+                for (v, w) in delta.iter() {
+                    changes.update(relid, v, *w);
+                }
+            }
+        }
+    }
+}
+
+impl MTUpdateHandler for SnapshotUpdateHandler {
+    fn mt_update_cb(&self) -> Arc<dyn RelationCallback> {
+        let handler = self.clone();
+        Arc::new(move |relid, v, w| handler.record(relid, v, w as isize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ddval::DDValConvert;
+
+    fn commit(handler: &SnapshotUpdateHandler, updates: &[(RelId, u64, isize)], success: bool) {
+        handler.before_commit();
+        let cb = handler.mt_update_cb();
+        for &(relid, v, w) in updates {
+            cb(relid, &v.into_ddvalue(), w as _);
+        }
+        handler.after_commit(success);
+    }
+
+    fn contents(snapshot: &Snapshot) -> Vec<(RelId, u64)> {
+        snapshot
+            .relations()
+            .flat_map(|(relid, values)| {
+                values
+                    .keys()
+                    .map(move |v| (relid, *u64::from_ddvalue_ref(v)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn snapshots() {
+        let handler = SnapshotUpdateHandler::new();
+        commit(&handler, &[(1, 1, 1), (1, 2, 1), (2, 3, 1)], true);
+        let first = handler.snapshot();
+        assert_eq!(contents(&first), vec![(1, 1), (1, 2), (2, 3)]);
+
+        // Later commits don't affect existing snapshots, and unchanged relations are shared.
+        commit(&handler, &[(1, 1, -1), (1, 4, 1)], true);
+        commit(&handler, &[(2, 5, 1)], false);
+        let second = handler.snapshot();
+        assert_eq!(contents(&first), vec![(1, 1), (1, 2), (2, 3)]);
+        assert_eq!(contents(&second), vec![(1, 2), (1, 4), (2, 3)]);
+        assert!(second.contains(1, &4u64.into_ddvalue()));
+        assert!(Arc::ptr_eq(&first.relations[&2], &second.relations[&2]));
+        assert!(Arc::ptr_eq(
+            &second.relations,
+            &handler.snapshot().relations
+        ));
+
+        // Updates outside of commits are ignored.
+        handler.mt_update_cb()(2, &6u64.into_ddvalue(), 1);
+        assert_eq!(contents(&handler.snapshot()), contents(&second));
+    }
+}
@@ -0,0 +1,310 @@
+//! Subscriptions to the changes of output relations.
+//!
+//! `SubscriptionUpdateHandler` collects the changes made by a transaction and, once it commits,
+//! sends every subscriber the changes to the relations it subscribed to that pass its filter.
+//! Every subscriber has its own bounded channel; its `SlowConsumerPolicy` decides what happens
+//! when the channel is full.  Subscribers unsubscribe by dropping their `Receiver`.
+
+use crate::{
+    api::update_handler::{MTUpdateHandler, SingleThreadedRelationCallback, UpdateHandler},
+    ddval::DDValue,
+    program::{RelId, RelationCallback},
+    DeltaMap,
+};
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Debug, Formatter},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// What to do with the changes of a commit when a subscriber's buffer is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+    /// Wait until the subscriber makes room, delaying the commit.
+    Block,
+    /// Discard the changes, which the subscriber will never see.
+    Drop,
+    /// Unsubscribe.  The subscriber still receives the changes buffered so far, after which its
+    /// channel is disconnected.
+    Disconnect,
+}
+
+/// Predicate selecting the values whose changes are sent to a subscriber.
+pub type SubscriptionFilter = Arc<dyn Fn(RelId, &DDValue) -> bool + Send + Sync>;
+
+struct Subscriber {
+    relations: BTreeSet<RelId>,
+    filter: SubscriptionFilter,
+    policy: SlowConsumerPolicy,
+    sender: Sender<DeltaMap<DDValue>>,
+}
+
+impl Subscriber {
+    /// Send the subscriber its share of `changes`.  Returns `false` if the subscriber must be
+    /// removed.
+    fn deliver(&self, changes: &DeltaMap<DDValue>) -> bool {
+        let mut delta = DeltaMap::new();
+        for (relid, values) in changes.iter() {
+            if self.relations.contains(relid) {
+                for (v, w) in values.iter() {
+                    if (self.filter)(*relid, v) {
+                        delta.update(*relid, v, *w);
+                    }
+                }
+            }
+        }
+        if delta.is_empty() {
+            return true;
+        }
+
+        match self.policy {
+            SlowConsumerPolicy::Block => self.sender.send(delta).is_ok(),
+            SlowConsumerPolicy::Drop => !matches!(
+                self.sender.try_send(delta),
+                Err(TrySendError::Disconnected(_))
+            ),
+            SlowConsumerPolicy::Disconnect => self.sender.try_send(delta).is_ok(),
+        }
+    }
+}
+
+/// Changes made by the commit in progress.
+struct Pending {
+    /// Relations with at least one subscriber.
+    relations: BTreeSet<RelId>,
+    changes: DeltaMap<DDValue>,
+}
+
+impl Default for Pending {
+    fn default() -> Self {
+        Self {
+            relations: BTreeSet::new(),
+            changes: DeltaMap::new(),
+        }
+    }
+}
+
+/// Multi-threaded `UpdateHandler` that streams the changes of every commit to subscribers.
+#[derive(Clone, Default)]
+pub struct SubscriptionUpdateHandler {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    /// Set between `before_commit()` and `after_commit()` if there are subscribers, so that
+    /// updates are ignored cheaply otherwise.
+    active: Arc<AtomicBool>,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl Debug for SubscriptionUpdateHandler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubscriptionUpdateHandler")
+            .field("subscribers", &self.subscribers.lock().unwrap().len())
+            .field("active", &self.active)
+            .finish()
+    }
+}
+
+impl SubscriptionUpdateHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a subscriber to the changes of `relations` that pass `filter`.  Up to
+    /// `capacity` commits worth of changes are buffered for the subscriber before `policy`
+    /// applies.  Commits that don't change any of its values aren't sent.
+    pub fn subscribe(
+        &self,
+        relations: BTreeSet<RelId>,
+        filter: SubscriptionFilter,
+        capacity: usize,
+        policy: SlowConsumerPolicy,
+    ) -> Receiver<DeltaMap<DDValue>> {
+        let (sender, receiver) = crossbeam_channel::bounded(capacity);
+        self.subscribers.lock().unwrap().push(Subscriber {
+            relations,
+            filter,
+            policy,
+            sender,
+        });
+
+        receiver
+    }
+
+    /// Number of registered subscribers, including ones that dropped their `Receiver` after
+    /// the last commit that concerned them.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    fn record(&self, relid: RelId, v: &DDValue, w: isize) {
+        // `update_cb` is also invoked during rollback and stop operations; ignore those.
+        if self.active.load(Ordering::Acquire) {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.relations.contains(&relid) {
+                pending.changes.update(relid, v, w);
+            }
+        }
+    }
+}
+
+impl UpdateHandler for SubscriptionUpdateHandler {
+    fn update_cb(&self) -> Arc<dyn SingleThreadedRelationCallback> {
+        let handler = self.clone();
+        Arc::new(move |relid, v, w| handler.record(relid, v, w))
+    }
+
+    fn before_commit(&self) {
+        let relations: BTreeSet<RelId> = self
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|subscriber| subscriber.relations.iter().copied())
+            .collect();
+
+        let active = !relations.is_empty();
+        *self.pending.lock().unwrap() = Pending {
+            relations,
+            changes: DeltaMap::new(),
+        };
+        self.active.store(active, Ordering::Release);
+    }
+
+    fn after_commit(&self, success: bool) {
+        if !self.active.swap(false, Ordering::AcqRel) {
+            return;
+        }
+
+        let changes = mem::replace(&mut self.pending.lock().unwrap().changes, DeltaMap::new());
+        if success && !changes.is_empty() {
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|subscriber| subscriber.deliver(&changes));
+        }
+    }
+}
+
+impl MTUpdateHandler for SubscriptionUpdateHandler {
+    fn mt_update_cb(&self) -> Arc<dyn RelationCallback> {
+        let handler = self.clone();
+        Arc::new(move |relid, v, w| handler.record(relid, v, w as isize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ddval::DDValConvert;
+
+    fn commit(handler: &SubscriptionUpdateHandler, updates: &[(RelId, u64, isize)]) {
+        handler.before_commit();
+        let cb = handler.mt_update_cb();
+        for &(relid, v, w) in updates {
+            cb(relid, &v.into_ddvalue(), w as _);
+        }
+        handler.after_commit(true);
+    }
+
+    fn describe(delta: DeltaMap<DDValue>) -> Vec<(RelId, String, isize)> {
+        delta
+            .into_iter()
+            .flat_map(|(relid, values)| {
+                values
+                    .into_iter()
+                    .map(move |(v, w)| (relid, v.to_string(), w))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filtered_changes() {
+        let handler = SubscriptionUpdateHandler::new();
+        let all = handler.subscribe(
+            [1, 2].iter().copied().collect(),
+            Arc::new(|_, _| true),
+            4,
+            SlowConsumerPolicy::Block,
+        );
+        let even = handler.subscribe(
+            [2].iter().copied().collect(),
+            Arc::new(|_, v| u64::from_ddvalue_ref(v) % 2 == 0),
+            4,
+            SlowConsumerPolicy::Block,
+        );
+
+        commit(&handler, &[(1, 1, 1), (2, 3, 1), (2, 4, 1), (3, 6, 1)]);
+        assert_eq!(
+            describe(all.try_recv().unwrap()),
+            vec![
+                (1, "1".to_string(), 1),
+                (2, "3".to_string(), 1),
+                (2, "4".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            describe(even.try_recv().unwrap()),
+            vec![(2, "4".to_string(), 1)]
+        );
+
+        // Commits that don't concern a subscriber aren't sent to it, and changes that cancel
+        // out aren't sent at all.
+        commit(&handler, &[(2, 5, 1)]);
+        commit(&handler, &[(1, 7, 1), (1, 7, -1)]);
+        assert_eq!(
+            describe(all.try_recv().unwrap()),
+            vec![(2, "5".to_string(), 1)]
+        );
+        assert!(all.try_recv().is_err());
+        assert!(even.try_recv().is_err());
+
+        // Failed commits are not published, and dropping the receiver unsubscribes.
+        handler.before_commit();
+        handler.mt_update_cb()(2, &8u64.into_ddvalue(), 1);
+        handler.after_commit(false);
+        assert!(even.try_recv().is_err());
+        drop(even);
+        commit(&handler, &[(2, 10, 1)]);
+        assert_eq!(handler.subscribers(), 1);
+    }
+
+    #[test]
+    fn slow_consumers() {
+        let handler = SubscriptionUpdateHandler::new();
+        let subscribe = |policy| {
+            handler.subscribe(
+                [1].iter().copied().collect(),
+                Arc::new(|_, _| true),
+                1,
+                policy,
+            )
+        };
+        let dropping = subscribe(SlowConsumerPolicy::Drop);
+        let disconnected = subscribe(SlowConsumerPolicy::Disconnect);
+
+        commit(&handler, &[(1, 1, 1)]);
+        commit(&handler, &[(1, 2, 1)]);
+        assert_eq!(handler.subscribers(), 1);
+
+        assert_eq!(
+            describe(dropping.try_recv().unwrap()),
+            vec![(1, "1".to_string(), 1)]
+        );
+        assert!(dropping.try_recv().is_err());
+        commit(&handler, &[(1, 3, 1)]);
+        assert_eq!(
+            describe(dropping.try_recv().unwrap()),
+            vec![(1, "3".to_string(), 1)]
+        );
+
+        assert_eq!(
+            describe(disconnected.recv().unwrap()),
+            vec![(1, "1".to_string(), 1)]
+        );
+        assert!(disconnected.recv().is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Durable checkpoints of the contents of input relations.
+//!
+//! A checkpoint file starts with `CHECKPOINT_MAGIC` and the format version as a little-endian
+//! `u32`, followed by one entry per value.  An entry is a `1` byte followed by the
+//! `bincode`-encoded `(polarity, relid, value)` tuple, which is the serialized form of the
+//! `UpdateSerializer` type generated for every DDlog program.  The file ends with a `0` byte and
+//! the number of entries as a little-endian `u64`, so that truncated checkpoints are detected.
+//!
+//! Values can only be deserialized by program-specific code, so `read_checkpoint` is generic
+//! over the program's `UpdateSerializer`.
+
+use crate::{ddval::DDValue, error::DDlogError, program::RelId, program::Update};
+use serde::de::DeserializeOwned;
+use std::io::{self, Read, Write};
+
+/// The first bytes of every checkpoint file.
+pub const CHECKPOINT_MAGIC: [u8; 8] = *b"DDLOGCKP";
+
+/// Version of the checkpoint format, bumped whenever it changes incompatibly.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+const ENTRY: u8 = 1;
+const END: u8 = 0;
+
+/// Writes a checkpoint one value at a time.
+#[derive(Debug)]
+pub struct CheckpointWriter<W: Write> {
+    writer: W,
+    entries: u64,
+}
+
+impl<W: Write> CheckpointWriter<W> {
+    /// Start a checkpoint by writing the header to `writer`.
+    pub fn new(mut writer: W) -> Result<Self, DDlogError> {
+        writer
+            .write_all(&CHECKPOINT_MAGIC)
+            .and_then(|_| writer.write_all(&CHECKPOINT_VERSION.to_le_bytes()))
+            .map_err(|e| DDlogError::io("failed to write checkpoint", e))?;
+
+        Ok(Self { writer, entries: 0 })
+    }
+
+    /// Add a value of relation `relid` to the checkpoint.  Values with negative `polarity`
+    /// are only needed for multisets with negative weights.
+    pub fn write(
+        &mut self,
+        polarity: bool,
+        relid: RelId,
+        value: &DDValue,
+    ) -> Result<(), DDlogError> {
+        self.writer
+            .write_all(&[ENTRY])
+            .map_err(|e| DDlogError::io("failed to write checkpoint", e))?;
+        bincode::serialize_into(&mut self.writer, &(polarity, relid, value)).map_err(|e| {
+            DDlogError::Other(format!(
+                "failed to serialize value of relation {}: {}",
+                relid, e
+            ))
+        })?;
+        self.entries += 1;
+
+        Ok(())
+    }
+
+    /// Write the end of the checkpoint and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, DDlogError> {
+        self.writer
+            .write_all(&[END])
+            .and_then(|_| self.writer.write_all(&self.entries.to_le_bytes()))
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| DDlogError::io("failed to write checkpoint", e))?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Read a checkpoint, returning the `Insert` (and possibly `DeleteValue`) updates that restore
+/// the checkpointed state.  `U` is the program's `UpdateSerializer`.
+pub fn read_checkpoint<U, R>(mut reader: R) -> Result<Vec<Update<DDValue>>, DDlogError>
+where
+    U: DeserializeOwned + Into<Update<DDValue>>,
+    R: Read,
+{
+    let mut magic = [0; 8];
+    let mut version = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .and_then(|_| reader.read_exact(&mut version))
+        .map_err(|e| DDlogError::io("failed to read checkpoint header", e))?;
+    if magic != CHECKPOINT_MAGIC {
+        return Err(DDlogError::other("not a DDlog checkpoint"));
+    }
+    let version = u32::from_le_bytes(version);
+    if version != CHECKPOINT_VERSION {
+        return Err(DDlogError::Other(format!(
+            "unsupported checkpoint version {} (expected {})",
+            version, CHECKPOINT_VERSION
+        )));
+    }
+
+    let mut updates = Vec::new();
+    loop {
+        let mut tag = [0; 1];
+        reader.read_exact(&mut tag).map_err(truncated)?;
+
+        match tag[0] {
+            ENTRY => {
+                let update: U = bincode::deserialize_from(&mut reader).map_err(|e| {
+                    DDlogError::Other(format!(
+                        "failed to deserialize checkpoint entry {}: {}",
+                        updates.len(),
+                        e
+                    ))
+                })?;
+                updates.push(update.into());
+            }
+            END => {
+                let mut entries = [0; 8];
+                reader.read_exact(&mut entries).map_err(truncated)?;
+                let entries = u64::from_le_bytes(entries);
+                if entries != updates.len() as u64 {
+                    return Err(DDlogError::Other(format!(
+                        "corrupted checkpoint: expected {} entries, found {}",
+                        entries,
+                        updates.len()
+                    )));
+                }
+
+                return Ok(updates);
+            }
+            tag => {
+                return Err(DDlogError::Other(format!(
+                    "corrupted checkpoint: unexpected tag {}",
+                    tag
+                )))
+            }
+        }
+    }
+}
+
+fn truncated(e: io::Error) -> DDlogError {
+    DDlogError::io("truncated checkpoint", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ddval::DDValConvert;
+    use serde::{Deserialize, Deserializer};
+
+    /// Stand-in for a program's `UpdateSerializer` whose relations all store `u64`s.
+    struct TestUpdate(Update<DDValue>);
+
+    impl<'de> Deserialize<'de> for TestUpdate {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let (polarity, relid, v) = <(bool, RelId, u64)>::deserialize(deserializer)?;
+            let v = v.into_ddvalue();
+            Ok(TestUpdate(if polarity {
+                Update::Insert { relid, v }
+            } else {
+                Update::DeleteValue { relid, v }
+            }))
+        }
+    }
+
+    impl From<TestUpdate> for Update<DDValue> {
+        fn from(update: TestUpdate) -> Self {
+            update.0
+        }
+    }
+
+    fn describe(updates: &[Update<DDValue>]) -> Vec<(bool, RelId, String)> {
+        updates
+            .iter()
+            .map(|update| {
+                (
+                    update.is_insert(),
+                    update.relid(),
+                    update.get_value().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let mut checkpoint = CheckpointWriter::new(Vec::new()).unwrap();
+        checkpoint.write(true, 1, &5u64.into_ddvalue()).unwrap();
+        checkpoint.write(false, 3, &7u64.into_ddvalue()).unwrap();
+        let bytes = checkpoint.finish().unwrap();
+
+        let updates = read_checkpoint::<TestUpdate, _>(bytes.as_slice()).unwrap();
+        assert_eq!(
+            describe(&updates),
+            vec![(true, 1, "5".to_string()), (false, 3, "7".to_string())]
+        );
+
+        // Any truncation is detected.
+        for len in 0..bytes.len() {
+            assert!(read_checkpoint::<TestUpdate, _>(&bytes[..len]).is_err());
+        }
+    }
+}
@@ -6,8 +6,10 @@
 
 pub mod api;
 mod callback;
+pub mod checkpoint;
 mod dataflow;
 mod ddlog;
+mod error;
 pub mod flatbuf;
 mod profile;
 mod profile_statistics;
@@ -15,6 +17,7 @@ mod render;
 pub mod replay;
 mod valmap;
 mod variable;
+pub mod wal;
 
 #[macro_use]
 pub mod ddval;
@@ -29,8 +32,9 @@ mod test_record;
 pub use callback::Callback;
 pub use ddlog::{
     D3log, D3logLocalizer, D3logLocationId, DDlog, DDlogDump, DDlogDynamic, DDlogInventory,
-    DDlogProfiling,
+    DDlogProfiling, IndexValues,
 };
+pub use error::DDlogError;
 pub use replay::CommandRecorder;
 pub use triomphe;
 pub use valmap::DeltaMap;
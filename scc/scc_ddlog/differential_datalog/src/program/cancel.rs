@@ -0,0 +1,71 @@
+//! Cancellation of commits from other threads.
+
+use crossbeam_channel::{Receiver, Sender};
+
+/// Cancels commits of a `RunningProgram` from another thread, e.g., while the thread that owns
+/// the program is blocked in `transaction_commit` (see `RunningProgram::cancellation_token`).
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    sender: Sender<()>,
+}
+
+impl CancellationToken {
+    /// Abort the commit of the transaction in progress as if its deadline expired.  If the
+    /// transaction isn't being committed yet, its commit is aborted as soon as it starts.
+    /// Cancellations made while no transaction is in progress are discarded by the next
+    /// `transaction_start`, so they have no effect.
+    pub fn cancel(&self) {
+        // A cancellation is already pending if the channel is full.
+        let _ = self.sender.try_send(());
+    }
+}
+
+/// Receiving end of the `CancellationToken`s of a program.
+#[derive(Debug)]
+pub(crate) struct Cancellation {
+    sender: Sender<()>,
+    receiver: Receiver<()>,
+}
+
+impl Cancellation {
+    pub(crate) fn new() -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        Self { sender, receiver }
+    }
+
+    pub(crate) fn token(&self) -> CancellationToken {
+        CancellationToken {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Receives a message for every pending cancellation.
+    pub(crate) fn receiver(&self) -> &Receiver<()> {
+        &self.receiver
+    }
+
+    /// Discard the pending cancellation, if any.
+    pub(crate) fn clear(&self) {
+        let _ = self.receiver.try_recv();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cancellation;
+
+    #[test]
+    fn cancel_is_idempotent() {
+        let cancellation = Cancellation::new();
+        let token = cancellation.token();
+        token.clone().cancel();
+        token.cancel();
+
+        assert!(cancellation.receiver().try_recv().is_ok());
+        assert!(cancellation.receiver().try_recv().is_err());
+
+        token.cancel();
+        cancellation.clear();
+        assert!(cancellation.receiver().try_recv().is_err());
+    }
+}
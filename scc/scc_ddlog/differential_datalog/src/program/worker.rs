@@ -8,8 +8,10 @@ use crate::{
     program::{
         arrange::{Arrangement, Arrangements},
         config::{Config, LoggingDestination, ProfilingConfig},
-        ArrId, Dep, Msg, ProgNode, Program, Reply, Update, TS,
+        ArrId, ArrangementCursor, Dep, FailureState, KeyConverter, KeyQuery, Msg, ProgNode,
+        Program, Reply, Update, TS,
     },
+    record::{prefix_lower_bound, record_has_prefix, IntoRecord},
     render::RenderContext,
     variable::Variable,
 };
@@ -97,6 +99,9 @@ pub struct DDlogWorker<'a> {
     reply_sender: Sender<Reply>,
     /// The logger for timely events
     logger: Option<TimelyLogger>,
+    /// Failure of any worker of the program, checked while waiting for progress so that the
+    /// remaining workers don't wait forever for the failed one
+    failure: Arc<FailureState>,
 }
 
 impl<'a> DDlogWorker<'a> {
@@ -110,6 +115,7 @@ impl<'a> DDlogWorker<'a> {
         request_receivers: Arc<[Receiver<Msg>]>,
         reply_senders: Arc<[Sender<Reply>]>,
         logger: Option<TimelyLogger>,
+        failure: Arc<FailureState>,
     ) -> Self {
         let worker_index = worker.index();
 
@@ -121,6 +127,7 @@ impl<'a> DDlogWorker<'a> {
             request_receiver: request_receivers[worker_index].clone(),
             reply_sender: reply_senders[worker_index].clone(),
             logger,
+            failure,
         }
     }
 
@@ -172,7 +179,7 @@ impl<'a> DDlogWorker<'a> {
                     // before flushing & compacting all previous timestamp's traces
                     Msg::Flush { advance_to } => {
                         self.advance(&mut session_data, advance_to);
-                        self.flush(&mut session_data, &probe);
+                        self.flush(&mut session_data, &probe)?;
                         timestamp = advance_to;
 
                         self.reply_sender
@@ -181,14 +188,22 @@ impl<'a> DDlogWorker<'a> {
                     }
 
                     // Handle queries
-                    Msg::Query(arrid, key) => {
-                        self.handle_query(&mut session_data.traces, arrid, key)?
+                    Msg::Query(arrid, query) => {
+                        self.handle_query(&mut session_data.traces, arrid, query)?
                     }
+                    Msg::Count(arrid, query) => {
+                        self.handle_count(&mut session_data.traces, arrid, query)?
+                    }
+                    Msg::QueryPage {
+                        arrid,
+                        after,
+                        limit,
+                    } => self.handle_page_query(&mut session_data.traces, arrid, after, limit)?,
 
                     // On either the stop message or a channel disconnection we can shut down
                     // the computation.
                     Msg::Stop => {
-                        self.disable(&mut session_data, timestamp, &probe);
+                        self.disable(&mut session_data, timestamp, &probe)?;
                         self.input_event(StartStop::Stop);
 
                         // TODO: Log worker #n disconnection
@@ -276,7 +291,7 @@ impl<'a> DDlogWorker<'a> {
 
         // All workers advance to timestamp 1 and flush their inputs
         self.advance(session_data, timestamp);
-        self.flush(session_data, probe);
+        self.flush(session_data, probe)?;
 
         self.reply_sender
             .send(Reply::FlushAck)
@@ -294,14 +309,19 @@ impl<'a> DDlogWorker<'a> {
     }
 
     /// Empty the Enabled relation to help the dataflow terminate.
-    fn disable(&mut self, session_data: &mut SessionData, timestamp: TS, probe: &ProbeHandle<TS>) {
+    fn disable(
+        &mut self,
+        session_data: &mut SessionData,
+        timestamp: TS,
+        probe: &ProbeHandle<TS>,
+    ) -> Result<(), String> {
         if self.is_leader() {
             // Delete the sole record from the Enabled relation.
             session_data.enabled_session.update_at((), timestamp, -1);
         }
 
         self.advance(session_data, timestamp + 1);
-        self.flush(session_data, probe);
+        self.flush(session_data, probe)
     }
 
     /// Advance the epoch on all input sessions
@@ -319,8 +339,14 @@ impl<'a> DDlogWorker<'a> {
         }
     }
 
-    /// Propagate all changes through the pipeline
-    fn flush(&mut self, session_data: &mut SessionData, probe: &ProbeHandle<TS>) {
+    /// Propagate all changes through the pipeline.  Fails if another worker fails in the
+    /// meantime, since the changes can't propagate without it, and stops early if the program
+    /// abandons the computation.
+    fn flush(
+        &mut self,
+        session_data: &mut SessionData,
+        probe: &ProbeHandle<TS>,
+    ) -> Result<(), String> {
         for relation_input in session_data.sessions.values_mut() {
             relation_input.flush();
         }
@@ -328,9 +354,18 @@ impl<'a> DDlogWorker<'a> {
 
         if let Some(session) = session_data.sessions.values_mut().next() {
             while probe.less_than(session.time()) {
+                if self.failure.failed() {
+                    return Err("another worker failed".to_string());
+                }
+                if self.failure.abandoned() {
+                    // Nobody waits for the changes anymore, e.g., because the commit was aborted.
+                    break;
+                }
                 self.worker.step_or_park(None);
             }
         }
+
+        Ok(())
     }
 
     /// Handle a query
@@ -338,24 +373,61 @@ impl<'a> DDlogWorker<'a> {
         &self,
         traces: &mut BTreeMap<ArrId, Trace>,
         arrid: ArrId,
-        key: Option<DDValue>,
+        query: KeyQuery,
     ) -> Result<(), String>
     where
         Trace: TraceReader<Key = DDValue, Val = DDValue, Time = TS, R = Weight>,
         <Trace as TraceReader>::Batch: BatchReader<DDValue, DDValue, TS, Weight>,
         <Trace as TraceReader>::Cursor: Cursor<DDValue, DDValue, TS, Weight>,
     {
-        let trace = match traces.get_mut(&arrid) {
-            Some(trace) => trace,
-            None => {
-                self.reply_sender
-                    .send(Reply::QueryRes(None))
-                    .map_err(|e| format!("handle_query: failed to send error response: {}", e))?;
+        let values = traces.get_mut(&arrid).map(|trace| {
+            let mut values = BTreeSet::new();
+            Self::scan_query(trace, query, |v| {
+                values.insert(v.clone());
+            });
+            values
+        });
 
-                return Ok(());
-            }
-        };
+        self.reply_sender
+            .send(Reply::QueryRes(values))
+            .map_err(|e| format!("handle_query: failed to send query response: {}", e))?;
+
+        Ok(())
+    }
 
+    /// Count the values a query would return, without copying them.
+    fn handle_count<Trace>(
+        &self,
+        traces: &mut BTreeMap<ArrId, Trace>,
+        arrid: ArrId,
+        query: KeyQuery,
+    ) -> Result<(), String>
+    where
+        Trace: TraceReader<Key = DDValue, Val = DDValue, Time = TS, R = Weight>,
+        <Trace as TraceReader>::Batch: BatchReader<DDValue, DDValue, TS, Weight>,
+        <Trace as TraceReader>::Cursor: Cursor<DDValue, DDValue, TS, Weight>,
+    {
+        let count = traces.get_mut(&arrid).map(|trace| {
+            let mut count = 0;
+            Self::scan_query(trace, query, |_| count += 1);
+            count
+        });
+
+        self.reply_sender
+            .send(Reply::CountRes(count))
+            .map_err(|e| format!("handle_count: failed to send count response: {}", e))?;
+
+        Ok(())
+    }
+
+    /// Invoke `visit` for every value of the keys selected by `query`.
+    fn scan_query<Trace, F>(trace: &mut Trace, query: KeyQuery, mut visit: F)
+    where
+        Trace: TraceReader<Key = DDValue, Val = DDValue, Time = TS, R = Weight>,
+        <Trace as TraceReader>::Batch: BatchReader<DDValue, DDValue, TS, Weight>,
+        <Trace as TraceReader>::Cursor: Cursor<DDValue, DDValue, TS, Weight>,
+        F: FnMut(&DDValue),
+    {
         let (mut cursor, storage) = trace.cursor();
         // for ((k, v), diffs) in cursor.to_vec(&storage).iter() {
         //     println!("{:?}:{:?}: {:?}", *k, *v, diffs);
@@ -365,62 +437,156 @@ impl<'a> DDlogWorker<'a> {
         cursor.rewind_keys(&storage);
         cursor.rewind_vals(&storage);
 
-        let values = match key {
-            Some(k) => {
+        match query {
+            KeyQuery::Key(k) => {
                 cursor.seek_key(&storage, &k);
-                if !cursor.key_valid(&storage) {
-                    BTreeSet::new()
+                if cursor.key_valid(&storage) && *cursor.key(&storage) == k {
+                    Self::visit_values(&mut cursor, &storage, &mut visit);
+                }
+            }
+
+            KeyQuery::All => {
+                while cursor.key_valid(&storage) {
+                    Self::visit_values(&mut cursor, &storage, &mut visit);
+                    cursor.step_key(&storage);
+                }
+            }
+
+            KeyQuery::Range { lo, hi } => {
+                if let Some(lo) = lo {
+                    cursor.seek_key(&storage, &lo);
+                }
+                while cursor.key_valid(&storage)
+                    && hi.as_ref().is_none_or(|hi| cursor.key(&storage) < hi)
+                {
+                    Self::visit_values(&mut cursor, &storage, &mut visit);
+                    cursor.step_key(&storage);
+                }
+            }
+
+            KeyQuery::Prefix(prefix, KeyConverter(convert)) => {
+                // Any key tells the types of the fields that follow the prefix.
+                let lower_bound = if cursor.key_valid(&storage) {
+                    let template = cursor.key(&storage).clone().into_record();
+                    prefix_lower_bound(&template, &prefix, |key| convert(key).is_some())
+                        .and_then(|key| convert(&key))
                 } else {
-                    let mut values = BTreeSet::new();
-                    while cursor.val_valid(&storage) && *cursor.key(&storage) == k {
-                        let mut weight = 0;
-                        cursor.map_times(&storage, |_, &diff| weight += diff);
-
-                        //assert!(weight >= 0);
-                        // FIXME: this will add the value to the set even if `weight < 0`,
-                        // i.e., positive and negative weights are treated the same way.
-                        // A negative wait should only be possible if there are values with
-                        // negative weights in one of the input multisets.
-                        if weight != 0 {
-                            values.insert(cursor.val(&storage).clone());
-                        }
+                    None
+                };
 
-                        cursor.step_val(&storage);
+                // Once at or past the first key with the prefix, the first key without it
+                // ends the scan.
+                let mut in_range = false;
+                if let Some(lower_bound) = lower_bound {
+                    cursor.seek_key(&storage, &lower_bound);
+                    in_range = true;
+                }
+                while cursor.key_valid(&storage) {
+                    if record_has_prefix(&cursor.key(&storage).clone().into_record(), &prefix) {
+                        in_range = true;
+                        Self::visit_values(&mut cursor, &storage, &mut visit);
+                    } else if in_range {
+                        break;
                     }
-
-                    values
+                    cursor.step_key(&storage);
                 }
             }
+        }
+    }
 
+    /// Send the first `limit` entries of an arrangement that follow `after`, in order, and
+    /// whether there are more.
+    fn handle_page_query<Trace>(
+        &self,
+        traces: &mut BTreeMap<ArrId, Trace>,
+        arrid: ArrId,
+        after: Option<ArrangementCursor>,
+        limit: usize,
+    ) -> Result<(), String>
+    where
+        Trace: TraceReader<Key = DDValue, Val = DDValue, Time = TS, R = Weight>,
+        <Trace as TraceReader>::Batch: BatchReader<DDValue, DDValue, TS, Weight>,
+        <Trace as TraceReader>::Cursor: Cursor<DDValue, DDValue, TS, Weight>,
+    {
+        let trace = match traces.get_mut(&arrid) {
+            Some(trace) => trace,
             None => {
-                let mut values = BTreeSet::new();
-                while cursor.key_valid(&storage) {
-                    while cursor.val_valid(&storage) {
-                        let mut weight = 0;
-                        cursor.map_times(&storage, |_, &diff| weight += diff);
+                self.reply_sender
+                    .send(Reply::QueryPageRes(None))
+                    .map_err(|e| {
+                        format!("handle_page_query: failed to send error response: {}", e)
+                    })?;
 
-                        //assert!(weight >= 0);
-                        if weight != 0 {
-                            values.insert(cursor.val(&storage).clone());
-                        }
+                return Ok(());
+            }
+        };
 
-                        cursor.step_val(&storage);
-                    }
+        let (mut cursor, storage) = trace.cursor();
+        cursor.rewind_keys(&storage);
+        cursor.rewind_vals(&storage);
+        if let Some(after) = &after {
+            cursor.seek_key(&storage, &after.key);
+        }
 
-                    cursor.step_key(&storage);
+        let mut entries = Vec::new();
+        let mut more = false;
+        'keys: while cursor.key_valid(&storage) {
+            let key = cursor.key(&storage).clone();
+            if let Some(after) = after.as_ref().filter(|after| after.key == key) {
+                cursor.seek_val(&storage, &after.val);
+                if cursor.val_valid(&storage) && *cursor.val(&storage) == after.val {
+                    cursor.step_val(&storage);
                 }
+            }
 
-                values
+            while cursor.val_valid(&storage) {
+                let mut weight = 0;
+                cursor.map_times(&storage, |_, &diff| weight += diff);
+                if weight != 0 {
+                    if entries.len() == limit {
+                        more = true;
+                        break 'keys;
+                    }
+                    entries.push(ArrangementCursor {
+                        key: key.clone(),
+                        val: cursor.val(&storage).clone(),
+                    });
+                }
+                cursor.step_val(&storage);
             }
-        };
+            cursor.step_key(&storage);
+        }
 
         self.reply_sender
-            .send(Reply::QueryRes(Some(values)))
-            .map_err(|e| format!("handle_query: failed to send query response: {}", e))?;
+            .send(Reply::QueryPageRes(Some((entries, more))))
+            .map_err(|e| format!("handle_page_query: failed to send query response: {}", e))?;
 
         Ok(())
     }
 
+    /// Invoke `visit` for the values of the cursor's current key.
+    fn visit_values<C, F>(cursor: &mut C, storage: &C::Storage, visit: &mut F)
+    where
+        C: Cursor<DDValue, DDValue, TS, Weight>,
+        F: FnMut(&DDValue),
+    {
+        while cursor.val_valid(storage) {
+            let mut weight = 0;
+            cursor.map_times(storage, |_, &diff| weight += diff);
+
+            //assert!(weight >= 0);
+            // FIXME: this will add the value to the set even if `weight < 0`,
+            // i.e., positive and negative weights are treated the same way.
+            // A negative wait should only be possible if there are values with
+            // negative weights in one of the input multisets.
+            if weight != 0 {
+                visit(cursor.val(storage));
+            }
+
+            cursor.step_val(storage);
+        }
+    }
+
     /// Initialize timely and differential profiling logging hooks
     fn init_profiling(&mut self) -> Result<(), String> {
         if let Some(profiling) = self.profiling.clone() {
@@ -0,0 +1,96 @@
+//! Converting `Record`s to JSON.
+//!
+//! The encoding follows the one `serde` derives for the generated Rust types, so that a value
+//! printed by the CLI can be fed back in through `Record::Serialized("json", ..)`:
+//!
+//! * structs with named fields become objects (the constructor name is dropped);
+//! * positional structs, tuples, vectors, sets and maps become arrays (map entries are
+//!   `[key, value]` pairs);
+//! * integers become numbers when they fit into 64 bits, and decimal strings otherwise;
+//! * non-finite floats, which JSON can't represent, become `null`;
+//! * values that are already serialized as JSON are embedded as is.
+
+use crate::record::Record;
+use num::ToPrimitive;
+use serde_json::{Map, Number, Value};
+
+impl Record {
+    /// Convert the record to a JSON value.
+    pub fn to_json(&self) -> Value {
+        match self {
+            Record::Bool(b) => Value::Bool(*b),
+            Record::Int(i) => i
+                .to_i64()
+                .map(Value::from)
+                .or_else(|| i.to_u64().map(Value::from))
+                .unwrap_or_else(|| Value::String(i.to_string())),
+            Record::Float(f) => float_to_json(f64::from(f.into_inner())),
+            Record::Double(d) => float_to_json(d.into_inner()),
+            Record::String(s) => Value::String(s.clone()),
+            Record::Serialized(format, s) if format == "json" => {
+                serde_json::from_str(s).unwrap_or_else(|_| Value::String(s.clone()))
+            }
+            Record::Serialized(_, s) => Value::String(s.clone()),
+            Record::Tuple(fields)
+            | Record::Array(_, fields)
+            | Record::PosStruct(_, fields) => {
+                Value::Array(fields.iter().map(Record::to_json).collect())
+            }
+            Record::NamedStruct(_, fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, field)| (name.to_string(), field.to_json()))
+                    .collect::<Map<String, Value>>(),
+            ),
+        }
+    }
+}
+
+fn float_to_json(f: f64) -> Value {
+    Number::from_f64(f).map(Value::Number).unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::record::{CollectionKind, IntoRecord, Record};
+    use num::BigInt;
+    use serde_json::json;
+    use std::borrow::Cow;
+
+    #[test]
+    fn named_struct_to_json() {
+        let record = Record::NamedStruct(
+            Cow::from("Edge"),
+            vec![
+                (Cow::from("src"), 1u32.into_record()),
+                (Cow::from("dest"), 2u32.into_record()),
+            ],
+        );
+        assert_eq!(record.to_json(), json!({"src": 1, "dest": 2}));
+    }
+
+    #[test]
+    fn nested_to_json() {
+        let big: BigInt = "123456789012345678901234567890".parse().unwrap();
+        let record = Record::Tuple(vec![
+            Record::String("foo".to_string()),
+            Record::Int(big),
+            Record::Array(
+                CollectionKind::Map,
+                vec![Record::Tuple(vec![true.into_record(), (-1i64).into_record()])],
+            ),
+            Record::PosStruct(Cow::from("Some"), vec![Record::Double(f64::NAN.into())]),
+            Record::Serialized(Cow::from("json"), r#"{"x": [1]}"#.to_string()),
+        ]);
+        assert_eq!(
+            record.to_json(),
+            json!([
+                "foo",
+                "123456789012345678901234567890",
+                [[true, -1]],
+                [null],
+                {"x": [1]}
+            ])
+        );
+    }
+}
@@ -1,9 +1,17 @@
 //! An untyped representation of DDlog values and database update commands.
 
 mod arrays;
+mod columns;
+mod json;
+mod prefix;
+mod schema;
 mod tuples;
 
-use crate::{ddval::DDValue, program::Update, DDlogInventory};
+pub use columns::{flatten_record, record_from_columns, records_from_columns};
+pub use prefix::{prefix_lower_bound, record_has_prefix};
+pub use schema::relation_declaration;
+
+use crate::{ddval::DDValue, program::Update, DDlogError, DDlogInventory};
 use num::{BigInt, BigUint, ToPrimitive};
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
@@ -310,7 +318,7 @@ pub enum UpdCmd {
 }
 
 impl UpdCmd {
-    pub fn to_update<I>(&self, inventory: &I) -> Result<Update<DDValue>, String>
+    pub fn to_update<I>(&self, inventory: &I) -> Result<Update<DDValue>, DDlogError>
     where
         I: DDlogInventory,
     {
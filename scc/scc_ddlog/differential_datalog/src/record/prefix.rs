@@ -0,0 +1,274 @@
+//! Matching the leading fields of tuple and struct records, e.g., index keys.
+
+use crate::record::Record;
+use num::BigInt;
+use ordered_float::OrderedFloat;
+
+/// Whether the leading fields of tuple or struct `record` equal `prefix`.  A record that is
+/// neither a tuple nor a struct has a single field, itself.
+///
+/// Structs are compared field by field in declaration order, so that a struct given with
+/// positional fields matches the same struct with named fields.
+pub fn record_has_prefix(record: &Record, prefix: &[Record]) -> bool {
+    match fields(record) {
+        Some(fields) => {
+            fields.len() >= prefix.len()
+                && fields
+                    .iter()
+                    .zip(prefix.iter())
+                    .all(|(field, expected)| same_record(field, expected))
+        }
+        None => match prefix {
+            [] => true,
+            [expected] => same_record(record, expected),
+            _ => false,
+        },
+    }
+}
+
+/// The smallest key whose leading fields equal `prefix`, given `template`, any key of the same
+/// type, and `is_key`, which tells if a record converts to a key of that type.  Keys with the
+/// prefix are adjacent, so they can be found by seeking to this key.
+///
+/// The remaining fields take their smallest values: `false`, empty strings and collections, and
+/// the smallest integer accepted by `is_key`.  Returns `None` if the smallest value of a field
+/// isn't known, i.e., for a `bigint` or a struct, which may be an enum whose first constructor
+/// isn't known, and for an empty prefix, which all keys start with.
+pub fn prefix_lower_bound<F>(template: &Record, prefix: &[Record], is_key: F) -> Option<Record>
+where
+    F: Fn(&Record) -> bool,
+{
+    let mut key = template.clone();
+    let len = match (fields_mut(&mut key), prefix) {
+        (_, []) => return None,
+        (Some(mut fields), _) if fields.len() >= prefix.len() => {
+            for (field, value) in fields.iter_mut().zip(prefix.iter()) {
+                **field = value.clone();
+            }
+            fields.len()
+        }
+        (None, [value]) => return Some(value.clone()),
+        _ => return None,
+    };
+
+    let mut path = Vec::new();
+    for i in prefix.len()..len {
+        path.push(i);
+        if !minimize(&mut key, &mut path, &is_key) {
+            return None;
+        }
+        path.pop();
+    }
+    Some(key)
+}
+
+/// Set the field of `key` at `path` to its smallest value, or return `false` if it isn't known.
+fn minimize(key: &mut Record, path: &mut Vec<usize>, is_key: &dyn Fn(&Record) -> bool) -> bool {
+    let len = match field_at(key, path) {
+        Some(Record::Bool(b)) => {
+            *b = false;
+            return true;
+        }
+        Some(Record::Float(x)) => {
+            *x = OrderedFloat(f32::NEG_INFINITY);
+            return true;
+        }
+        Some(Record::Double(x)) => {
+            *x = OrderedFloat(f64::NEG_INFINITY);
+            return true;
+        }
+        Some(Record::String(s)) => {
+            s.clear();
+            return true;
+        }
+        Some(Record::Array(_, items)) => {
+            items.clear();
+            return true;
+        }
+        Some(Record::Int(_)) => return minimize_int(key, path, is_key),
+        Some(Record::Tuple(fields)) => fields.len(),
+        _ => return false,
+    };
+
+    (0..len).all(|i| {
+        path.push(i);
+        let known = minimize(key, path, is_key);
+        path.pop();
+        known
+    })
+}
+
+/// Set the integer at `path` to the smallest value accepted by `is_key`: 0 for unsigned types and
+/// -2^(width - 1) for signed ones.
+fn minimize_int(key: &mut Record, path: &[usize], is_key: &dyn Fn(&Record) -> bool) -> bool {
+    let set = |key: &mut Record, value: &BigInt| {
+        if let Some(field) = field_at(key, path) {
+            *field = Record::Int(value.clone());
+        }
+        is_key(key)
+    };
+
+    let mut min = BigInt::from(-1);
+    if !set(key, &min) {
+        return set(key, &BigInt::from(0));
+    }
+    // Signed types are at most 128 bits wide, whereas `bigint`s have no smallest value.
+    for _ in 0..128 {
+        let next = &min * 2;
+        if !set(key, &next) {
+            return set(key, &min);
+        }
+        min = next;
+    }
+    false
+}
+
+pub(super) fn field_at<'a>(record: &'a mut Record, path: &[usize]) -> Option<&'a mut Record> {
+    match path.split_first() {
+        None => Some(record),
+        Some((&i, rest)) => field_at(fields_mut(record)?.into_iter().nth(i)?, rest),
+    }
+}
+
+fn fields(record: &Record) -> Option<Vec<&Record>> {
+    match record {
+        Record::Tuple(fields) | Record::PosStruct(_, fields) => Some(fields.iter().collect()),
+        Record::NamedStruct(_, fields) => Some(fields.iter().map(|(_, field)| field).collect()),
+        _ => None,
+    }
+}
+
+fn fields_mut(record: &mut Record) -> Option<Vec<&mut Record>> {
+    match record {
+        Record::Tuple(fields) | Record::PosStruct(_, fields) => Some(fields.iter_mut().collect()),
+        Record::NamedStruct(_, fields) => Some(fields.iter_mut().map(|(_, field)| field).collect()),
+        _ => None,
+    }
+}
+
+fn same_record(x: &Record, y: &Record) -> bool {
+    match (x, y) {
+        (
+            Record::PosStruct(xname, _) | Record::NamedStruct(xname, _),
+            Record::PosStruct(yname, _) | Record::NamedStruct(yname, _),
+        ) if xname == yname => same_fields(x, y),
+        (Record::Tuple(_), Record::Tuple(_)) => same_fields(x, y),
+        _ => x == y,
+    }
+}
+
+fn same_fields(x: &Record, y: &Record) -> bool {
+    match (fields(x), fields(y)) {
+        (Some(xfields), Some(yfields)) => {
+            xfields.len() == yfields.len()
+                && xfields
+                    .iter()
+                    .zip(yfields.iter())
+                    .all(|(x, y)| same_record(x, y))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigInt;
+    use std::borrow::Cow;
+
+    fn int(i: i64) -> Record {
+        Record::Int(BigInt::from(i))
+    }
+
+    #[test]
+    fn prefixes() {
+        let key = Record::Tuple(vec![
+            int(1),
+            Record::NamedStruct(Cow::from("Port"), vec![(Cow::from("num"), int(80))]),
+            Record::String("x".to_string()),
+        ]);
+
+        assert!(record_has_prefix(&key, &[]));
+        assert!(record_has_prefix(&key, &[int(1)]));
+        assert!(record_has_prefix(
+            &key,
+            &[int(1), Record::PosStruct(Cow::from("Port"), vec![int(80)])]
+        ));
+        assert!(!record_has_prefix(
+            &key,
+            &[int(1), Record::PosStruct(Cow::from("Port"), vec![int(81)])]
+        ));
+        assert!(!record_has_prefix(&key, &[int(2)]));
+        assert!(!record_has_prefix(
+            &key,
+            &[int(1), int(80), Record::String("x".to_string()), int(0)]
+        ));
+
+        assert!(record_has_prefix(&int(5), &[int(5)]));
+        assert!(!record_has_prefix(&int(5), &[int(5), int(6)]));
+    }
+
+    /// Whether `record` is a key of type `(u8, (i16, string), bool)`.
+    fn is_key(record: &Record) -> bool {
+        let in_range = |field: &Record, min: i64, max: i64| match field {
+            Record::Int(i) => *i >= BigInt::from(min) && *i <= BigInt::from(max),
+            _ => false,
+        };
+        match record {
+            Record::Tuple(fields) => match fields.as_slice() {
+                [a, Record::Tuple(nested), Record::Bool(_)] => match nested.as_slice() {
+                    [b, Record::String(_)] => in_range(a, 0, 255) && in_range(b, -32768, 32767),
+                    _ => false,
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn lower_bounds() {
+        let key = |a: i64, b: i64, s: &str, flag: bool| {
+            Record::Tuple(vec![
+                int(a),
+                Record::Tuple(vec![int(b), Record::String(s.to_string())]),
+                Record::Bool(flag),
+            ])
+        };
+        let template = key(3, 7, "x", true);
+
+        assert_eq!(
+            prefix_lower_bound(&template, &[int(5)], is_key),
+            Some(key(5, -32768, "", false))
+        );
+        assert_eq!(
+            prefix_lower_bound(
+                &template,
+                &[
+                    int(5),
+                    Record::Tuple(vec![int(1), Record::String("y".to_string())])
+                ],
+                is_key
+            ),
+            Some(key(5, 1, "y", false))
+        );
+        assert_eq!(prefix_lower_bound(&template, &[], is_key), None);
+        assert_eq!(
+            prefix_lower_bound(&template, &[int(1), int(2), int(3), int(4)], is_key),
+            None
+        );
+        assert_eq!(
+            prefix_lower_bound(&int(3), &[int(4)], |_| true),
+            Some(int(4))
+        );
+
+        // Neither `bigint`s nor structs have a known smallest value.
+        let bigint = Record::Tuple(vec![int(1), int(2)]);
+        assert_eq!(prefix_lower_bound(&bigint, &[int(1)], |_| true), None);
+        let with_struct = Record::Tuple(vec![
+            int(1),
+            Record::PosStruct(Cow::from("ddlog_std::Some"), vec![int(2)]),
+        ]);
+        assert_eq!(prefix_lower_bound(&with_struct, &[int(1)], |_| true), None);
+    }
+}
@@ -10,7 +10,9 @@ use std::fmt::Display;
 use std::io;
 
 use crate::ddlog::DDlogInventory;
+use crate::ddval::DDValue;
 use crate::program::RelId;
+use crate::record::{flatten_record, IntoRecord};
 
 /* Stores a set of changes to output tables.
  */
@@ -152,3 +154,103 @@ impl<V: Display + Ord + Clone> DeltaMap<V> {
         };
     }
 }
+
+impl DeltaMap<DDValue> {
+    /// Like `format`, but writes one JSON object per value, e.g.,
+    /// `{"relation":"Edge","record":{"src":1,"dest":2},"weight":1}`.
+    pub fn format_json(
+        &self,
+        w: &mut dyn io::Write,
+        inventory: &dyn DDlogInventory,
+    ) -> io::Result<()> {
+        for (relid, relmap) in &self.map {
+            let relation = inventory.get_table_name(*relid).unwrap();
+            for (val, weight) in relmap {
+                write_json_line(w, relation, val, *weight)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `format_rel`, but writes one JSON object per value.
+    pub fn format_rel_json(
+        &mut self,
+        relid: RelId,
+        w: &mut dyn io::Write,
+        inventory: &dyn DDlogInventory,
+    ) -> io::Result<()> {
+        let relation = inventory.get_table_name(relid).unwrap();
+        for (val, weight) in self.get_rel(relid) {
+            write_json_line(w, relation, val, *weight)?;
+        }
+        Ok(())
+    }
+
+    /// Write the values of relation `relid` as CSV rows, flattened into columns by
+    /// `record::flatten_record`.  If `weights` is true, every row ends with a `weight` column.
+    ///
+    /// If `header` is true, a row with the names of the columns is written first, even if the
+    /// relation is empty.  The names are those of the relation's template (see
+    /// `DDlogInventory::relation_template`); for types without one, they are taken from the
+    /// first value, so no header can be written for an empty relation.  Returns whether the
+    /// header was written.
+    pub fn format_rel_csv(
+        &self,
+        relid: RelId,
+        w: &mut dyn io::Write,
+        inventory: &dyn DDlogInventory,
+        header: bool,
+        weights: bool,
+    ) -> io::Result<bool> {
+        // Values of enum types may flatten to different numbers of columns.
+        let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(w);
+        let write_header = |writer: &mut csv::Writer<_>, columns: &[(String, String)]| {
+            let mut names: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+            if weights {
+                names.push("weight");
+            }
+            writer.write_record(&names)
+        };
+
+        let mut header = header;
+        let mut header_written = false;
+        if let (true, Some(template)) = (header, inventory.relation_template(relid)) {
+            write_header(&mut writer, &flatten_record(&template))?;
+            header = false;
+            header_written = true;
+        }
+
+        for (val, weight) in self.try_get_rel(relid).into_iter().flatten() {
+            let columns = flatten_record(&val.clone().into_record());
+            if header {
+                write_header(&mut writer, &columns)?;
+                header = false;
+                header_written = true;
+            }
+
+            let weight = weight.to_string();
+            let mut row: Vec<&str> = columns.iter().map(|(_, value)| value.as_str()).collect();
+            if weights {
+                row.push(&weight);
+            }
+            writer.write_record(&row)?;
+        }
+
+        writer.flush()?;
+        Ok(header_written)
+    }
+}
+
+fn write_json_line(
+    w: &mut dyn io::Write,
+    relation: &str,
+    val: &DDValue,
+    weight: isize,
+) -> io::Result<()> {
+    w.write_fmt(format_args!(
+        "{{\"relation\":{},\"record\":{},\"weight\":{}}}\n",
+        serde_json::Value::from(relation),
+        val.clone().into_record().to_json(),
+        weight
+    ))
+}
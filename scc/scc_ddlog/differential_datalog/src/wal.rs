@@ -0,0 +1,544 @@
+//! Write-ahead log of committed transactions.
+//!
+//! The log is a directory holding numbered segments, `<seq>.wal`, and checkpoints,
+//! `checkpoint-<seq>`.  Checkpoint `seq` holds the contents of input relations before the
+//! transactions logged in segment `seq` and the segments after it.  Every record in a segment
+//! is one committed transaction in the `.dat` format written by `CommandRecorder`, preceded by
+//! a header holding its length, its CRC-32 and the CRC-32 of these two fields, as
+//! little-endian `u32`s.  Records are synced to disk before the transaction is committed.  If the
+//! commit then fails, an empty record is appended to abort the transaction before it.
+//!
+//! Recovery restores the latest checkpoint and replays the transactions logged after it,
+//! except aborted ones.  A
+//! crash while appending leaves an incomplete record at the end of the last segment, which is
+//! discarded.  Any other damage, including a damaged header, is an error.
+
+use crate::{
+    ddlog::{DDlog, DDlogDynamic, DDlogInventory},
+    ddval::DDValue,
+    error::DDlogError,
+    program::{RelId, Update},
+    replay::CommandRecorder,
+};
+use std::{
+    convert::TryFrom,
+    fmt::{self, Debug, Formatter},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+const SEGMENT_EXTENSION: &str = ".wal";
+const CHECKPOINT_PREFIX: &str = "checkpoint-";
+const RECORD_HEADER_LEN: usize = 12;
+
+/// Segments of a write-ahead log, opened for appending.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    dir: PathBuf,
+    seq: u64,
+    segment: File,
+}
+
+/// State to recover from a write-ahead log.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Recovery {
+    /// The latest checkpoint, if any.
+    pub checkpoint: Option<PathBuf>,
+    /// Transactions committed after the checkpoint, in the order they were committed.
+    pub transactions: Vec<String>,
+}
+
+impl WriteAheadLog {
+    /// Open the log in directory `dir`, creating it if needed, and return the state to recover
+    /// from it.  A torn record at the end of the log is truncated.  Transactions appended from
+    /// now on go to a new segment.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<(Self, Recovery), DDlogError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .map_err(|e| DDlogError::io(format!("failed to create '{}'", dir.display()), e))?;
+
+        let mut segments = Vec::new();
+        let mut checkpoint = None;
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| DDlogError::io(format!("failed to read '{}'", dir.display()), e))?;
+        for entry in entries {
+            let entry = entry
+                .map_err(|e| DDlogError::io(format!("failed to read '{}'", dir.display()), e))?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if let Some(seq) = parse_seq(name.strip_suffix(SEGMENT_EXTENSION)) {
+                segments.push(seq);
+            } else if let Some(seq) = parse_seq(name.strip_prefix(CHECKPOINT_PREFIX)) {
+                checkpoint = checkpoint.max(Some(seq));
+            }
+        }
+        segments.sort_unstable();
+
+        let mut recovery = Recovery {
+            checkpoint: checkpoint.map(|seq| checkpoint_path(&dir, seq)),
+            transactions: Vec::new(),
+        };
+        let replayed: Vec<u64> = segments
+            .iter()
+            .copied()
+            .filter(|seq| *seq >= checkpoint.unwrap_or(0))
+            .collect();
+        for (idx, seq) in replayed.iter().enumerate() {
+            let path = segment_path(&dir, *seq);
+            let is_last = idx + 1 == replayed.len();
+            read_segment(&path, is_last, &mut recovery.transactions)?;
+        }
+
+        let seq = segments
+            .last()
+            .copied()
+            .max(checkpoint)
+            .map_or(1, |seq| seq + 1);
+        let segment = create_segment(&dir, seq)?;
+
+        Ok((Self { dir, seq, segment }, recovery))
+    }
+
+    /// Append a transaction to the current segment and sync it to disk.
+    pub fn append(&mut self, transaction: &[u8]) -> Result<(), DDlogError> {
+        if transaction.is_empty() {
+            return Err(DDlogError::other("cannot log an empty transaction"));
+        }
+        self.append_record(transaction)
+    }
+
+    /// Append an abort record, which makes recovery skip the transaction appended right
+    /// before it to the current segment, because committing it failed.
+    pub fn append_abort(&mut self) -> Result<(), DDlogError> {
+        self.append_record(&[])
+    }
+
+    fn append_record(&mut self, transaction: &[u8]) -> Result<(), DDlogError> {
+        let len = u32::try_from(transaction.len())
+            .map_err(|_| DDlogError::other("transaction is too large for the write-ahead log"))?;
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + transaction.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(transaction).to_le_bytes());
+        let header_crc = crc32fast::hash(&record);
+        record.extend_from_slice(&header_crc.to_le_bytes());
+        record.extend_from_slice(transaction);
+
+        self.segment
+            .write_all(&record)
+            .and_then(|_| self.segment.sync_data())
+            .map_err(|e| {
+                DDlogError::io(
+                    format!(
+                        "failed to append to '{}'",
+                        segment_path(&self.dir, self.seq).display()
+                    ),
+                    e,
+                )
+            })
+    }
+
+    /// Start a new segment and save a checkpoint of the state before it by calling `save`
+    /// with the checkpoint's path.  Once the checkpoint is saved, the segments and checkpoints
+    /// it supersedes are removed.
+    pub fn checkpoint<F>(&mut self, save: F) -> Result<(), DDlogError>
+    where
+        F: FnOnce(&Path) -> Result<(), DDlogError>,
+    {
+        let seq = self.seq + 1;
+        self.segment = create_segment(&self.dir, seq)?;
+        self.seq = seq;
+
+        save(&checkpoint_path(&self.dir, seq))?;
+        sync_dir(&self.dir)
+            .map_err(|e| DDlogError::io(format!("failed to sync '{}'", self.dir.display()), e))?;
+
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| DDlogError::io(format!("failed to read '{}'", self.dir.display()), e))?;
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let obsolete = parse_seq(name.strip_suffix(SEGMENT_EXTENSION))
+                .or_else(|| parse_seq(name.strip_prefix(CHECKPOINT_PREFIX)))
+                .is_some_and(|old| old < seq);
+            if obsolete {
+                fs::remove_file(entry.path()).map_err(|e| {
+                    DDlogError::io(format!("failed to remove '{}'", entry.path().display()), e)
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_seq(name: Option<&str>) -> Option<u64> {
+    name.filter(|seq| !seq.is_empty() && seq.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|seq| seq.parse().ok())
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{:020}{}", seq, SEGMENT_EXTENSION))
+}
+
+fn checkpoint_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{}{:020}", CHECKPOINT_PREFIX, seq))
+}
+
+fn create_segment(dir: &Path, seq: u64) -> Result<File, DDlogError> {
+    let path = segment_path(dir, seq);
+    let segment = OpenOptions::new()
+        .append(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| DDlogError::io(format!("failed to create '{}'", path.display()), e))?;
+    sync_dir(dir).map_err(|e| DDlogError::io(format!("failed to sync '{}'", dir.display()), e))?;
+
+    Ok(segment)
+}
+
+/// Make the creation, removal and renaming of files in `dir` durable.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Read the transactions of a segment into `transactions`, leaving out aborted ones.  If
+/// `is_last` is true, an incomplete record at the end of the segment is treated as torn by a
+/// crash and truncated.
+fn read_segment(
+    path: &Path,
+    is_last: bool,
+    transactions: &mut Vec<String>,
+) -> Result<(), DDlogError> {
+    let bytes = fs::read(path)
+        .map_err(|e| DDlogError::io(format!("failed to read '{}'", path.display()), e))?;
+
+    let mut offset = 0;
+    // Whether the previous record of the segment is a transaction, which an abort record
+    // would abort.
+    let mut abortable = false;
+    while offset < bytes.len() {
+        match parse_record(&bytes[offset..]) {
+            Ok([]) => {
+                if abortable {
+                    transactions.pop();
+                }
+                abortable = false;
+                offset += RECORD_HEADER_LEN;
+            }
+            Ok(transaction) => {
+                let transaction = std::str::from_utf8(transaction).map_err(|_| {
+                    DDlogError::Other(format!(
+                        "corrupted write-ahead log '{}': invalid UTF-8 at offset {}",
+                        path.display(),
+                        offset
+                    ))
+                })?;
+                transactions.push(transaction.to_string());
+                abortable = true;
+                offset += RECORD_HEADER_LEN + transaction.len();
+            }
+            Err(Damage::Torn) if is_last => {
+                let segment = OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .and_then(|segment| {
+                        segment.set_len(offset as u64)?;
+                        segment.sync_all()
+                    });
+                return segment.map_err(|e| {
+                    DDlogError::io(
+                        format!("failed to truncate torn record in '{}'", path.display()),
+                        e,
+                    )
+                });
+            }
+            Err(_) => {
+                return Err(DDlogError::Other(format!(
+                    "corrupted write-ahead log '{}': damaged record at offset {}",
+                    path.display(),
+                    offset
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+enum Damage {
+    /// The record is incomplete, as if writing it at the end of the log was interrupted.
+    Torn,
+    /// The header or payload of the record fails its checksum.
+    Corrupted,
+}
+
+/// Return the payload of the record at the start of `bytes`.
+fn parse_record(bytes: &[u8]) -> Result<&[u8], Damage> {
+    if bytes.len() < RECORD_HEADER_LEN {
+        return Err(Damage::Torn);
+    }
+    let (header, rest) = bytes.split_at(RECORD_HEADER_LEN);
+    let field = |at: usize| {
+        u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+    };
+
+    // The length can only be trusted once the header is known to be intact: otherwise, a
+    // damaged length could make the records after it look like the torn end of the log.
+    if crc32fast::hash(&header[..8]) != field(8) {
+        return Err(Damage::Corrupted);
+    }
+    let payload = rest.get(..field(0) as usize).ok_or(Damage::Torn)?;
+    if crc32fast::hash(payload) == field(4) {
+        Ok(payload)
+    } else {
+        Err(Damage::Corrupted)
+    }
+}
+
+/// A write-ahead log together with the commands of the transaction in progress, which are
+/// recorded by a `CommandRecorder` and appended to the log when the transaction commits.
+pub struct TransactionLog<I> {
+    log: WriteAheadLog,
+    transaction: CommandRecorder<Vec<u8>, I>,
+    /// The transaction made changes that need to be logged.
+    changed: bool,
+    /// An update of the transaction failed, so it's unknown which of its updates took effect.
+    failed: bool,
+    /// The segment the transaction being committed was appended to, if it was appended.
+    appended: Option<u64>,
+}
+
+impl<I> Debug for TransactionLog<I> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransactionLog")
+            .field("log", &self.log)
+            .field("changed", &self.changed)
+            .field("failed", &self.failed)
+            .field("appended", &self.appended)
+            .finish()
+    }
+}
+
+impl<I> TransactionLog<I>
+where
+    I: Deref<Target = dyn DDlogInventory + Send + Sync>,
+{
+    pub fn new(log: WriteAheadLog, inventory: I) -> Self {
+        Self {
+            log,
+            transaction: CommandRecorder::new(Vec::new(), inventory),
+            changed: false,
+            failed: false,
+            appended: None,
+        }
+    }
+
+    pub fn transaction_start(&mut self) -> Result<(), DDlogError> {
+        self.transaction.take_writer();
+        self.changed = false;
+        self.failed = false;
+        self.appended = None;
+        self.transaction.transaction_start()
+    }
+
+    pub fn apply_updates(&mut self, updates: &[Update<DDValue>]) -> Result<(), DDlogError> {
+        self.changed |= !updates.is_empty();
+        self.transaction.apply_updates(&mut updates.iter().cloned())
+    }
+
+    pub fn clear_relation(&mut self, relid: RelId) -> Result<(), DDlogError> {
+        self.changed = true;
+        self.transaction.clear_relation(relid)
+    }
+
+    pub fn savepoint(&mut self, name: &str) -> Result<(), DDlogError> {
+        self.transaction.record_savepoint(name)
+    }
+
+    pub fn rollback_to(&mut self, name: &str) -> Result<(), DDlogError> {
+        self.transaction.record_rollback_to(name)
+    }
+
+    /// Mark the transaction as failed: since it can't be replayed faithfully, it can no longer
+    /// be committed, only rolled back.
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    /// Append the transaction to the log.  Must be called before committing it.
+    pub fn transaction_commit(&mut self) -> Result<(), DDlogError> {
+        if self.failed {
+            return Err(DDlogError::other(
+                "cannot log a transaction with failed updates; roll it back instead",
+            ));
+        }
+        if !self.changed {
+            return Ok(());
+        }
+
+        self.transaction.transaction_commit()?;
+        self.changed = false;
+        self.log.append(&self.transaction.take_writer())?;
+        self.appended = Some(self.log.seq);
+        Ok(())
+    }
+
+    /// Abort the transaction appended by `transaction_commit`, because committing it failed, so
+    /// that recovery doesn't replay it.  Nothing needs to be aborted if the transaction wasn't
+    /// appended, or if a checkpoint taken since has superseded it.
+    pub fn transaction_abort(&mut self) -> Result<(), DDlogError> {
+        match self.appended.take() {
+            Some(seq) if seq == self.log.seq => self.log.append_abort(),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn transaction_rollback(&mut self) {
+        self.transaction.take_writer();
+        self.changed = false;
+        self.failed = false;
+    }
+
+    /// See `WriteAheadLog::checkpoint`.
+    pub fn checkpoint<F>(&mut self, save: F) -> Result<(), DDlogError>
+    where
+        F: FnOnce(&Path) -> Result<(), DDlogError>,
+    {
+        self.log.checkpoint(save)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ddlog-wal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn recover_after_torn_record() {
+        let dir = test_dir("torn");
+        let (mut log, recovery) = WriteAheadLog::open(&dir).unwrap();
+        assert_eq!(recovery, Recovery::default());
+        log.append(b"start;\ninsert R[1];\ncommit;\n").unwrap();
+        log.append(b"start;\ninsert R[2];\ncommit;\n").unwrap();
+
+        // Simulate a crash in the middle of appending a record.
+        log.segment.write_all(&[10, 0, 0, 0, 1, 2]).unwrap();
+        drop(log);
+
+        let (mut log, recovery) = WriteAheadLog::open(&dir).unwrap();
+        assert_eq!(
+            recovery.transactions,
+            vec![
+                "start;\ninsert R[1];\ncommit;\n".to_string(),
+                "start;\ninsert R[2];\ncommit;\n".to_string()
+            ]
+        );
+        assert_eq!(
+            fs::metadata(segment_path(&dir, 1)).unwrap().len(),
+            2 * (RECORD_HEADER_LEN as u64 + 28)
+        );
+        log.append(b"start;\ndelete R[1];\ncommit;\n").unwrap();
+        drop(log);
+
+        let (_, recovery) = WriteAheadLog::open(&dir).unwrap();
+        assert_eq!(recovery.transactions.len(), 3);
+
+        // A damaged record that isn't the last one is not silently dropped.
+        let mut bytes = fs::read(segment_path(&dir, 1)).unwrap();
+        bytes[RECORD_HEADER_LEN] ^= 1;
+        fs::write(segment_path(&dir, 1), bytes).unwrap();
+        assert!(WriteAheadLog::open(&dir).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn damaged_header_is_not_torn() {
+        let dir = test_dir("header");
+        let (mut log, _) = WriteAheadLog::open(&dir).unwrap();
+        log.append(b"first").unwrap();
+        log.append(b"second").unwrap();
+        drop(log);
+
+        // A length running past the end of the segment doesn't discard the records after it.
+        let path = segment_path(&dir, 1);
+        let mut bytes = fs::read(&path).unwrap();
+        let len = bytes.len();
+        bytes[1] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert!(WriteAheadLog::open(&dir).is_err());
+        assert_eq!(fs::metadata(&path).unwrap().len(), len as u64);
+
+        // A record whose payload was cut short is torn.
+        bytes[1] ^= 1;
+        bytes.truncate(len - 1);
+        fs::write(&path, &bytes).unwrap();
+        let (_, recovery) = WriteAheadLog::open(&dir).unwrap();
+        assert_eq!(recovery.transactions, vec!["first".to_string()]);
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            RECORD_HEADER_LEN as u64 + 5
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn skip_aborted_transactions() {
+        let dir = test_dir("abort");
+        let (mut log, _) = WriteAheadLog::open(&dir).unwrap();
+        assert!(log.append(b"").is_err());
+        log.append(b"first").unwrap();
+        log.append(b"failed").unwrap();
+        log.append_abort().unwrap();
+        // An abort record only aborts the transaction right before it.
+        log.append_abort().unwrap();
+        log.append(b"second").unwrap();
+        drop(log);
+
+        let (mut log, recovery) = WriteAheadLog::open(&dir).unwrap();
+        assert_eq!(
+            recovery.transactions,
+            vec!["first".to_string(), "second".to_string()]
+        );
+
+        // Nor does it abort a transaction logged in an earlier segment.
+        log.append_abort().unwrap();
+        drop(log);
+        let (_, recovery) = WriteAheadLog::open(&dir).unwrap();
+        assert_eq!(recovery.transactions.len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replay_after_checkpoint() {
+        let dir = test_dir("checkpoint");
+        let (mut log, _) = WriteAheadLog::open(&dir).unwrap();
+        log.append(b"before").unwrap();
+        log.checkpoint(|path| {
+            fs::write(path, "state").map_err(|e| DDlogError::io("failed to write checkpoint", e))
+        })
+        .unwrap();
+        log.append(b"after").unwrap();
+        drop(log);
+
+        let (_, recovery) = WriteAheadLog::open(&dir).unwrap();
+        assert_eq!(recovery.checkpoint, Some(checkpoint_path(&dir, 2)));
+        assert_eq!(recovery.transactions, vec!["after".to_string()]);
+        assert!(!segment_path(&dir, 1).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+//! Generate input graphs for `scc_cli` as a stream of `cmd_parser` commands.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use rustop::opts;
+use scc_workload::GraphKind;
+
+fn main() -> Result<(), String> {
+    let parser = opts! {
+        synopsis "Generate a random graph as DDlog 'Edge' transactions.";
+        auto_shorts false;
+        opt kind:String=String::from("erdos-renyi"), short:'k', desc:"Graph family: erdos-renyi, power-law, grid, chain, ring or planted-scc.";
+        opt nodes:u32=1000, short:'n', desc:"The number of nodes in the graph. Rounded up to a multiple of ceil(sqrt(nodes)) by grid and planted-scc.";
+        opt edges:usize=10000, short:'e', desc:"The number of edges to generate. Only an approximate budget for the power-law and planted-scc families, ignored by grid, chain and ring.";
+        opt seed:u64=0, short:'s', desc:"Random seed. The same seed always produces the same output.";
+        opt transactions:usize=1, short:'t', desc:"Split the edges into this many incremental transactions.";
+        opt delete_ratio:f64=0.0, short:'d', desc:"Probability that an update in an incremental transaction deletes a previously inserted edge.";
+        opt output:Option<String>, short:'o', desc:"Write to this file instead of stdout, e.g., 'scc.dat'.";
+    };
+    let (args, rest) = parser.parse_or_exit();
+
+    if !rest.is_empty() {
+        return Err("Invalid command line arguments; try -h for help".to_string());
+    }
+    if args.transactions == 0 {
+        return Err("Invalid number of transactions: 0".to_string());
+    }
+    if !(0.0..1.0).contains(&args.delete_ratio) {
+        return Err(format!(
+            "Invalid delete ratio {}, must be in [0, 1)",
+            args.delete_ratio
+        ));
+    }
+
+    let kind = GraphKind::from_name(&args.kind, args.nodes, args.edges)?;
+    let edges = kind.generate(args.seed);
+    let transactions =
+        scc_workload::split_transactions(&edges, args.transactions, args.delete_ratio, args.seed);
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| format!("failed to create '{}': {}", path, e))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+
+    scc_workload::write_transactions(&mut out, &transactions)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("failed to write graph: {}", e))
+}
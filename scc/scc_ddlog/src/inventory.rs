@@ -1,15 +1,15 @@
 use crate::{
     d3log_localize_val, idxkey_from_record, indexes2arrid, indexid2name, rel_name2orig_name,
-    relid2name, relkey_from_record, relval_from_record, Indexes, Relations,
-    RAW_INPUT_RELATION_ID_MAP,
+    relid2name, relkey_from_record, relval_from_record, Indexes, Relations, IDXIDMAP,
+    RAW_INPUT_RELATION_ID_MAP, RELIDMAP,
 };
 #[cfg(feature = "c_api")]
 use crate::{indexid2cname, rel_name2orig_cname, relid2cname};
 use differential_datalog::{
     ddval::DDValue,
     program::{ArrId, IdxId, RelId},
-    record::{Record, RelIdentifier},
-    D3logLocalizer, D3logLocationId, DDlogInventory,
+    record::{relation_declaration, Record, RelIdentifier},
+    D3logLocalizer, D3logLocationId, DDlogError, DDlogInventory,
 };
 use fnv::FnvHashMap;
 #[cfg(feature = "c_api")]
@@ -20,55 +20,88 @@ use std::{any::TypeId, convert::TryFrom};
 pub struct Inventory;
 
 impl DDlogInventory for Inventory {
-    fn get_table_id(&self, table_name: &str) -> Result<RelId, String> {
+    fn get_table_id(&self, table_name: &str) -> Result<RelId, DDlogError> {
         Relations::try_from(table_name).map_or_else(
-            |()| Err(format!("unknown relation {}", table_name)),
+            |()| Err(DDlogError::UnknownRelation(table_name.to_string())),
             |rel| Ok(rel as RelId),
         )
     }
 
-    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, String> {
-        relid2name(table_id).ok_or_else(|| format!("unknown relation {}", table_id))
+    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, DDlogError> {
+        relid2name(table_id).ok_or_else(|| DDlogError::UnknownRelation(table_id.to_string()))
     }
 
-    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, String> {
-        rel_name2orig_name(table_name).ok_or_else(|| format!("unknown relation {}", table_name))
+    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, DDlogError> {
+        rel_name2orig_name(table_name)
+            .ok_or_else(|| DDlogError::UnknownRelation(table_name.to_string()))
     }
 
     #[cfg(feature = "c_api")]
-    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, String> {
-        rel_name2orig_cname(table_name).ok_or_else(|| format!("unknown relation {}", table_name))
+    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, DDlogError> {
+        rel_name2orig_cname(table_name)
+            .ok_or_else(|| DDlogError::UnknownRelation(table_name.to_string()))
     }
 
     #[cfg(feature = "c_api")]
-    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, String> {
-        relid2cname(table_id).ok_or_else(|| format!("unknown relation {}", table_id))
+    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, DDlogError> {
+        relid2cname(table_id).ok_or_else(|| DDlogError::UnknownRelation(table_id.to_string()))
     }
 
-    fn get_index_id(&self, index_name: &str) -> Result<IdxId, String> {
+    fn get_index_id(&self, index_name: &str) -> Result<IdxId, DDlogError> {
         Indexes::try_from(index_name).map_or_else(
-            |()| Err(format!("unknown index {}", index_name)),
+            |()| Err(DDlogError::UnknownIndex(index_name.to_string())),
             |idx| Ok(idx as IdxId),
         )
     }
 
-    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, String> {
-        indexid2name(index_id).ok_or_else(|| format!("unknown index {}", index_id))
+    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, DDlogError> {
+        indexid2name(index_id).ok_or_else(|| DDlogError::UnknownIndex(index_id.to_string()))
     }
 
     #[cfg(feature = "c_api")]
-    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, String> {
-        indexid2cname(index_id).ok_or_else(|| format!("unknown index {}", index_id))
+    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, DDlogError> {
+        indexid2cname(index_id).ok_or_else(|| DDlogError::UnknownIndex(index_id.to_string()))
+    }
+
+    fn table_names(&self) -> Vec<&'static str> {
+        let mut relations: Vec<_> = RELIDMAP.iter().collect();
+        relations.sort_unstable_by_key(|(&relation, _)| relation as RelId);
+        relations.into_iter().map(|(_, &name)| name).collect()
+    }
+
+    fn index_names(&self) -> Vec<&'static str> {
+        let mut indexes: Vec<_> = IDXIDMAP.iter().collect();
+        indexes.sort_unstable_by_key(|(&index, _)| index as IdxId);
+        indexes.into_iter().map(|(_, &name)| name).collect()
+    }
+
+    fn get_table_schema(&self, table_id: RelId) -> Result<String, DDlogError> {
+        let relation = Relations::try_from(table_id)
+            .map_err(|_| DDlogError::UnknownRelation(table_id.to_string()))?;
+        let kind = if relation.is_input() {
+            "input relation"
+        } else if relation.is_output() {
+            "output relation"
+        } else {
+            "relation"
+        };
+        let template = self.relation_template(table_id);
+        Ok(relation_declaration(
+            kind,
+            self.get_table_name(table_id)?,
+            template.as_ref(),
+            |value| relval_from_record(relation, value).is_ok(),
+        ))
     }
 
     fn input_relation_ids(&self) -> &'static FnvHashMap<RelId, &'static str> {
         &*RAW_INPUT_RELATION_ID_MAP
     }
 
-    fn index_from_record(&self, index_id: IdxId, key: &Record) -> Result<DDValue, String> {
-        let index =
-            Indexes::try_from(index_id).map_err(|_| format!("Unknown index {}", index_id))?;
-        idxkey_from_record(index, key)
+    fn index_from_record(&self, index_id: IdxId, key: &Record) -> Result<DDValue, DDlogError> {
+        let index = Indexes::try_from(index_id)
+            .map_err(|_| DDlogError::UnknownIndex(index_id.to_string()))?;
+        idxkey_from_record(index, key).map_err(DDlogError::TypeMismatch)
     }
 
     fn relation_type_id(&self, relation: RelId) -> Option<TypeId> {
@@ -81,20 +114,24 @@ impl DDlogInventory for Inventory {
         &self,
         relation: &RelIdentifier,
         record: &Record,
-    ) -> Result<(RelId, DDValue), String> {
+    ) -> Result<(RelId, DDValue), DDlogError> {
         let relation = Relations::try_from(relation)
-            .map_err(|_| format!("Unknown relation {:?}", relation))?;
-        relval_from_record(relation, record).map(|value| (relation as RelId, value))
+            .map_err(|_| DDlogError::UnknownRelation(relation.to_string()))?;
+        relval_from_record(relation, record)
+            .map_err(DDlogError::TypeMismatch)
+            .map(|value| (relation as RelId, value))
     }
 
     fn relation_key_from_record(
         &self,
         relation: &RelIdentifier,
         record: &Record,
-    ) -> Result<(RelId, DDValue), String> {
+    ) -> Result<(RelId, DDValue), DDlogError> {
         let relation = Relations::try_from(relation)
-            .map_err(|_| format!("Unknown relation {:?}", relation))?;
-        relkey_from_record(relation, record).map(|key| (relation as RelId, key))
+            .map_err(|_| DDlogError::UnknownRelation(relation.to_string()))?;
+        relkey_from_record(relation, record)
+            .map_err(DDlogError::TypeMismatch)
+            .map(|key| (relation as RelId, key))
     }
 
     fn index_to_arrangement_id(&self, index: IdxId) -> Option<ArrId> {
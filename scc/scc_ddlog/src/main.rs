@@ -6,7 +6,8 @@
 
 use std::{
     convert::TryFrom,
-    io::{stdout, Write},
+    fs::{self, File},
+    io::{self, stdout, BufReader, BufWriter, Write},
     net::SocketAddr,
     process,
     process::Stdio,
@@ -20,12 +21,16 @@ use cmd_parser::*;
 use scc_ddlog::*;
 use ddlog_log::log_set_default_callback;
 use differential_datalog::{
-    api::HDDlog,
+    api::{csv_deltas::CsvDeltaWriter, HDDlog},
     ddval::*,
     program::config::{Config, LoggingDestination, ProfilingConfig},
     program::*,
     record::*,
-    DDlog, DDlogDynamic, DDlogProfiling, DeltaMap,
+    replay::{
+        diff_changes, format_changes, parse_timestamp, EXPECTED_CHANGES_HEADER,
+        EXPECTED_CHANGE_PREFIX,
+    },
+    DDlog, DDlogDynamic, DDlogError, DDlogInventory, DDlogProfiling, DeltaMap,
 };
 use num_traits::cast::ToPrimitive;
 use rustop::opts;
@@ -33,15 +38,48 @@ use rustop::opts;
 #[cfg(feature = "profile")]
 use cpuprofiler::PROFILER;
 
+mod server;
+
 const DDSHOW_TIMEOUT_MILLIS: u64 = 3_000;
 
-#[allow(clippy::let_and_return)]
+/// How records are printed by `dump`, `commit dump_changes`, `query_index` and `dump_index`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// DDlog's textual record syntax.
+    Text,
+    /// One `{"relation": .., "record": .., "weight": ..}` object per line.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "Invalid output format '{}', must be 'text' or 'json'",
+                s
+            )),
+        }
+    }
+}
+
+/// Output relation whose changes are appended to a CSV file after every commit, as requested by
+/// `dump_csv <relation> "<path>" deltas;`.
+type CsvDeltas = CsvDeltaWriter<BufWriter<File>>;
+
+#[allow(clippy::let_and_return, clippy::too_many_arguments)]
 fn handle_cmd(
     start_time: Instant,
     hddlog: &HDDlog,
     print_deltas: bool,
+    format: OutputFormat,
     interactive: bool,
     upds: &mut Vec<Update<DDValue>>,
+    csv_deltas: &mut Vec<CsvDeltas>,
+    out: &mut dyn Write,
     cmd: Command,
 ) -> (Result<(), String>, bool) {
     let resp = (if !is_upd_cmd(&cmd) {
@@ -60,7 +98,7 @@ fn handle_cmd(
             );
             Ok(())
         }
-        Command::Commit(record_delta) => {
+        Command::Commit(record_delta, timeout) => {
             #[cfg(feature = "profile")]
             {
                 PROFILER
@@ -70,12 +108,20 @@ fn handle_cmd(
                     .expect("Couldn't start profiling");
             }
 
-            let res = if record_delta {
-                hddlog.transaction_commit_dump_changes().map(|changes| {
-                    if print_deltas {
-                        dump_delta(&changes)
+            // CSV delta files need the changes of every commit.
+            let res = if record_delta || !csv_deltas.is_empty() {
+                match timeout {
+                    Some(timeout) => hddlog.transaction_commit_dump_changes_with_deadline(timeout),
+                    None => hddlog.transaction_commit_dump_changes(),
+                }
+                .and_then(|changes| {
+                    if record_delta && print_deltas {
+                        dump_delta(&changes, format, out)
                     }
+                    write_csv_deltas(csv_deltas, &changes)
                 })
+            } else if let Some(timeout) = timeout {
+                hddlog.transaction_commit_with_deadline(timeout)
             } else {
                 hddlog.transaction_commit()
             };
@@ -92,23 +138,31 @@ fn handle_cmd(
         }
         Command::Comment => Ok(()),
         Command::Rollback => hddlog.transaction_rollback(),
+        Command::Savepoint(name) => hddlog.savepoint(&name),
+        Command::RollbackTo(name) => hddlog.rollback_to(&name),
+        Command::WhatIf(updates) => updates
+            .iter()
+            .map(|update| hddlog.convert_update_command(update))
+            .collect::<Response<Vec<_>>>()
+            .and_then(|updates| hddlog.evaluate_hypothetical(&mut updates.into_iter()))
+            .map(|changes| dump_delta(&changes, format, out)),
         Command::Timestamp => {
-            println!("Timestamp: {}", start_time.elapsed().as_nanos());
+            let _ = writeln!(out, "Timestamp: {}", start_time.elapsed().as_nanos());
             Ok(())
         }
-        Command::Profile(None) => hddlog
-            .profile()
-            .map(|profile| println!("Profile:\n{}", profile)),
+        Command::Profile(None) => hddlog.profile().map(|profile| {
+            let _ = writeln!(out, "Profile:\n{}", profile);
+        }),
         Command::Profile(Some(ProfileCmd::Cpu(enable))) => hddlog.enable_cpu_profiling(enable),
         Command::Profile(Some(ProfileCmd::Timely(enable))) => {
             hddlog.enable_timely_profiling(enable)
         }
 
         Command::Dump(None) => {
-            let _ = hddlog
-                .db
-                .as_ref()
-                .map(|db| db.lock().unwrap().format_as_sets(&mut stdout(), &Inventory));
+            let _ = hddlog.db.as_ref().map(|db| match format {
+                OutputFormat::Text => db.lock().unwrap().format_as_sets(out, &Inventory),
+                OutputFormat::Json => db.lock().unwrap().format_json(out, &Inventory),
+            });
 
             Ok(())
         }
@@ -123,10 +177,10 @@ fn handle_cmd(
                     return (Err(err), interactive);
                 }
             };
-            let _ = hddlog
-                .db
-                .as_ref()
-                .map(|db| db.lock().unwrap().format_rel_as_set(relid, &mut stdout()));
+            let _ = hddlog.db.as_ref().map(|db| match format {
+                OutputFormat::Text => db.lock().unwrap().format_rel_as_set(relid, out),
+                OutputFormat::Json => db.lock().unwrap().format_rel_json(relid, out, &Inventory),
+            });
             Ok(())
         }
         Command::Clear(rname) => {
@@ -142,11 +196,11 @@ fn handle_cmd(
             };
             hddlog.clear_relation(relid)
         }
-        Command::Exit => {
+        Command::Exit | Command::Shutdown => {
             return (Ok(()), false);
         }
         Command::Echo(txt) => {
-            println!("{}", txt);
+            let _ = writeln!(out, "{}", txt);
             Ok(())
         }
         Command::Sleep(ms) => {
@@ -162,7 +216,7 @@ fn handle_cmd(
                         eprintln!("Error: {}", err);
                     }
 
-                    return (Err(err), interactive);
+                    return (Err(err.to_string()), interactive);
                 }
             }
 
@@ -173,23 +227,145 @@ fn handle_cmd(
             }
         }
         Command::QueryIndex(idx, key) => Indexes::try_from(idx.as_str())
-            .map_err(|_| format!("Unknown index {}", idx))
+            .map_err(|_| DDlogError::UnknownIndex(idx.clone()))
             .and_then(|idxid| {
-                idxkey_from_record(idxid, &key)
+                Inventory
+                    .index_from_record(idxid as IdxId, &key)
                     .and_then(|keyval| hddlog.query_index(idxid as IdxId, keyval))
+                    .map(|vals| dump_index_values(idxid, vals, format, out))
+            }),
+        Command::QueryIndexRange(idx, lo, hi) => Indexes::try_from(idx.as_str())
+            .map_err(|_| DDlogError::UnknownIndex(idx.clone()))
+            .and_then(|idxid| {
+                let bound = |key: Option<Record>| {
+                    key.map(|key| Inventory.index_from_record(idxid as IdxId, &key))
+                        .transpose()
+                };
+                bound(lo)
+                    .and_then(|lo| Ok((lo, bound(hi)?)))
+                    .and_then(|(lo, hi)| hddlog.query_index_range(idxid as IdxId, lo, hi))
+                    .map(|vals| dump_index_values(idxid, vals, format, out))
+            }),
+        Command::QueryIndexPrefix(idx, prefix) => Indexes::try_from(idx.as_str())
+            .map_err(|_| DDlogError::UnknownIndex(idx.clone()))
+            .and_then(|idxid| {
+                hddlog
+                    .query_index_prefix(idxid as IdxId, &prefix)
+                    .map(|vals| dump_index_values(idxid, vals, format, out))
+            }),
+        Command::DumpIndex(idx) => Indexes::try_from(idx.as_str())
+            .map_err(|_| DDlogError::UnknownIndex(idx.clone()))
+            .and_then(|idxid| {
+                hddlog
+                    .dump_index(idxid as IdxId)
+                    .map(|vals| dump_index_values(idxid, vals, format, out))
+            }),
+        Command::DumpIndexPage(idx, limit, after) => Indexes::try_from(idx.as_str())
+            .map_err(|_| DDlogError::UnknownIndex(idx.clone()))
+            .and_then(|idxid| {
+                after
+                    .map(|(key, val)| index_cursor_from_records(idxid, &key, &val))
+                    .transpose()
+                    .and_then(|after| hddlog.dump_index_page(idxid as IdxId, after, limit))
+                    .map(|page| {
+                        dump_index_values(idxid, page.values, format, out);
+                        if let Some(next) = page.next {
+                            let _ = writeln!(
+                                out,
+                                "# next page: dump_index {} limit {} after ({}), ({});",
+                                idx,
+                                limit,
+                                next.key.into_record(),
+                                next.val.into_record()
+                            );
+                        }
+                    })
+            }),
+        Command::Query(query) => Relations::try_from(query.relation.as_str())
+            .ok()
+            .filter(|relid| relid.is_output())
+            .ok_or_else(|| DDlogError::UnknownRelation(query.relation.clone()))
+            .and_then(|relid| {
+                let db = hddlog.db.as_ref().ok_or_else(|| {
+                    DDlogError::other(
+                        "Cannot query relations whose contents are not stored (see --no-store)",
+                    )
+                })?;
+                match db.lock().unwrap().try_get_rel(relid as RelId) {
+                    Some(rel) => query
+                        .evaluate(rel.keys().map(|val| val.clone().into_record()))
+                        .map_err(DDlogError::TypeMismatch),
+                    None => Ok(Vec::new()),
+                }
             })
-            .map(|vals| {
-                for val in vals.into_iter() {
-                    let _ = writeln!(stdout(), "{}", val.clone().into_record());
+            .map(|records| {
+                for record in records.iter() {
+                    let _ = writeln!(out, "{}", record);
                 }
             }),
-        Command::DumpIndex(idx) => Indexes::try_from(idx.as_str())
-            .map_err(|_| format!("Unknown index {}", idx))
-            .and_then(|idxid| hddlog.dump_index(idxid as IdxId))
-            .map(|vals| {
-                for val in vals.into_iter() {
-                    let _ = writeln!(stdout(), "{}", val.clone().into_record());
+        Command::Count(rname) => Relations::try_from(rname.as_str())
+            .map_err(|_| DDlogError::UnknownRelation(rname.clone()))
+            .and_then(|relid| hddlog.count_relation(relid as RelId))
+            .map(|count| {
+                let _ = writeln!(out, "{}", count);
+            }),
+        Command::CountIndex(idx, key) => Indexes::try_from(idx.as_str())
+            .map_err(|_| DDlogError::UnknownIndex(idx.clone()))
+            .and_then(|idxid| {
+                key.map(|key| Inventory.index_from_record(idxid as IdxId, &key))
+                    .transpose()
+                    .and_then(|key| hddlog.count_index(idxid as IdxId, key))
+            })
+            .map(|count| {
+                let _ = writeln!(out, "{}", count);
+            }),
+        Command::LoadCsv(rname, path, header) => File::open(&path)
+            .map_err(|e| DDlogError::io(format!("Failed to open '{}'", path), e))
+            .and_then(|file| hddlog.load_csv(&rname, BufReader::new(file), header))
+            .map(|_| ()),
+        Command::DumpCsv(rname, path, deltas) => {
+            let relid = match Relations::try_from(rname.as_str()) {
+                Ok(rid) if rid.is_output() => rid as RelId,
+                _ => {
+                    let err = format!("Unknown output relation {}", rname);
+                    if interactive {
+                        eprintln!("Error: {}", err);
+                    }
+                    return (Err(err), interactive);
                 }
+            };
+            File::create(&path)
+                .map_err(|e| DDlogError::io(format!("Failed to create '{}'", path), e))
+                .and_then(|file| {
+                    if deltas {
+                        csv_deltas.push(hddlog.csv_deltas(relid, BufWriter::new(file))?);
+                        Ok(())
+                    } else {
+                        hddlog.dump_csv(relid, &mut BufWriter::new(file))
+                    }
+                })
+        }
+        Command::Checkpoint(Some(path)) => hddlog.checkpoint(&path),
+        Command::Checkpoint(None) => hddlog.checkpoint_wal(),
+        Command::Help(None) => {
+            let _ = writeln!(out, "Commands: {}", COMMANDS.join(", "));
+            let _ = writeln!(out, "Relations:");
+            for name in Inventory.table_names() {
+                if let Ok(schema) = Inventory
+                    .get_table_id(name)
+                    .and_then(|relid| Inventory.get_table_schema(relid))
+                {
+                    let _ = writeln!(out, "    {}", schema);
+                }
+            }
+            let _ = writeln!(out, "Indexes: {}", Inventory.index_names().join(", "));
+            Ok(())
+        }
+        Command::Help(Some(rname)) => Inventory
+            .get_table_id(&rname)
+            .and_then(|relid| Inventory.get_table_schema(relid))
+            .map(|schema| {
+                let _ = writeln!(out, "{}", schema);
             }),
     });
     match resp {
@@ -198,21 +374,73 @@ fn handle_cmd(
             if interactive {
                 eprintln!("Error: {}", e);
             }
-            (Err(e), interactive)
+            (Err(e.to_string()), interactive)
         }
     }
 }
 
-fn dump_delta(delta: &DeltaMap<DDValue>) {
+fn dump_delta(delta: &DeltaMap<DDValue>, format: OutputFormat, out: &mut dyn Write) {
+    if format == OutputFormat::Json {
+        let _ = delta.format_json(out, &Inventory);
+        return;
+    }
+
     for (table_id, table_data) in delta.iter() {
-        let _ = writeln!(stdout(), "{}:", relid2name(*table_id).unwrap());
+        let _ = writeln!(out, "{}:", relid2name(*table_id).unwrap());
         for (val, weight) in table_data.iter() {
             //debug_assert!(*weight == 1 || *weight == -1);
-            let _ = writeln!(stdout(), "{}: {:+}", val.clone().into_record(), *weight);
+            let _ = writeln!(out, "{}: {:+}", val.clone().into_record(), *weight);
         }
     }
 }
 
+fn dump_index_values(
+    idxid: Indexes,
+    vals: impl IntoIterator<Item = DDValue>,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) {
+    match format {
+        OutputFormat::Text => {
+            for val in vals.into_iter() {
+                let _ = writeln!(out, "{}", val.into_record());
+            }
+        }
+        OutputFormat::Json => {
+            // Print index contents as records of the relation the index is built on.
+            let relid = indexes2arrid(idxid).0;
+            let _ = DeltaMap::singleton(relid, vals.into_iter().map(|val| (val, 1)).collect())
+                .format_json(out, &Inventory);
+        }
+    }
+}
+
+/// Convert the key and value of an index entry, as printed by a paginated `dump_index`, to an
+/// `ArrangementCursor`.
+fn index_cursor_from_records(
+    idxid: Indexes,
+    key: &Record,
+    val: &Record,
+) -> Response<ArrangementCursor> {
+    // Index values are records of the relation the index is built on.
+    let relation = Relations::try_from(indexes2arrid(idxid).0)
+        .map_err(|_| DDlogError::Other(format!("Unknown relation of index {:?}", idxid)))?;
+    Ok(ArrangementCursor {
+        key: Inventory.index_from_record(idxid as IdxId, key)?,
+        val: relval_from_record(relation, val).map_err(DDlogError::TypeMismatch)?,
+    })
+}
+
+fn write_csv_deltas(csv_deltas: &mut [CsvDeltas], changes: &DeltaMap<DDValue>) -> Response<()> {
+    for deltas in csv_deltas.iter_mut() {
+        deltas
+            .write_changes(changes)
+            .map_err(|e| DDlogError::io("Failed to write CSV deltas", e))?;
+    }
+
+    Ok(())
+}
+
 fn apply_updates(hddlog: &HDDlog, upds: &mut Vec<Update<DDValue>>) -> Response<()> {
     if !upds.is_empty() {
         hddlog.apply_updates(&mut upds.drain(..))
@@ -221,6 +449,174 @@ fn apply_updates(hddlog: &HDDlog, upds: &mut Vec<Update<DDValue>>) -> Response<(
     }
 }
 
+/// Execute a transaction recovered from the write-ahead log.
+fn replay_transaction(hddlog: &HDDlog, transaction: &str) -> Response<()> {
+    let start_time = Instant::now();
+    let mut upds = Vec::new();
+    for cmd in parse_commands(transaction).map_err(DDlogError::Other)? {
+        handle_cmd(
+            start_time,
+            hddlog,
+            false,
+            OutputFormat::Text,
+            false,
+            &mut upds,
+            &mut Vec::new(),
+            &mut io::sink(),
+            cmd,
+        )
+        .0
+        .map_err(DDlogError::Other)?;
+    }
+
+    Ok(())
+}
+
+/// Replay a recording made with `--record-changes` and compare the changes of every annotated
+/// commit with the recorded ones, failing with a diff on the first mismatch.
+fn verify(hddlog: HDDlog, path: &str) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+
+    let mut upds = Vec::new();
+    let mut commands = String::new();
+    let mut commits = 0;
+    let mut verified = 0;
+    let mut lines = text.lines().enumerate().peekable();
+    while let Some((lineno, line)) = lines.next() {
+        if line.trim_end() != EXPECTED_CHANGES_HEADER {
+            commands.push_str(line);
+            commands.push('\n');
+            continue;
+        }
+
+        let changes = replay_commands(&hddlog, &commands, &mut upds, &mut commits)
+            .map_err(|e| format!("Failed to replay '{}' up to line {}: {}", path, lineno, e))?
+            .ok_or_else(|| {
+                format!(
+                    "Expected changes at line {} of '{}' don't follow a commit",
+                    lineno + 1,
+                    path
+                )
+            })?;
+        commands.clear();
+
+        let mut expected = Vec::new();
+        while let Some(change) = lines
+            .peek()
+            .and_then(|(_, line)| line.strip_prefix(EXPECTED_CHANGE_PREFIX))
+        {
+            expected.push(change.trim_end().to_string());
+            lines.next();
+        }
+
+        let diff = diff_changes(&expected, &format_changes(&*hddlog.inventory, &changes));
+        if !diff.is_empty() {
+            return Err(format!(
+                "Commit {} (line {} of '{}') differs from the recording:\n{}",
+                commits,
+                lineno,
+                path,
+                diff.join("\n")
+            ));
+        }
+        verified += 1;
+    }
+
+    replay_commands(&hddlog, &commands, &mut upds, &mut commits)
+        .map_err(|e| format!("Failed to replay '{}': {}", path, e))?;
+    println!("Verified {} of {} commits in '{}'", verified, commits, path);
+
+    hddlog.stop().map_err(String::from)
+}
+
+/// Execute a file recorded with `--record-timestamps`, starting every transaction after the
+/// same delay from the start of the replay as in the recording, divided by `speedup`.
+fn replay_paced(
+    hddlog: HDDlog,
+    path: &str,
+    speedup: f64,
+    print_deltas: bool,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {}", path, e))?;
+
+    let start_time = Instant::now();
+    let mut upds = Vec::new();
+    let mut csv_deltas = Vec::new();
+    let mut execute = |commands: &str| -> Result<(), String> {
+        for cmd in parse_commands(commands)? {
+            handle_cmd(
+                start_time,
+                &hddlog,
+                print_deltas,
+                format,
+                false,
+                &mut upds,
+                &mut csv_deltas,
+                &mut stdout(),
+                cmd,
+            )
+            .0?;
+        }
+        Ok(())
+    };
+
+    let mut commands = String::new();
+    for (lineno, line) in text.lines().enumerate() {
+        match parse_timestamp(line) {
+            Some(timestamp) => {
+                execute(&commands).map_err(|e| {
+                    format!("Failed to replay '{}' up to line {}: {}", path, lineno, e)
+                })?;
+                commands.clear();
+
+                let due = start_time + timestamp.div_f64(speedup);
+                thread::sleep(due.saturating_duration_since(Instant::now()));
+            }
+            None => {
+                commands.push_str(line);
+                commands.push('\n');
+            }
+        }
+    }
+    execute(&commands).map_err(|e| format!("Failed to replay '{}': {}", path, e))?;
+
+    hddlog.stop().map_err(String::from)
+}
+
+/// Execute `commands`, returning the changes made by the last commit among them.
+fn replay_commands(
+    hddlog: &HDDlog,
+    commands: &str,
+    upds: &mut Vec<Update<DDValue>>,
+    commits: &mut usize,
+) -> Result<Option<DeltaMap<DDValue>>, String> {
+    let start_time = Instant::now();
+    let mut changes = None;
+    for cmd in parse_commands(commands)? {
+        if let Command::Commit(..) = cmd {
+            apply_updates(hddlog, upds)?;
+            changes = Some(hddlog.transaction_commit_dump_changes()?);
+            *commits += 1;
+        } else {
+            handle_cmd(
+                start_time,
+                hddlog,
+                false,
+                OutputFormat::Text,
+                false,
+                upds,
+                &mut Vec::new(),
+                &mut io::sink(),
+                cmd,
+            )
+            .0?;
+        }
+    }
+
+    Ok(changes)
+}
+
 fn is_upd_cmd(c: &Command) -> bool {
     match c {
         Command::Update(_, _) => true,
@@ -228,21 +624,30 @@ fn is_upd_cmd(c: &Command) -> bool {
     }
 }
 
-fn run(hddlog: HDDlog, print_deltas: bool) -> Result<(), String> {
+fn run(
+    hddlog: HDDlog,
+    print_deltas: bool,
+    input: InputFormat,
+    format: OutputFormat,
+) -> Result<(), String> {
     let upds = Arc::new(Mutex::new(Vec::new()));
+    let csv_deltas = Arc::new(Mutex::new(Vec::new()));
     let start_time = Instant::now();
-    interact(|cmd, interactive| {
+    interact_with_inventory(input, Some(&Inventory), |cmd, interactive| {
         handle_cmd(
             start_time,
             &hddlog,
             print_deltas,
+            format,
             interactive,
             &mut upds.lock().unwrap(),
+            &mut csv_deltas.lock().unwrap(),
+            &mut stdout(),
             cmd,
         )
     })?;
 
-    hddlog.stop()
+    hddlog.stop().map_err(String::from)
 }
 
 #[allow(clippy::redundant_closure)]
@@ -264,6 +669,17 @@ fn main() -> Result<(), String> {
         opt differential_profiler_socket:Option<String>, desc:"Socket address to send Differential Dataflow profiling events. Default (if '--profile-differential' is specified is '127.0.0.1:51318'. Implies '--profile-differential'.";
         opt differential_trace_dir:Option<String>, desc:"Path to a directory to store Differential Dataflow profiling events, e.g., './differential_trace'. Implies '--profile-differential'.";
         opt ddshow:bool=false, desc:"Start 'ddshow' profiler on sockets specified by '--timely-profiler-socket' and (optionally) '--differential-profiler-socket' options. Implies '--timely-profiler'.";
+        opt restore_from:Option<String>, desc:"Restore input relations from a checkpoint file written by the 'checkpoint' command.";
+        opt wal:Option<String>, desc:"Directory of a write-ahead log. The state saved in it is recovered on startup, and every committed transaction is logged to it. 'checkpoint;' compacts the log.";
+        opt record:Option<String>, desc:"Record all commands to a file that can be replayed.";
+        opt record_changes:bool=false, desc:"Annotate every commit in the '--record' file with the changes it produced, for '--verify'.";
+        opt record_timestamps:bool=false, desc:"Stamp every transaction in the '--record' file with the time at which it started, for '--replay'.";
+        opt replay:Option<String>, desc:"Execute a file recorded with '--record-timestamps' instead of reading commands from stdin, reproducing the original pacing of its transactions.";
+        opt speedup:f64=1.0, desc:"With '--replay', divide the delays between transactions by this factor.";
+        opt listen:Option<String>, desc:"Serve commands to clients connecting to this TCP address, or to a Unix domain socket given as 'unix:<path>', instead of reading them from stdin.";
+        opt verify:Option<String>, desc:"Replay a file recorded with '--record-changes' instead of reading commands from stdin, and fail on the first commit whose changes differ from the recorded ones.";
+        opt input_format:String=String::from("text"), desc:"Format of commands read from stdin or, with '--listen', from clients: 'text' (DDlog syntax) or 'json' (one JSON object per line).";
+        opt output_format:String=String::from("text"), desc:"Format of dumped records: 'text' (DDlog syntax) or 'json' (one JSON object per line).";
     };
     let (mut args, rest) = parser.parse_or_exit();
 
@@ -271,6 +687,18 @@ fn main() -> Result<(), String> {
         return Err("Invalid command line arguments; try -h for help".to_string());
     }
 
+    let input = match args.input_format.as_str() {
+        "text" => InputFormat::Text,
+        "json" => InputFormat::Json,
+        input => {
+            return Err(format!(
+                "Invalid input format '{}', must be 'text' or 'json'",
+                input
+            ))
+        }
+    };
+    let format = OutputFormat::from_str(&args.output_format)?;
+
     let mut config = Config {
         num_timely_workers: if args.workers == 0 {
             return Err("Invalid number of workers: 0".to_string());
@@ -350,6 +778,33 @@ fn main() -> Result<(), String> {
         );
     }
 
+    // 'restore-from' and 'wal' are mutually exclusive.
+    if args.restore_from.is_some() && args.wal.is_some() {
+        return Err("Conflicting options: --restore-from and --wal.".to_string());
+    }
+
+    // 'record-changes' and 'record-timestamps' require 'record'
+    if args.record_changes && args.record.is_none() {
+        return Err("--record-changes requires --record.".to_string());
+    }
+    if args.record_timestamps && args.record.is_none() {
+        return Err("--record-timestamps requires --record.".to_string());
+    }
+
+    // 'replay' and 'verify' are mutually exclusive.
+    if args.replay.is_some() && args.verify.is_some() {
+        return Err("Conflicting options: --replay and --verify.".to_string());
+    }
+
+    // 'listen' conflicts with 'replay' and 'verify'.
+    if args.listen.is_some() && (args.replay.is_some() || args.verify.is_some()) {
+        return Err("--listen conflicts with --replay and --verify.".to_string());
+    }
+
+    if !(args.speedup.is_finite() && args.speedup > 0.0) {
+        return Err(format!("Invalid speedup factor: {}", args.speedup));
+    }
+
     // 'differential-trace-dir' requires 'timely-trace-dir'
     if args.differential_trace_dir.is_some() && args.timely_trace_dir.is_none() {
         return Err("--differential-trace-dir requires --timely-trace-dir.".to_string());
@@ -413,11 +868,43 @@ fn main() -> Result<(), String> {
     };
 
     let ddlog_res = match crate::run_with_config(config, args.store) {
-        Ok((hddlog, init_output)) => {
+        Ok((mut hddlog, init_output)) => {
             if args.init_snapshot {
-                dump_delta(&init_output);
+                dump_delta(&init_output, format, &mut stdout());
             }
-            run(hddlog, args.delta)
+            let recording = match &args.record {
+                Some(path) => File::create(path)
+                    .map(|file| {
+                        hddlog.record_commands(&mut Some(file));
+                        hddlog.record_changes(args.record_changes);
+                        hddlog.record_timestamps(args.record_timestamps);
+                    })
+                    .map_err(|e| DDlogError::io(format!("Failed to create '{}'", path), e)),
+                None => Ok(()),
+            };
+            let recovered = recording.and_then(|_| match (&args.restore_from, &args.wal) {
+                (Some(path), _) => hddlog.restore::<UpdateSerializer, _>(path).map(|restored| {
+                    if args.init_snapshot {
+                        dump_delta(&restored, format, &mut stdout());
+                    }
+                }),
+                (None, Some(dir)) => {
+                    hddlog.recover_wal::<UpdateSerializer, _, _>(dir, replay_transaction)
+                }
+                (None, None) => Ok(()),
+            });
+            recovered
+                .map_err(String::from)
+                .and_then(|_| match (&args.verify, &args.replay) {
+                    (Some(path), _) => verify(hddlog, path),
+                    (None, Some(path)) => {
+                        replay_paced(hddlog, path, args.speedup, args.delta, format)
+                    }
+                    (None, None) => match &args.listen {
+                        Some(addr) => server::listen(hddlog, addr, args.delta, input, format),
+                        None => run(hddlog, args.delta, input, format),
+                    },
+                })
         }
         Err(err) => Err(format!("Failed to run differential datalog: {}", err)),
     };
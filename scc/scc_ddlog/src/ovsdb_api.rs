@@ -63,11 +63,12 @@ fn apply_updates(
         .map_err(|e| format!("invalid UTF8 string in prefix: {}", e))?;
     let commands = cmds_from_table_updates_str(prefix, updates_str)?;
 
-    let updates: Result<Vec<Update<DDValue>>, String> = commands
+    let updates: Result<Vec<Update<DDValue>>, _> = commands
         .iter()
         .map(|c| prog.convert_update_command(c))
         .collect();
     prog.apply_updates(&mut updates?.into_iter())
+        .map_err(String::from)
 }
 
 /// Dump OVSDB Delta-Plus, Delta-Minus, and Delta-Update tables as a sequence of OVSDB
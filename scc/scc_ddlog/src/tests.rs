@@ -0,0 +1,248 @@
+//! Tests of the runtime API (`HDDlog`) that need a program to run, which the
+//! `differential_datalog` crate doesn't have.
+
+use crate::{typedefs::Edge, D3logInventory, Inventory, Relations};
+use differential_datalog::{
+    api::{subscription::SlowConsumerPolicy, HDDlog},
+    ddval::{DDValConvert, DDValue},
+    flatbuf::UnimplementedFlatbufConverter,
+    program::{config::Config, Program, RelId, RelationCallback, Update, Weight},
+    DDlog, DDlogDynamic, DDlogError,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Run the program built by `init` on two workers, storing its outputs.
+fn run_program(init: fn(Arc<dyn RelationCallback>) -> Program) -> HDDlog {
+    HDDlog::new(
+        Config::new().with_timely_workers(2),
+        true,
+        None,
+        init,
+        Box::new(Inventory),
+        Box::new(D3logInventory),
+        Box::new(UnimplementedFlatbufConverter),
+    )
+    .unwrap()
+    .0
+}
+
+fn edges(edges: &[(u32, u32)]) -> impl Iterator<Item = Update<DDValue>> + '_ {
+    edges.iter().map(|&(src, dest)| Update::Insert {
+        relid: Relations::Edge as RelId,
+        v: Edge { src, dest }.into_ddvalue(),
+    })
+}
+
+/// Insert `edges` in a transaction of their own.
+fn insert_edges(hddlog: &HDDlog, new: &[(u32, u32)]) -> Result<(), DDlogError> {
+    hddlog.transaction_start()?;
+    hddlog.apply_updates(&mut edges(new))?;
+    hddlog.transaction_commit()
+}
+
+/// The stored contents of `StronglyConnected`.
+fn strongly_connected(hddlog: &HDDlog) -> BTreeMap<DDValue, isize> {
+    hddlog
+        .db
+        .as_ref()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .try_get_rel(Relations::StronglyConnected as RelId)
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[test]
+fn restart_after_worker_failure() {
+    /// Makes the workers panic when they report changes to output relations.
+    static FAIL_OUTPUTS: AtomicBool = AtomicBool::new(false);
+
+    /// `crate::prog`, with an output callback that panics while `FAIL_OUTPUTS` is set.
+    fn prog(update_cb: Arc<dyn RelationCallback>) -> Program {
+        crate::prog(Arc::new(move |relid: RelId, v: &DDValue, w: Weight| {
+            if FAIL_OUTPUTS.load(Ordering::SeqCst) {
+                panic!("output callback failed");
+            }
+            update_cb(relid, v, w)
+        }))
+    }
+
+    let hddlog = run_program(prog);
+    insert_edges(&hddlog, &[(1, 2), (2, 1)]).unwrap();
+    let outputs = strongly_connected(&hddlog);
+    assert_eq!(outputs.len(), 2);
+
+    // The commit waiting for the workers is woken up by the failure.
+    FAIL_OUTPUTS.store(true, Ordering::SeqCst);
+    let res = insert_edges(&hddlog, &[(2, 3), (3, 2)]);
+    FAIL_OUTPUTS.store(false, Ordering::SeqCst);
+    match res {
+        Err(DDlogError::WorkerFailure {
+            worker: Some(_),
+            message,
+        }) => assert_eq!(message, "panicked: output callback failed"),
+        res => panic!("unexpected commit result: {:?}", res),
+    }
+    let failure = hddlog.worker_failure().unwrap();
+    assert!(failure.panicked);
+    assert_eq!(failure.message, "output callback failed");
+
+    // The restarted dataflow recomputes the outputs of the last commit from the retained
+    // inputs, and the failed transaction is discarded.
+    hddlog.restart().unwrap();
+    assert_eq!(hddlog.worker_failure(), None);
+    assert_eq!(strongly_connected(&hddlog), outputs);
+    assert_eq!(hddlog.count_relation(Relations::Edge as RelId).unwrap(), 2);
+
+    insert_edges(&hddlog, &[(2, 3), (3, 2)]).unwrap();
+    assert_eq!(strongly_connected(&hddlog).len(), 3);
+    hddlog.stop().unwrap();
+}
+
+#[test]
+fn cancel_slow_commit() {
+    /// Makes the workers sleep when they report changes to output relations.
+    static SLOW_OUTPUTS: AtomicBool = AtomicBool::new(false);
+    /// Set by the workers once they sleep.
+    static SLOWED_DOWN: AtomicBool = AtomicBool::new(false);
+
+    /// `crate::prog`, with an output callback that sleeps while `SLOW_OUTPUTS` is set.
+    fn prog(update_cb: Arc<dyn RelationCallback>) -> Program {
+        crate::prog(Arc::new(move |relid: RelId, v: &DDValue, w: Weight| {
+            if SLOW_OUTPUTS.load(Ordering::SeqCst) {
+                SLOWED_DOWN.store(true, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(50));
+            }
+            update_cb(relid, v, w)
+        }))
+    }
+
+    let hddlog = run_program(prog);
+    insert_edges(&hddlog, &[(1, 2), (2, 1)]).unwrap();
+    let outputs = strongly_connected(&hddlog);
+
+    // Cancel the commit once the workers are busy reporting its outputs.
+    SLOW_OUTPUTS.store(true, Ordering::SeqCst);
+    let token = hddlog.cancellation_token();
+    let canceller = thread::spawn(move || {
+        while !SLOWED_DOWN.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        token.cancel();
+    });
+    let res = insert_edges(&hddlog, &[(2, 3), (3, 2), (3, 4), (4, 3)]);
+    canceller.join().unwrap();
+    SLOW_OUTPUTS.store(false, Ordering::SeqCst);
+    SLOWED_DOWN.store(false, Ordering::SeqCst);
+    assert!(matches!(res, Err(DDlogError::Cancelled(_))), "{:?}", res);
+
+    // The transaction is rolled back, and the dataflow, restarted by the commit, has the
+    // outputs of the last commit.
+    assert_eq!(hddlog.worker_failure(), None);
+    assert_eq!(hddlog.count_relation(Relations::Edge as RelId).unwrap(), 2);
+    assert_eq!(strongly_connected(&hddlog), outputs);
+
+    insert_edges(&hddlog, &[(2, 3), (3, 2)]).unwrap();
+    assert_eq!(strongly_connected(&hddlog).len(), 3);
+    hddlog.stop().unwrap();
+}
+
+#[test]
+fn evaluate_hypothetical_leaves_no_trace() {
+    let hddlog = run_program(crate::prog);
+    insert_edges(&hddlog, &[(1, 2), (2, 1)]).unwrap();
+    let outputs = strongly_connected(&hddlog);
+    let snapshot = hddlog.snapshot().unwrap();
+    let subscriber = hddlog
+        .subscribe(
+            &[Relations::StronglyConnected as RelId],
+            |_, _| true,
+            8,
+            SlowConsumerPolicy::Block,
+        )
+        .unwrap();
+
+    // Node 3 joins the component of nodes 1 and 2.
+    let changes = hddlog
+        .evaluate_hypothetical(&mut edges(&[(2, 3), (3, 2)]))
+        .unwrap();
+    let added = changes
+        .try_get_rel(Relations::StronglyConnected as RelId)
+        .unwrap();
+    assert_eq!(added.len(), 1);
+    assert!(added.values().all(|&w| w == 1));
+
+    // Inputs, stored outputs, snapshots and subscribers are as before.
+    assert_eq!(hddlog.count_relation(Relations::Edge as RelId).unwrap(), 2);
+    assert_eq!(strongly_connected(&hddlog), outputs);
+    assert_eq!(
+        hddlog
+            .snapshot()
+            .unwrap()
+            .relation(Relations::StronglyConnected as RelId),
+        snapshot.relation(Relations::StronglyConnected as RelId)
+    );
+    assert!(subscriber.try_recv().is_err());
+
+    // The next commit is seen by all of them.
+    insert_edges(&hddlog, &[(2, 3), (3, 2)]).unwrap();
+    assert_eq!(strongly_connected(&hddlog).len(), 3);
+    let changes = subscriber.try_recv().unwrap();
+    assert_eq!(
+        changes
+            .try_get_rel(Relations::StronglyConnected as RelId)
+            .map(|added| added.len()),
+        Some(1)
+    );
+    hddlog.stop().unwrap();
+}
+
+#[test]
+fn evaluate_hypothetical_restores_inputs_when_undo_fails() {
+    /// Makes the next retraction from an output relation panic.
+    static FAIL_RETRACTION: AtomicBool = AtomicBool::new(false);
+
+    /// `crate::prog`, with an output callback that panics once `FAIL_RETRACTION` is set.
+    fn prog(update_cb: Arc<dyn RelationCallback>) -> Program {
+        crate::prog(Arc::new(move |relid: RelId, v: &DDValue, w: Weight| {
+            if w < 0 && FAIL_RETRACTION.swap(false, Ordering::SeqCst) {
+                panic!("output callback failed");
+            }
+            update_cb(relid, v, w)
+        }))
+    }
+
+    let hddlog = run_program(prog);
+    insert_edges(&hddlog, &[(1, 2), (2, 1)]).unwrap();
+    let outputs = strongly_connected(&hddlog);
+
+    // Only the commit that undoes the updates retracts outputs.
+    FAIL_RETRACTION.store(true, Ordering::SeqCst);
+    match hddlog.evaluate_hypothetical(&mut edges(&[(2, 3), (3, 2)])) {
+        Err(DDlogError::HypotheticalUndo { cause, restored }) => {
+            assert!(
+                matches!(*cause, DDlogError::WorkerFailure { .. }),
+                "{}",
+                cause
+            );
+            assert!(restored);
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+    assert!(!FAIL_RETRACTION.swap(false, Ordering::SeqCst));
+
+    // The restarted dataflow has the inputs and outputs of the last commit.
+    assert_eq!(hddlog.worker_failure(), None);
+    assert_eq!(hddlog.count_relation(Relations::Edge as RelId).unwrap(), 2);
+    assert_eq!(strongly_connected(&hddlog), outputs);
+    hddlog.stop().unwrap();
+}
@@ -1,5 +1,6 @@
 //! nom-based parser for Datalog values.
 
+use crate::query::{CmpOp, Field, FieldPath, Predicate, Query};
 use differential_datalog::record::*;
 use nom::*;
 use num::bigint::*;
@@ -8,6 +9,7 @@ use num::Num;
 use num::ToPrimitive;
 use ordered_float::OrderedFloat;
 use std::borrow::Cow;
+use std::time::Duration;
 
 #[derive(Copy, Debug, PartialEq, Eq, Clone)]
 pub enum ProfileCmd {
@@ -18,20 +20,53 @@ pub enum ProfileCmd {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Command {
     Start,
-    Commit(bool),
+    /// `commit [dump_changes] [timeout <duration>];`, where the duration is, e.g., `500ms`, `5s`
+    /// or `2m`.
+    Commit(bool, Option<Duration>),
     Comment,
     Rollback,
+    /// `savepoint <name>;`
+    Savepoint(String),
+    /// `rollback_to <name>;`
+    RollbackTo(String),
     Timestamp,
     Profile(Option<ProfileCmd>),
     Dump(Option<String>),
     Clear(String),
     Exit,
+    /// `shutdown;`, which stops a server started with `--listen` and otherwise ends the session
+    /// like `exit;`.
+    Shutdown,
     Echo(String),
     LogLevel(i32),
     Sleep(BigInt),
     Update(UpdCmd, bool),
     QueryIndex(String, Record),
+    /// `query_index_range <index> <lo>, <hi>;`, where a bound is `(<key>)` or `_`.
+    QueryIndexRange(String, Option<Record>, Option<Record>),
+    /// `query_index_prefix <index>(<fields>);`
+    QueryIndexPrefix(String, Vec<Record>),
     DumpIndex(String),
+    /// `dump_index <index> limit <n> [after (<key>), (<value>)];`, which dumps the `n` values
+    /// following the given entry.
+    DumpIndexPage(String, usize, Option<(Record, Record)>),
+    /// `query <relation> [where <predicates>] [select <fields>] [limit <n>];`
+    Query(Query),
+    /// `count <relation>;`
+    Count(String),
+    /// `count_index <index>[(<key>)];`
+    CountIndex(String, Option<Record>),
+    /// `load_csv <relation> "<path>" [header];`
+    LoadCsv(String, String, bool),
+    /// `dump_csv <relation> "<path>" [deltas];`
+    DumpCsv(String, String, bool),
+    /// `checkpoint ["<path>"];`, where a checkpoint without a path goes to the write-ahead log.
+    Checkpoint(Option<String>),
+    /// `help [<relation>];`
+    Help(Option<String>),
+    /// `what_if <update>, ...;`, which prints the changes to output relations that the updates
+    /// would make, without applying them.
+    WhatIf(Vec<UpdCmd>),
 }
 
 named!(spaces<&[u8], ()>,
@@ -75,8 +110,9 @@ named!(pub parse_command<&[u8], Command>,
         upd: alt!(do_parse!(apply!(sym,"start")     >> apply!(sym,";") >> (Command::Start))     |
                   do_parse!(apply!(sym,"commit")    >>
                             delta: opt!(apply!(sym, "dump_changes"))   >>
+                            timeout: opt!(do_parse!(apply!(sym,"timeout") >> d: duration >> (d))) >>
                             apply!(sym,";")         >>
-                            (Command::Commit(delta.is_some())))                                 |
+                            (Command::Commit(delta.is_some(), timeout)))                        |
                   do_parse!(apply!(sym,"timestamp") >> apply!(sym,";") >> (Command::Timestamp)) |
                   do_parse!(apply!(sym,"#")         >>
                             take_until!("\n")       >>
@@ -103,6 +139,11 @@ named!(pub parse_command<&[u8], Command>,
                             apply!(sym,";")         >>
                             (Command::Sleep(ms)))                                               |
                   do_parse!(apply!(sym,"exit")      >> apply!(sym,";") >> (Command::Exit))      |
+                  do_parse!(apply!(sym,"shutdown")  >> apply!(sym,";") >> (Command::Shutdown))  |
+                  do_parse!(apply!(sym,"help")      >>
+                            rel: opt!(identifier)   >>
+                            apply!(sym,";")         >>
+                            (Command::Help(rel)))                                               |
                   do_parse!(apply!(sym,"echo")      >>
                             txt: take_until!(";")   >>
                             apply!(sym,";")         >>
@@ -111,7 +152,30 @@ named!(pub parse_command<&[u8], Command>,
                             level: bigint_val       >>
                             apply!(sym,";")         >>
                             (Command::LogLevel(level.to_i32().unwrap())))                       |
+                  do_parse!(apply!(sym,"savepoint") >>
+                            name: identifier        >>
+                            apply!(sym,";")         >>
+                            (Command::Savepoint(name)))                                         |
+                  do_parse!(apply!(sym,"rollback_to") >>
+                            name: identifier          >>
+                            apply!(sym,";")           >>
+                            (Command::RollbackTo(name)))                                        |
                   do_parse!(apply!(sym,"rollback") >> apply!(sym,";") >> (Command::Rollback))   |
+                  do_parse!(apply!(sym,"query_index_range")                   >>
+                            idx: identifier                                   >>
+                            lo: index_bound                                   >>
+                            apply!(sym,",")                                   >>
+                            hi: index_bound                                   >>
+                            apply!(sym,";")                                   >>
+                            (Command::QueryIndexRange(idx, lo, hi)))                            |
+                  do_parse!(apply!(sym,"query_index_prefix")                  >>
+                            idx: identifier                                   >>
+                            fields: delimited!(
+                                apply!(sym,"("),
+                                separated_list!(apply!(sym,","), record),
+                                apply!(sym,")"))                              >>
+                            apply!(sym,";")                                   >>
+                            (Command::QueryIndexPrefix(idx, fields)))                           |
                   do_parse!(apply!(sym,"query_index")                         >>
                             idx: identifier                                   >>
                             args: delimited!(
@@ -125,10 +189,55 @@ named!(pub parse_command<&[u8], Command>,
                                                  } else {
                                                      Record::Tuple(args)
                                                  } )))                                          |
+                  do_parse!(apply!(sym,"dump_index")                          >>
+                            idx: identifier                                   >>
+                            apply!(sym,"limit")                               >>
+                            limit: dec_val                                    >>
+                            after: opt!(do_parse!(
+                                apply!(sym,"after")                           >>
+                                key: parenthesized_record                     >>
+                                apply!(sym,",")                               >>
+                                val: parenthesized_record                     >>
+                                ((key, val))))                                >>
+                            apply!(sym,";")                                   >>
+                            (Command::DumpIndexPage(idx, limit.to_usize().unwrap(), after)))    |
                   do_parse!(apply!(sym,"dump_index")                          >>
                             idx: identifier                                   >>
                             apply!(sym,";")                                   >>
                             (Command::DumpIndex(idx)))                                          |
+                  do_parse!(apply!(sym,"query")                               >>
+                            query: query                                      >>
+                            apply!(sym,";")                                   >>
+                            (Command::Query(query)))                                            |
+                  do_parse!(apply!(sym,"count_index")                         >>
+                            idx: identifier                                   >>
+                            key: opt!(parenthesized_record)                   >>
+                            apply!(sym,";")                                   >>
+                            (Command::CountIndex(idx, key)))                                    |
+                  do_parse!(apply!(sym,"count")                               >>
+                            rel: identifier                                   >>
+                            apply!(sym,";")                                   >>
+                            (Command::Count(rel)))                                              |
+                  do_parse!(apply!(sym,"load_csv")                            >>
+                            rel: identifier                                   >>
+                            path: string_literal                              >>
+                            header: opt!(apply!(sym,"header"))                >>
+                            apply!(sym,";")                                   >>
+                            (Command::LoadCsv(rel, path, header.is_some())))                    |
+                  do_parse!(apply!(sym,"dump_csv")                            >>
+                            rel: identifier                                   >>
+                            path: string_literal                              >>
+                            deltas: opt!(apply!(sym,"deltas"))                >>
+                            apply!(sym,";")                                   >>
+                            (Command::DumpCsv(rel, path, deltas.is_some())))                    |
+                  do_parse!(apply!(sym,"checkpoint")                          >>
+                            path: opt!(string_literal)                        >>
+                            apply!(sym,";")                                   >>
+                            (Command::Checkpoint(path)))                                        |
+                  do_parse!(apply!(sym,"what_if")                             >>
+                            upds: separated_list!(apply!(sym,","), update)    >>
+                            apply!(sym,";")                                   >>
+                            (Command::WhatIf(upds)))                                            |
                   do_parse!(upd:  update >>
                             last: alt!(map!(apply!(sym,";"), |_|true) | map!(apply!(sym, ","), |_|false)) >>
                             (Command::Update(upd, last)))) >>
@@ -136,6 +245,102 @@ named!(pub parse_command<&[u8], Command>,
     )
 );
 
+/// Parse all commands in `text`, which must not end in the middle of a command.
+pub fn parse_commands(text: &str) -> Result<Vec<Command>, String> {
+    let mut commands = Vec::new();
+    let mut rest = text.as_bytes();
+    while !rest.iter().all(u8::is_ascii_whitespace) {
+        match parse_command(rest) {
+            Ok((next, cmd)) => {
+                commands.push(cmd);
+                rest = next;
+            }
+            Err(Err::Incomplete(_)) => return Err("incomplete command at end of input".to_string()),
+            Err(e) => return Err(format!("Invalid input: {}", crate::err_str(&e))),
+        }
+    }
+
+    Ok(commands)
+}
+
+/// Incremental parser for commands arriving in pieces, e.g., lines read from a socket.
+#[derive(Debug, Default)]
+pub struct CommandParser {
+    buf: Vec<u8>,
+}
+
+impl CommandParser {
+    /// Add `input` to the buffered text and return the commands completed by it.  Invalid input
+    /// is returned as an error after the commands that precede it and discarded together with
+    /// the rest of the buffered text.
+    pub fn push(&mut self, input: &str) -> Vec<Result<Command, String>> {
+        self.buf.extend_from_slice(input.as_bytes());
+
+        let mut commands = Vec::new();
+        while !self.buf.iter().all(u8::is_ascii_whitespace) {
+            match parse_command(&self.buf) {
+                Ok((rest, cmd)) => {
+                    commands.push(Ok(cmd));
+                    self.buf = rest.to_vec();
+                }
+                Err(Err::Incomplete(_)) => return commands,
+                Err(e) => {
+                    commands.push(Err(format!("Invalid input: {}", crate::err_str(&e))));
+                    self.buf.clear();
+                }
+            }
+        }
+
+        self.buf.clear();
+        commands
+    }
+}
+
+#[test]
+fn test_command_parser() {
+    let mut parser = CommandParser::default();
+    assert_eq!(
+        parser.push("start;\ninsert Rel1[true],\n"),
+        vec![
+            Ok(Command::Start),
+            Ok(Command::Update(
+                UpdCmd::Insert(
+                    RelIdentifier::RelName(Cow::from("Rel1")),
+                    Record::Bool(true)
+                ),
+                false
+            )),
+        ]
+    );
+    assert_eq!(parser.push("com"), vec![]);
+    assert_eq!(
+        parser.push("mit;\n"),
+        vec![Ok(Command::Commit(false, None))]
+    );
+    assert!(parser.push("commit!\n")[0].is_err());
+    assert_eq!(parser.push("exit;\n"), vec![Ok(Command::Exit)]);
+}
+
+#[test]
+fn test_commands() {
+    assert_eq!(
+        parse_commands("start;\ninsert Rel1[true],\nclear Rel2;\ncommit;\n"),
+        Ok(vec![
+            Command::Start,
+            Command::Update(
+                UpdCmd::Insert(
+                    RelIdentifier::RelName(Cow::from("Rel1")),
+                    Record::Bool(true)
+                ),
+                false
+            ),
+            Command::Clear("Rel2".to_string()),
+            Command::Commit(false, None),
+        ])
+    );
+    assert!(parse_commands("start;\ninsert Rel1[tr").is_err());
+}
+
 #[test]
 fn test_command() {
     assert_eq!(parse_command(br"start;"), Ok((&br""[..], Command::Start)));
@@ -145,8 +350,24 @@ fn test_command() {
     );
     assert_eq!(
         parse_command(br"commit;"),
-        Ok((&br""[..], Command::Commit(false)))
+        Ok((&br""[..], Command::Commit(false, None)))
     );
+    assert_eq!(
+        parse_command(br"commit timeout 5s;"),
+        Ok((
+            &br""[..],
+            Command::Commit(false, Some(Duration::from_secs(5)))
+        ))
+    );
+    assert_eq!(
+        parse_command(br"commit dump_changes timeout 250ms;"),
+        Ok((
+            &br""[..],
+            Command::Commit(true, Some(Duration::from_millis(250)))
+        ))
+    );
+    assert!(parse_command(br"commit timeout 5;").is_err());
+    assert!(parse_command(br"commit timeout 5h;").is_err());
     assert_eq!(
         parse_command(br"timestamp;"),
         Ok((&br""[..], Command::Timestamp))
@@ -176,6 +397,18 @@ fn test_command() {
         Ok((&br""[..], Command::Clear("Tab".to_string())))
     );
     assert_eq!(parse_command(br"exit;"), Ok((&br""[..], Command::Exit)));
+    assert_eq!(
+        parse_command(br"shutdown;"),
+        Ok((&br""[..], Command::Shutdown))
+    );
+    assert_eq!(
+        parse_command(br"help;"),
+        Ok((&br""[..], Command::Help(None)))
+    );
+    assert_eq!(
+        parse_command(br"help Edge;"),
+        Ok((&br""[..], Command::Help(Some("Edge".to_string()))))
+    );
     assert_eq!(
         parse_command(br"echo test;"),
         Ok((&br""[..], Command::Echo("test".to_string())))
@@ -196,6 +429,194 @@ fn test_command() {
         parse_command(br"rollback;"),
         Ok((&br""[..], Command::Rollback))
     );
+    assert_eq!(
+        parse_command(br"what_if;"),
+        Ok((&br""[..], Command::WhatIf(vec![])))
+    );
+    assert_eq!(
+        parse_command(br"what_if insert Rel1[true], delete Rel1[false];"),
+        Ok((
+            &br""[..],
+            Command::WhatIf(vec![
+                UpdCmd::Insert(
+                    RelIdentifier::RelName(Cow::from("Rel1")),
+                    Record::Bool(true)
+                ),
+                UpdCmd::Delete(
+                    RelIdentifier::RelName(Cow::from("Rel1")),
+                    Record::Bool(false)
+                ),
+            ])
+        ))
+    );
+    assert_eq!(
+        parse_command(br"savepoint before_edges;"),
+        Ok((&br""[..], Command::Savepoint("before_edges".to_string())))
+    );
+    assert_eq!(
+        parse_command(br"rollback_to before_edges;"),
+        Ok((&br""[..], Command::RollbackTo("before_edges".to_string())))
+    );
+    assert_eq!(
+        parse_command(br#"load_csv Edge "edges.csv";"#),
+        Ok((
+            &br""[..],
+            Command::LoadCsv("Edge".to_string(), "edges.csv".to_string(), false)
+        ))
+    );
+    assert_eq!(
+        parse_command(br#"load_csv Edge "data/edges.csv" header;"#),
+        Ok((
+            &br""[..],
+            Command::LoadCsv("Edge".to_string(), "data/edges.csv".to_string(), true)
+        ))
+    );
+    assert_eq!(
+        parse_command(br#"checkpoint "scc.ckpt";"#),
+        Ok((&br""[..], Command::Checkpoint(Some("scc.ckpt".to_string()))))
+    );
+    assert_eq!(
+        parse_command(br"checkpoint;"),
+        Ok((&br""[..], Command::Checkpoint(None)))
+    );
+    assert_eq!(
+        parse_command(br"query_index_range EdgesBySrc (1, 2), _;"),
+        Ok((
+            &br""[..],
+            Command::QueryIndexRange(
+                "EdgesBySrc".to_string(),
+                Some(Record::Tuple(vec![
+                    Record::Int(1.into()),
+                    Record::Int(2.into())
+                ])),
+                None
+            )
+        ))
+    );
+    assert_eq!(
+        parse_command(br"query_index_range EdgesBySrc _, (5);"),
+        Ok((
+            &br""[..],
+            Command::QueryIndexRange("EdgesBySrc".to_string(), None, Some(Record::Int(5.into())))
+        ))
+    );
+    assert_eq!(
+        parse_command(br"query_index_prefix EdgesBySrc(1);"),
+        Ok((
+            &br""[..],
+            Command::QueryIndexPrefix("EdgesBySrc".to_string(), vec![Record::Int(1.into())])
+        ))
+    );
+    assert_eq!(
+        parse_command(br#"query Connected where src >= 1 and 1 != 8 and e.name == "x" select dest, 0 limit 2;"#),
+        Ok((
+            &br""[..],
+            Command::Query(Query {
+                relation: "Connected".to_string(),
+                predicates: vec![
+                    Predicate {
+                        field: FieldPath(vec![Field::Name("src".to_string())]),
+                        op: CmpOp::Ge,
+                        value: Record::Int(1.into())
+                    },
+                    Predicate {
+                        field: FieldPath(vec![Field::Pos(1)]),
+                        op: CmpOp::Ne,
+                        value: Record::Int(8.into())
+                    },
+                    Predicate {
+                        field: FieldPath(vec![
+                            Field::Name("e".to_string()),
+                            Field::Name("name".to_string())
+                        ]),
+                        op: CmpOp::Eq,
+                        value: Record::String("x".to_string())
+                    }
+                ],
+                projection: vec![
+                    FieldPath(vec![Field::Name("dest".to_string())]),
+                    FieldPath(vec![Field::Pos(0)])
+                ],
+                limit: Some(2)
+            })
+        ))
+    );
+    assert_eq!(
+        parse_command(br"query StronglyConnected;"),
+        Ok((
+            &br""[..],
+            Command::Query(Query {
+                relation: "StronglyConnected".to_string(),
+                predicates: Vec::new(),
+                projection: Vec::new(),
+                limit: None
+            })
+        ))
+    );
+    assert_eq!(
+        parse_command(br"count StronglyConnected;"),
+        Ok((&br""[..], Command::Count("StronglyConnected".to_string())))
+    );
+    assert_eq!(
+        parse_command(br"count_index EdgesBySrc;"),
+        Ok((
+            &br""[..],
+            Command::CountIndex("EdgesBySrc".to_string(), None)
+        ))
+    );
+    assert_eq!(
+        parse_command(br"count_index EdgesBySrc(1);"),
+        Ok((
+            &br""[..],
+            Command::CountIndex("EdgesBySrc".to_string(), Some(Record::Int(1.into())))
+        ))
+    );
+    assert_eq!(
+        parse_command(br"dump_index EdgesBySrc limit 100;"),
+        Ok((
+            &br""[..],
+            Command::DumpIndexPage("EdgesBySrc".to_string(), 100, None)
+        ))
+    );
+    assert_eq!(
+        parse_command(br"dump_index EdgesBySrc limit 10 after (1), (Edge{1, 2});"),
+        Ok((
+            &br""[..],
+            Command::DumpIndexPage(
+                "EdgesBySrc".to_string(),
+                10,
+                Some((
+                    Record::Int(1.into()),
+                    Record::PosStruct(
+                        Cow::from("Edge"),
+                        vec![Record::Int(1.into()), Record::Int(2.into())]
+                    )
+                ))
+            )
+        ))
+    );
+    assert_eq!(
+        parse_command(br#"dump_csv StronglyConnected "scc.csv";"#),
+        Ok((
+            &br""[..],
+            Command::DumpCsv(
+                "StronglyConnected".to_string(),
+                "scc.csv".to_string(),
+                false
+            )
+        ))
+    );
+    assert_eq!(
+        parse_command(br#"dump_csv StronglyConnected "scc_deltas.csv" deltas;"#),
+        Ok((
+            &br""[..],
+            Command::DumpCsv(
+                "StronglyConnected".to_string(),
+                "scc_deltas.csv".to_string(),
+                true
+            )
+        ))
+    );
     assert_eq!(
         parse_command(br"insert Rel1(true);"),
         Ok((
@@ -326,6 +747,65 @@ named!(rel_key<&[u8], (Name, Record)>,
               (Cow::from(rel), val))
 );
 
+named!(query<&[u8], Query>,
+    do_parse!(relation: identifier                                            >>
+              predicates: opt!(do_parse!(
+                  apply!(sym,"where")                                         >>
+                  predicates: separated_nonempty_list!(apply!(sym,"and"), predicate) >>
+                  (predicates)))                                              >>
+              projection: opt!(do_parse!(
+                  apply!(sym,"select")                                        >>
+                  fields: separated_nonempty_list!(apply!(sym,","), field_path) >>
+                  (fields)))                                                  >>
+              limit: opt!(do_parse!(apply!(sym,"limit") >> limit: dec_val >> (limit))) >>
+              (Query {
+                  relation,
+                  predicates: predicates.unwrap_or_default(),
+                  projection: projection.unwrap_or_default(),
+                  limit: limit.map(|limit| limit.to_usize().unwrap()),
+              }))
+);
+
+named!(predicate<&[u8], Predicate>,
+    do_parse!(field: field_path >>
+              op: cmp_op        >>
+              value: record     >>
+              (Predicate { field, op, value }))
+);
+
+named!(cmp_op<&[u8], CmpOp>,
+    alt!(map!(apply!(sym,"=="), |_| CmpOp::Eq) |
+         map!(apply!(sym,"!="), |_| CmpOp::Ne) |
+         map!(apply!(sym,"<="), |_| CmpOp::Le) |
+         map!(apply!(sym,">="), |_| CmpOp::Ge) |
+         map!(apply!(sym,"<"), |_| CmpOp::Lt)  |
+         map!(apply!(sym,">"), |_| CmpOp::Gt))
+);
+
+named!(field_path<&[u8], FieldPath>,
+    map!(separated_nonempty_list!(apply!(sym,"."),
+                                  alt!(map!(dec_val, |pos| Field::Pos(pos.to_usize().unwrap())) |
+                                       map!(identifier, Field::Name))),
+         FieldPath)
+);
+
+named!(index_bound<&[u8], Option<Record>>,
+    alt!(map!(apply!(sym,"_"), |_| None) |
+         map!(parenthesized_record, Some))
+);
+
+// A record in parentheses, where several comma-separated records form a tuple.
+named!(parenthesized_record<&[u8], Record>,
+    map!(delimited!(apply!(sym,"("),
+                    separated_list!(apply!(sym,","), record),
+                    apply!(sym,")")),
+         |args| if args.len() == 1 {
+             args[0].clone()
+         } else {
+             Record::Tuple(args)
+         })
+);
+
 named!(record<&[u8], Record>,
     alt!(bool_val | string_val | serialized_val | tuple_val | array_val | struct_val | float_val | int_val )
 );
@@ -662,6 +1142,28 @@ named!(hex_val<&[u8], BigInt>,
                 BigInt::parse_bytes(bs.as_slice(), 16).unwrap()}))
 );
 
+/// Parse a duration such as `500ms`, `5s` or `2m`.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let n: u64 = s[..digits].parse().ok()?;
+    match &s[digits..] {
+        "ms" => Some(Duration::from_millis(n)),
+        "s" => Some(Duration::from_secs(n)),
+        "m" => Some(Duration::from_secs(n.checked_mul(60)?)),
+        _ => None,
+    }
+}
+
+fn duration_from_bytes(bs: &[u8]) -> Option<Duration> {
+    std::str::from_utf8(bs).ok().and_then(parse_duration)
+}
+
+named!(duration<&[u8], Duration>,
+    do_parse!(d: map_opt!(take_while1!(is_alphanumeric), duration_from_bytes) >>
+              spaces >>
+              (d))
+);
+
 named!(dec_val<&[u8], BigInt>,
     do_parse!(bs1: take_while1!(|x| is_digit(x)) >>
               bs2: take_while!(|x| is_digit(x) || x == b'_') >>
@@ -0,0 +1,292 @@
+//! Ad-hoc queries over the contents of relations, e.g.,
+//! `query Connected where src < 10 select dest limit 5;`.
+//!
+//! A query filters the records of a relation by predicates on their fields, optionally projects
+//! them onto some of their fields, and returns at most `limit` of them.  Fields are selected by
+//! name in structs with named fields, and by position, starting at `0`, in tuples and structs.
+//! Paths such as `edge.src` select fields of nested records.
+
+use differential_datalog::record::Record;
+use num::ToPrimitive;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Field of a record selected by name or by position.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Field {
+    Name(String),
+    Pos(usize),
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Field::Name(name) => write!(f, "{}", name),
+            Field::Pos(pos) => write!(f, "{}", pos),
+        }
+    }
+}
+
+/// Path to a field of nested records, e.g., `edge.src`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FieldPath(pub Vec<Field>);
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<String> = self.0.iter().map(|field| field.to_string()).collect();
+        write!(f, "{}", fields.join("."))
+    }
+}
+
+impl FieldPath {
+    /// The field of `record` at this path, or `None` if it doesn't have one.
+    pub fn get<'a>(&self, record: &'a Record) -> Option<&'a Record> {
+        self.0
+            .iter()
+            .try_fold(record, |record, field| match (record, field) {
+                (Record::NamedStruct(_, fields), Field::Name(name)) => fields
+                    .iter()
+                    .find(|(fname, _)| fname == name)
+                    .map(|(_, value)| value),
+                (Record::NamedStruct(_, fields), Field::Pos(pos)) => {
+                    fields.get(*pos).map(|(_, value)| value)
+                }
+                (Record::Tuple(fields), Field::Pos(pos))
+                | (Record::PosStruct(_, fields), Field::Pos(pos)) => fields.get(*pos),
+                _ => None,
+            })
+    }
+}
+
+#[derive(Copy, Debug, PartialEq, Eq, Clone)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            CmpOp::Eq => ordering == Ordering::Equal,
+            CmpOp::Ne => ordering != Ordering::Equal,
+            CmpOp::Lt => ordering == Ordering::Less,
+            CmpOp::Le => ordering != Ordering::Greater,
+            CmpOp::Gt => ordering == Ordering::Greater,
+            CmpOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// `<field> <op> <value>`, e.g., `regime == 5`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Predicate {
+    pub field: FieldPath,
+    pub op: CmpOp,
+    pub value: Record,
+}
+
+impl Predicate {
+    /// Whether `record` satisfies the predicate.  Records without the field don't.
+    pub fn matches(&self, record: &Record) -> Result<bool, String> {
+        match self.field.get(record) {
+            Some(field) => compare(field, &self.value)
+                .map(|ordering| self.op.holds(ordering))
+                .ok_or_else(|| {
+                    format!(
+                        "cannot compare field '{}' = {} with {}",
+                        self.field, field, self.value
+                    )
+                }),
+            None => Ok(false),
+        }
+    }
+}
+
+/// `query <relation> [where <predicate> [and <predicate>]...] [select <field>, ...] [limit <n>];`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Query {
+    pub relation: String,
+    pub predicates: Vec<Predicate>,
+    /// Fields to return instead of the whole record.
+    pub projection: Vec<FieldPath>,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// Whether `record` satisfies all predicates.
+    pub fn matches(&self, record: &Record) -> Result<bool, String> {
+        for predicate in self.predicates.iter() {
+            if !predicate.matches(record)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The selected field of `record`, a tuple of the selected fields if there are several,
+    /// or `record` itself if the query doesn't select any.
+    pub fn project(&self, record: Record) -> Result<Record, String> {
+        let mut fields = self
+            .projection
+            .iter()
+            .map(|path| {
+                path.get(&record)
+                    .cloned()
+                    .ok_or_else(|| format!("record {} has no field '{}'", record, path))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(match fields.len() {
+            0 => record,
+            1 => fields.remove(0),
+            _ => Record::Tuple(fields),
+        })
+    }
+
+    /// Evaluate the query against the records of its relation.
+    pub fn evaluate<I>(&self, records: I) -> Result<Vec<Record>, String>
+    where
+        I: IntoIterator<Item = Record>,
+    {
+        let mut results = Vec::new();
+        for record in records {
+            if self.limit.is_some_and(|limit| results.len() >= limit) {
+                break;
+            }
+            if self.matches(&record)? {
+                results.push(self.project(record)?);
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// Compare records of the same type, where integers and floating point numbers are compared by
+/// value, and structs given with positional fields can be compared with the same structs with
+/// named fields.  Returns `None` if the records can't be compared.
+fn compare(x: &Record, y: &Record) -> Option<Ordering> {
+    match (x, y) {
+        (Record::Bool(x), Record::Bool(y)) => Some(x.cmp(y)),
+        (Record::Int(x), Record::Int(y)) => Some(x.cmp(y)),
+        (Record::String(x), Record::String(y)) => Some(x.cmp(y)),
+        (
+            Record::Int(_) | Record::Float(_) | Record::Double(_),
+            Record::Int(_) | Record::Float(_) | Record::Double(_),
+        ) => as_f64(x)?.partial_cmp(&as_f64(y)?),
+        (Record::Tuple(xs), Record::Tuple(ys)) => compare_fields(xs.iter(), ys.iter()),
+        (Record::Array(_, xs), Record::Array(_, ys)) => compare_fields(xs.iter(), ys.iter()),
+        (
+            Record::PosStruct(xname, _) | Record::NamedStruct(xname, _),
+            Record::PosStruct(yname, _) | Record::NamedStruct(yname, _),
+        ) => {
+            if xname != yname {
+                return None;
+            }
+            compare_fields(struct_fields(x), struct_fields(y))
+        }
+        _ => None,
+    }
+}
+
+fn compare_fields<'a, X, Y>(xs: X, ys: Y) -> Option<Ordering>
+where
+    X: ExactSizeIterator<Item = &'a Record>,
+    Y: ExactSizeIterator<Item = &'a Record>,
+{
+    let lengths = xs.len().cmp(&ys.len());
+    for (x, y) in xs.zip(ys) {
+        match compare(x, y)? {
+            Ordering::Equal => (),
+            ordering => return Some(ordering),
+        }
+    }
+    Some(lengths)
+}
+
+fn struct_fields(record: &Record) -> std::vec::IntoIter<&Record> {
+    let fields: Vec<&Record> = match record {
+        Record::PosStruct(_, fields) => fields.iter().collect(),
+        Record::NamedStruct(_, fields) => fields.iter().map(|(_, field)| field).collect(),
+        _ => Vec::new(),
+    };
+    fields.into_iter()
+}
+
+fn as_f64(record: &Record) -> Option<f64> {
+    match record {
+        Record::Int(i) => i.to_f64(),
+        Record::Float(f) => Some(f64::from(f.into_inner())),
+        Record::Double(d) => Some(d.into_inner()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+fn connected(src: i64, dest: i64) -> Record {
+    use std::borrow::Cow;
+
+    Record::NamedStruct(
+        Cow::from("Connected"),
+        vec![
+            (Cow::from("src"), Record::Int(src.into())),
+            (Cow::from("dest"), Record::Int(dest.into())),
+        ],
+    )
+}
+
+#[test]
+fn test_query() {
+    let records = || (0..5).map(|i| connected(i, 10 - i));
+    let predicate = |field: Field, op, value: i64| Predicate {
+        field: FieldPath(vec![field]),
+        op,
+        value: Record::Int(value.into()),
+    };
+
+    let query = Query {
+        relation: "Connected".to_string(),
+        predicates: vec![
+            predicate(Field::Name("src".to_string()), CmpOp::Ge, 1),
+            predicate(Field::Pos(1), CmpOp::Ne, 8),
+        ],
+        projection: vec![FieldPath(vec![Field::Name("dest".to_string())])],
+        limit: Some(2),
+    };
+    assert_eq!(
+        query.evaluate(records()),
+        Ok(vec![Record::Int(9.into()), Record::Int(7.into())])
+    );
+
+    // Without projection and limit, all matching records are returned whole.
+    let query = Query {
+        projection: Vec::new(),
+        limit: None,
+        ..query
+    };
+    assert_eq!(
+        query.evaluate(records()),
+        Ok(vec![connected(1, 9), connected(3, 7), connected(4, 6)])
+    );
+
+    // Records without the field don't match, but fields of the wrong type are an error.
+    let query = Query {
+        relation: "Connected".to_string(),
+        predicates: vec![predicate(Field::Name("regime".to_string()), CmpOp::Eq, 5)],
+        projection: Vec::new(),
+        limit: None,
+    };
+    assert_eq!(query.evaluate(records()), Ok(Vec::new()));
+    let query = Query {
+        predicates: vec![Predicate {
+            field: FieldPath(vec![Field::Name("src".to_string())]),
+            op: CmpOp::Lt,
+            value: Record::String("x".to_string()),
+        }],
+        ..query
+    };
+    assert!(query.evaluate(records()).is_err());
+}
@@ -113,6 +113,41 @@ typedef struct {
     ssize_t weight;
 } ddlog_record_update;
 
+/*
+ * Error codes returned by API functions that return `int`.  `0` means
+ * success, and a negative value identifies the kind of error.  Invalid
+ * arguments, e.g., a NULL program handle, are reported as
+ * `DDLOG_ERR_OTHER`.
+ */
+typedef enum {
+    // An error not covered by the other codes.
+    DDLOG_ERR_OTHER                   = -1,
+    // The relation doesn't exist or doesn't support the operation, e.g.,
+    // updating a relation that is not an input relation.
+    DDLOG_ERR_UNKNOWN_RELATION        = -2,
+    // The index doesn't exist.
+    DDLOG_ERR_UNKNOWN_INDEX           = -3,
+    // The function requires a transaction, but none is in progress.
+    DDLOG_ERR_NO_TRANSACTION          = -4,
+    // The function can't be called while a transaction is in progress.
+    DDLOG_ERR_TRANSACTION_IN_PROGRESS = -5,
+    // A worker thread failed.
+    DDLOG_ERR_WORKER_FAILURE          = -6,
+    // A record doesn't have the type expected by the relation or index.
+    DDLOG_ERR_TYPE_MISMATCH           = -7,
+    // An I/O operation failed.
+    DDLOG_ERR_IO                      = -8,
+    // A commit didn't complete before its deadline and was rolled back.
+    DDLOG_ERR_TIMEOUT                 = -9,
+    // A commit was cancelled with `ddlog_cancel_commit()` and rolled back.
+    DDLOG_ERR_CANCELLED               = -10,
+    // The transaction has no savepoint with the name.
+    DDLOG_ERR_UNKNOWN_SAVEPOINT       = -11,
+    // A hypothetical evaluation failed to undo its updates after committing
+    // them.
+    DDLOG_ERR_HYPOTHETICAL_UNDO       = -12
+} ddlog_error_code;
+
 /* DDlog profiling modes. */
 typedef enum {
     // Profiling disabled.
@@ -338,7 +373,8 @@ extern int ddlog_dump_input_snapshot(ddlog_prog hprog, int fd);
  * All concurrent calls using the handle must complete before calling this
  * function.
  *
- * On success, returns `0`; on error, returns `-1` and prints error message
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * and prints error message
  * (see `print_err_msg` parameter to `ddlog_run()`).
  *
  * IMPORTANT: this function is _not_ thread-safe and must not be invoked
@@ -349,7 +385,8 @@ extern int ddlog_stop(ddlog_prog hprog);
 /*
  * Start a transaction.
  *
- * On success, returns `0`; on error, returns `-1` and prints error message
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * and prints error message
  * (see `print_err_msg` parameter to `ddlog_run()`).
  *
  * This function will fail if another transaction is in progress.
@@ -363,13 +400,42 @@ extern int ddlog_transaction_start(ddlog_prog hprog);
  * Commit a transaction; propagate all buffered changes through all
  * rules in the program and update all output relations.
  *
- * On success, returns `0`; on error, returns `-1` and prints error message
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * and prints error message
  * (see `print_err_msg` parameter to `ddlog_run()`).
  *
  * This function will fail if there is no transaction in progress.
  */
 extern int ddlog_transaction_commit(ddlog_prog hprog);
 
+/*
+ * Like `ddlog_transaction_commit()`, but aborts the commit if it doesn't
+ * complete within `timeout_ms` milliseconds or is cancelled with
+ * `ddlog_cancel_commit()`.  An aborted commit rolls back the transaction,
+ * restarts the program from the contents of input relations as of the last
+ * commit (see `ddlog_restart()`), and returns `DDLOG_ERR_TIMEOUT` or
+ * `DDLOG_ERR_CANCELLED`.
+ *
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * and prints error message
+ * (see `print_err_msg` parameter to `ddlog_run()`).
+ */
+extern int ddlog_transaction_commit_with_deadline(ddlog_prog hprog,
+                                                  uint64_t timeout_ms);
+
+/*
+ * Cancel the commit of the transaction in progress.  If the transaction is
+ * not being committed yet, its commit is cancelled as soon as it starts.
+ * Has no effect if no transaction is in progress.
+ * Unlike other functions, it can be called from any thread while another
+ * thread is blocked in `ddlog_transaction_commit()` or
+ * `ddlog_transaction_commit_with_deadline()`, which then fails with
+ * `DDLOG_ERR_CANCELLED`.
+ *
+ * Returns `0`, or `-1` if `hprog` is `NULL`.
+ */
+extern int ddlog_cancel_commit(ddlog_prog hprog);
+
 /*
  * Commit a transaction; propagate all buffered changes through all
  * rules in the program and update all output relations and returns
@@ -392,7 +458,8 @@ ddlog_transaction_commit_dump_changes(
  * deallocated (along with all it contents) using the
  * `ddlog_free_record_updates()` function.
  *
- * On success, returns `0`; on error, returns `-1` and prints error message
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * and prints error message
  * (see `print_err_msg` parameter to `ddlog_run()`).
  *
  * This function will fail if there is no transaction in progress.
@@ -445,13 +512,53 @@ ddlog_flatbuf_free(
 /*
  * Discard all buffered updates and abort the current transaction.
  *
- * On success, returns `0`; on error, returns `-1` and prints error message
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * and prints error message
  * (see `print_err_msg` parameter to `ddlog_run()`).
  *
  * This function will fail if there is no transaction in progress.
  */
 extern int ddlog_transaction_rollback(ddlog_prog hprog);
 
+/*
+ * Create a savepoint named `name` in the current transaction, which
+ * `ddlog_rollback_to()` can later return to.  If several savepoints have the
+ * same name, the latest one is used.
+ *
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * and prints error message
+ * (see `print_err_msg` parameter to `ddlog_run()`).
+ *
+ * This function will fail if there is no transaction in progress.
+ */
+extern int ddlog_savepoint(ddlog_prog hprog, const char *name);
+
+/*
+ * Undo the updates applied in the current transaction since savepoint `name`
+ * was created, and discard the savepoints created after it.  The savepoint
+ * itself is kept, so the transaction can return to it again.
+ *
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * (`DDLOG_ERR_UNKNOWN_SAVEPOINT` if there is no such savepoint) and prints
+ * error message (see `print_err_msg` parameter to `ddlog_run()`).
+ */
+extern int ddlog_rollback_to(ddlog_prog hprog, const char *name);
+
+/*
+ * Restart the program after a worker thread failed.  Once a worker fails,
+ * all other functions that use the workers fail with
+ * `DDLOG_ERR_WORKER_FAILURE`.  The restarted program contains the contents
+ * of input relations as of the last commit; the transaction in progress, if
+ * any, is discarded.
+ *
+ * On success, returns `0`; on error, returns a negative `ddlog_error_code`
+ * and prints error message
+ * (see `print_err_msg` parameter to `ddlog_run()`).
+ *
+ * This function will fail if no worker has failed.
+ */
+extern int ddlog_restart(ddlog_prog hprog);
+
 /*
  * Apply updates to DDlog tables.  See the ddlog_cmd API below.
  *
@@ -507,6 +614,51 @@ ddlog_query_index(ddlog_prog prog,
                   void (*cb)(uintptr_t arg, const ddlog_record *rec),
                   uintptr_t cb_arg);
 
+/*
+ * Query index by key range.
+ *
+ * `idxid` - id of the index to query.
+ * `lo`, `hi` - bounds of the range of keys `[lo, hi)`.  A `NULL` bound
+ *     leaves the range unbounded on that side.
+ *     NOTE: the caller keeps ownership of the bounds after the call and
+ *     must deallocate them using `ddlog_free()`.
+ * `cb` - callback invoked for each value associated with a key in the range.
+ * `cb_arg` - opaque handle passed to each `cb invocation`.
+ *
+ * On success, returns `0`. On error, returns a negative value and
+ * writes error message (see `print_err_msg` parameter to `ddlog_run()`).
+ */
+extern int
+ddlog_query_index_range(ddlog_prog prog,
+                        index_id idxid,
+                        const ddlog_record *lo,
+                        const ddlog_record *hi,
+                        void (*cb)(uintptr_t arg, const ddlog_record *rec),
+                        uintptr_t cb_arg);
+
+/*
+ * Query index by key prefix, i.e., for tuple or struct keys whose leading
+ * fields are equal to `fields`.
+ *
+ * `idxid` - id of the index to query.
+ * `fields` - array of `len` leading fields of the keys.  If `len` is
+ *     greater than `0`, then `fields` must not be NULL.
+ *     NOTE: the caller keeps ownership of the records in `fields` after
+ *     the call and must deallocate them using `ddlog_free()`.
+ * `cb` - callback invoked for each value associated with a matching key.
+ * `cb_arg` - opaque handle passed to each `cb invocation`.
+ *
+ * On success, returns `0`. On error, returns a negative value and
+ * writes error message (see `print_err_msg` parameter to `ddlog_run()`).
+ */
+extern int
+ddlog_query_index_prefix(ddlog_prog prog,
+                         index_id idxid,
+                         const ddlog_record **fields,
+                         size_t len,
+                         void (*cb)(uintptr_t arg, const ddlog_record *rec),
+                         uintptr_t cb_arg);
+
 /*
  * Perform a query serialized in a flatbuf; return result in another flatbuf.
  *
@@ -551,6 +703,78 @@ ddlog_dump_index(ddlog_prog prog,
                  void (*cb)(uintptr_t arg, const ddlog_record *rec),
                  uintptr_t cb_arg);
 
+/*
+ * Count the distinct records in a relation.  Output relations can only be
+ * counted if `ddlog_run()` was invoked with `do_store` set to `true`.
+ *
+ * `table` - id of the input or output relation to count.
+ * `count` - address where the function will store the number of records.
+ *
+ * On success, returns `0`. On error, returns a negative value and
+ * writes error message (see `print_err_msg` parameter to `ddlog_run()`).
+ */
+extern int
+ddlog_count_relation(ddlog_prog prog,
+                     table_id table,
+                     size_t *count);
+
+/*
+ * Count the records in an index without retrieving them.
+ *
+ * `idxid` - id of the index to count.
+ * `key` - when not NULL, only records associated with this key are counted.
+ *     NOTE: the caller keeps ownership of `key` after the call and must
+ *     deallocate it using `ddlog_free()`.
+ * `count` - address where the function will store the number of records.
+ *
+ * On success, returns `0`. On error, returns a negative value and
+ * writes error message (see `print_err_msg` parameter to `ddlog_run()`).
+ */
+extern int
+ddlog_count_index(ddlog_prog prog,
+                  index_id idxid,
+                  const ddlog_record *key,
+                  size_t *count);
+
+/*
+ * Position in an index returned by `ddlog_dump_index_page()`, from which the
+ * next page of the index can be dumped.
+ */
+typedef struct ddlog_index_cursor_struct ddlog_index_cursor;
+
+/*
+ * Enumerates up to `limit` records of an index, one page at a time, so that
+ * large indexes can be dumped without materializing all their contents.
+ * Records are enumerated in the order of their keys.
+ *
+ * `idxid` - id of the index to dump.
+ * `after` - cursor returned by the previous call, or NULL to start from the
+ *     beginning of the index.  The caller keeps ownership of the cursor.
+ * `limit` - maximum number of records to enumerate; must be greater than `0`.
+ * `cb` - callback invoked for each record in the page.
+ * `cb_arg` - opaque handle passed to each `cb invocation`.
+ * `next` - address where the function will store the cursor of the next
+ *     page, or NULL if this is the last page.  The caller is responsible for
+ *     deallocating the cursor using `ddlog_free_index_cursor()`.
+ *
+ * On success, returns `0`. On error, returns a negative value and
+ * writes error message (see `print_err_msg` parameter to `ddlog_run()`).
+ */
+extern int
+ddlog_dump_index_page(ddlog_prog prog,
+                      index_id idxid,
+                      const ddlog_index_cursor *after,
+                      size_t limit,
+                      void (*cb)(uintptr_t arg, const ddlog_record *rec),
+                      uintptr_t cb_arg,
+                      ddlog_index_cursor **next);
+
+/*
+ * Deallocate a cursor returned by `ddlog_dump_index_page()`.  Invalidates the
+ * pointer.
+ */
+extern void ddlog_free_index_cursor(ddlog_index_cursor *cursor);
+
 
 /*
  * Dump all values in an index to a flatbuf.
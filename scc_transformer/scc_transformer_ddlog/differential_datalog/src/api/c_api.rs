@@ -7,10 +7,10 @@ use crate::{
     ddval::DDValue,
     program::{
         config::{Config, LoggingDestination, ProfilingConfig},
-        IdxId, RelId,
+        ArrangementCursor, IdxId, RelId,
     },
     record::{IntoRecord, Record, UpdCmd},
-    DDlog, DDlogDump, DDlogDynamic, DDlogInventory, DDlogProfiling, DeltaMap,
+    DDlog, DDlogDump, DDlogDynamic, DDlogError, DDlogInventory, DDlogProfiling, DeltaMap,
 };
 use std::{
     collections::BTreeMap,
@@ -21,6 +21,7 @@ use std::{
     os::raw,
     ptr, slice,
     str::FromStr,
+    time::Duration,
 };
 use triomphe::Arc;
 
@@ -386,7 +387,7 @@ pub unsafe extern "C" fn ddlog_stop(prog: *const HDDlog) -> raw::c_int {
         .map(|mut program| {
             program.stop().map(|_| 0).unwrap_or_else(|e| {
                 HDDlog::print_err(print_err, &format!("ddlog_stop(): error: {}", e));
-                -1
+                e.code()
             })
         })
         .unwrap_or_else(|e| {
@@ -407,7 +408,7 @@ pub unsafe extern "C" fn ddlog_transaction_start(prog: *const HDDlog) -> raw::c_
 
     prog.transaction_start().map(|_| 0).unwrap_or_else(|e| {
         prog.eprintln(&format!("ddlog_transaction_start(): error: {}", e));
-        -1
+        e.code()
     })
 }
 
@@ -456,7 +457,7 @@ pub unsafe extern "C" fn ddlog_transaction_commit_dump_changes_as_array(
                 "ddlog_transaction_commit_dump_changes_as_array: error: {}",
                 e
             ));
-            -1
+            e.code()
         })
 }
 
@@ -464,7 +465,7 @@ unsafe fn do_transaction_commit_dump_changes_as_array(
     prog: &HDDlog,
     changes: *mut *const ddlog_record_update,
     num_changes: *mut libc::size_t,
-) -> Result<(), String> {
+) -> Result<(), DDlogError> {
     let updates = prog.transaction_commit_dump_changes()?;
     let mut size = 0;
     for (_, delta) in updates.as_ref().iter() {
@@ -518,8 +519,10 @@ pub unsafe extern "C" fn ddlog_transaction_commit_dump_changes_to_flatbuf(
 
     prog.transaction_commit_dump_changes()
         .and_then(|changes| {
-            let (flatbuf_vec, flatbuf_offset) =
-                prog.flatbuf_converter.updates_to_buffer(&changes)?;
+            let (flatbuf_vec, flatbuf_offset) = prog
+                .flatbuf_converter
+                .updates_to_buffer(&changes)
+                .map_err(DDlogError::Other)?;
             let flatbuf_vec = ManuallyDrop::new(flatbuf_vec);
 
             *buf = flatbuf_vec.as_ptr();
@@ -534,7 +537,7 @@ pub unsafe extern "C" fn ddlog_transaction_commit_dump_changes_to_flatbuf(
                 "ddlog_transaction_commit_dump_changes_to_flatbuf: error: {}",
                 e
             ));
-            -1
+            e.code()
         })
 }
 
@@ -562,6 +565,7 @@ pub unsafe extern "C" fn ddlog_query_index_from_flatbuf(
 
     prog.flatbuf_converter
         .query_index_from_buffer(slice::from_raw_parts(buf, n))
+        .map_err(DDlogError::TypeMismatch)
         .and_then(|(index_id, key)| {
             prog.query_index(index_id, key)
                 .map(|contents| (index_id, contents))
@@ -569,7 +573,8 @@ pub unsafe extern "C" fn ddlog_query_index_from_flatbuf(
         .and_then(|(index_id, contents)| {
             let (flatbuf_vec, flatbuf_offset) = prog
                 .flatbuf_converter
-                .index_values_to_buffer(index_id, &contents)?;
+                .index_values_to_buffer(index_id, &contents)
+                .map_err(DDlogError::Other)?;
             let flatbuf_vec = ManuallyDrop::new(flatbuf_vec);
 
             *resbuf = flatbuf_vec.as_ptr();
@@ -581,7 +586,7 @@ pub unsafe extern "C" fn ddlog_query_index_from_flatbuf(
         })
         .unwrap_or_else(|e| {
             prog.eprintln(&format!("ddlog_query_index_from_flatbuf(): error: {}", e));
-            -1
+            e.code()
         })
 }
 
@@ -608,10 +613,98 @@ pub unsafe extern "C" fn ddlog_dump_index(
         })
         .unwrap_or_else(|e| {
             prog.eprintln(&format!("ddlog_dump_index: error: {}", e));
-            -1
+            e.code()
+        })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_count_relation(
+    prog: *const HDDlog,
+    table: libc::size_t,
+    count: *mut libc::size_t,
+) -> raw::c_int {
+    if prog.is_null() || count.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    prog.count_relation(table as RelId)
+        .map(|n| {
+            *count = n as libc::size_t;
+            0
+        })
+        .unwrap_or_else(|e| {
+            prog.eprintln(&format!("ddlog_count_relation: error: {}", e));
+            e.code()
         })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_count_index(
+    prog: *const HDDlog,
+    idxid: libc::size_t,
+    key: *const Record,
+    count: *mut libc::size_t,
+) -> raw::c_int {
+    if prog.is_null() || count.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    key.as_ref()
+        .map(|key| prog.inventory.index_from_record(idxid as IdxId, key))
+        .transpose()
+        .and_then(|key| prog.count_index(idxid as IdxId, key))
+        .map(|n| {
+            *count = n as libc::size_t;
+            0
+        })
+        .unwrap_or_else(|e| {
+            prog.eprintln(&format!("ddlog_count_index: error: {}", e));
+            e.code()
+        })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_dump_index_page(
+    prog: *const HDDlog,
+    idxid: libc::size_t,
+    after: *const ArrangementCursor,
+    limit: libc::size_t,
+    cb: Option<extern "C" fn(arg: libc::uintptr_t, rec: *const Record)>,
+    cb_arg: libc::uintptr_t,
+    next: *mut *mut ArrangementCursor,
+) -> raw::c_int {
+    if prog.is_null() || next.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    prog.dump_index_page(idxid as IdxId, after.as_ref().cloned(), limit)
+        .map(|page| {
+            if let Some(f) = cb {
+                for val in page.values.into_iter() {
+                    f(cb_arg, &val.into_record());
+                }
+            }
+            *next = page
+                .next
+                .map_or(ptr::null_mut(), |cursor| Box::into_raw(Box::new(cursor)));
+            0
+        })
+        .unwrap_or_else(|e| {
+            prog.eprintln(&format!("ddlog_dump_index_page: error: {}", e));
+            e.code()
+        })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_free_index_cursor(cursor: *mut ArrangementCursor) {
+    if !cursor.is_null() {
+        drop(Box::from_raw(cursor));
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn ddlog_query_index(
     prog: *const HDDlog,
@@ -636,7 +729,73 @@ pub unsafe extern "C" fn ddlog_query_index(
         })
         .unwrap_or_else(|e| {
             prog.eprintln(&format!("ddlog_dump_index: error: {}", e));
-            -1
+            e.code()
+        })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_query_index_range(
+    prog: *const HDDlog,
+    idxid: libc::size_t,
+    lo: *const Record,
+    hi: *const Record,
+    cb: Option<extern "C" fn(arg: libc::uintptr_t, rec: *const Record)>,
+    cb_arg: libc::uintptr_t,
+) -> raw::c_int {
+    if prog.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    prog.query_index_range_dynamic(idxid as IdxId, lo.as_ref(), hi.as_ref())
+        .map(|set| {
+            if let Some(f) = cb {
+                for val in set.iter() {
+                    f(cb_arg, val);
+                }
+            }
+            0
+        })
+        .unwrap_or_else(|e| {
+            prog.eprintln(&format!("ddlog_query_index_range: error: {}", e));
+            e.code()
+        })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_query_index_prefix(
+    prog: *const HDDlog,
+    idxid: libc::size_t,
+    fields: *const *const Record,
+    len: libc::size_t,
+    cb: Option<extern "C" fn(arg: libc::uintptr_t, rec: *const Record)>,
+    cb_arg: libc::uintptr_t,
+) -> raw::c_int {
+    if prog.is_null() || (fields.is_null() && len > 0) {
+        return -1;
+    }
+    let prog = &*prog;
+
+    let prefix: Vec<Record> = if len == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(fields, len as usize)
+            .iter()
+            .map(|field| (**field).clone())
+            .collect()
+    };
+    prog.query_index_prefix_dynamic(idxid as IdxId, &prefix)
+        .map(|set| {
+            if let Some(f) = cb {
+                for val in set.iter() {
+                    f(cb_arg, val);
+                }
+            }
+            0
+        })
+        .unwrap_or_else(|e| {
+            prog.eprintln(&format!("ddlog_query_index_prefix: error: {}", e));
+            e.code()
         })
 }
 
@@ -663,7 +822,8 @@ pub unsafe extern "C" fn ddlog_dump_index_to_flatbuf(
         .and_then(|contents| {
             let (flatbuf_vec, flatbuf_offset) = prog
                 .flatbuf_converter
-                .index_values_to_buffer(idxid, &contents)?;
+                .index_values_to_buffer(idxid, &contents)
+                .map_err(DDlogError::Other)?;
             let flatbuf_vec = ManuallyDrop::new(flatbuf_vec);
 
             *resbuf = flatbuf_vec.as_ptr();
@@ -675,7 +835,7 @@ pub unsafe extern "C" fn ddlog_dump_index_to_flatbuf(
         })
         .unwrap_or_else(|e| {
             prog.eprintln(&format!("ddlog_dump_index_to_flatbuf(): error: {}", e));
-            -1
+            e.code()
         })
 }
 
@@ -697,10 +857,42 @@ pub unsafe extern "C" fn ddlog_transaction_commit(prog: *const HDDlog) -> raw::c
 
     prog.transaction_commit().map(|_| 0).unwrap_or_else(|e| {
         prog.eprintln(&format!("ddlog_transaction_commit(): error: {}", e));
-        -1
+        e.code()
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_transaction_commit_with_deadline(
+    prog: *const HDDlog,
+    timeout_ms: u64,
+) -> raw::c_int {
+    if prog.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    prog.transaction_commit_with_deadline(Duration::from_millis(timeout_ms))
+        .map(|_| 0)
+        .unwrap_or_else(|e| {
+            prog.eprintln(&format!(
+                "ddlog_transaction_commit_with_deadline(): error: {}",
+                e
+            ));
+            e.code()
+        })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_cancel_commit(prog: *const HDDlog) -> raw::c_int {
+    if prog.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    prog.cancellation_token().cancel();
+    0
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn ddlog_transaction_rollback(prog: *const HDDlog) -> raw::c_int {
     if prog.is_null() {
@@ -710,7 +902,64 @@ pub unsafe extern "C" fn ddlog_transaction_rollback(prog: *const HDDlog) -> raw:
 
     prog.transaction_rollback().map(|_| 0).unwrap_or_else(|e| {
         prog.eprintln(&format!("ddlog_transaction_rollback(): error: {}", e));
-        -1
+        e.code()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_savepoint(
+    prog: *const HDDlog,
+    name: *const raw::c_char,
+) -> raw::c_int {
+    if prog.is_null() || name.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    savepoint_name(name)
+        .and_then(|name| prog.savepoint(name))
+        .map(|_| 0)
+        .unwrap_or_else(|e| {
+            prog.eprintln(&format!("ddlog_savepoint(): error: {}", e));
+            e.code()
+        })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_rollback_to(
+    prog: *const HDDlog,
+    name: *const raw::c_char,
+) -> raw::c_int {
+    if prog.is_null() || name.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    savepoint_name(name)
+        .and_then(|name| prog.rollback_to(name))
+        .map(|_| 0)
+        .unwrap_or_else(|e| {
+            prog.eprintln(&format!("ddlog_rollback_to(): error: {}", e));
+            e.code()
+        })
+}
+
+unsafe fn savepoint_name<'a>(name: *const raw::c_char) -> Result<&'a str, DDlogError> {
+    CStr::from_ptr(name)
+        .to_str()
+        .map_err(|e| DDlogError::Other(format!("invalid savepoint name: {}", e)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ddlog_restart(prog: *const HDDlog) -> raw::c_int {
+    if prog.is_null() {
+        return -1;
+    }
+    let prog = &*prog;
+
+    prog.restart().map(|_| 0).unwrap_or_else(|e| {
+        prog.eprintln(&format!("ddlog_restart(): error: {}", e));
+        e.code()
     })
 }
 
@@ -729,7 +978,7 @@ pub unsafe extern "C" fn ddlog_apply_updates(
         .map(|_| 0)
         .unwrap_or_else(|e| {
             prog.eprintln(&format!("ddlog_apply_updates(): error: {}", e));
-            -1
+            e.code()
         })
 }
 
@@ -748,7 +997,7 @@ pub unsafe extern "C" fn ddlog_apply_updates_from_flatbuf(
         .map_or_else(
             |e| {
                 prog.eprintln(&format!("ddlog_apply_updates_from_flatbuf(): error: {}", e));
-                -1
+                e.code()
             },
             |_| 0,
         )
@@ -766,7 +1015,7 @@ pub unsafe extern "C" fn ddlog_clear_relation(
 
     prog.clear_relation(table).map(|_| 0).unwrap_or_else(|e| {
         prog.eprintln(&format!("ddlog_clear_relation(): error: {}", e));
-        -1
+        e.code()
     })
 }
 
@@ -785,7 +1034,7 @@ pub unsafe extern "C" fn ddlog_dump_table(
     match cb {
         None => prog.dump_table(table, None).map(|_| 0).unwrap_or_else(|e| {
             prog.eprintln(&format!("ddlog_dump_table(): error: {}", e));
-            -1
+            e.code()
         }),
         Some(f) => {
             let f = move |rec: &Record, w: isize| f(cb_arg, rec, w as libc::ssize_t);
@@ -793,7 +1042,7 @@ pub unsafe extern "C" fn ddlog_dump_table(
                 .map(|_| 0)
                 .unwrap_or_else(|e| {
                     prog.eprintln(&format!("ddlog_dump_table(): error: {}", e));
-                    -1
+                    e.code()
                 })
         }
     }
@@ -813,7 +1062,7 @@ pub unsafe extern "C" fn ddlog_enable_cpu_profiling(
         .map(|_| 0)
         .unwrap_or_else(|e| {
             prog.eprintln(&format!("ddlog_enable_cpu_profiling(): error: {}", e));
-            -1
+            e.code()
         })
 }
 
@@ -831,7 +1080,7 @@ pub unsafe extern "C" fn ddlog_enable_timely_profiling(
         .map(|_| 0)
         .unwrap_or_else(|e| {
             prog.eprintln(&format!("ddlog_enable_timely_profiling(): error: {}", e));
-            -1
+            e.code()
         })
 }
 
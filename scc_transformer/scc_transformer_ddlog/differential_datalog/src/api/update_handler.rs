@@ -8,6 +8,7 @@
 //! - accumulating changes from one or multiple transactions in
 //!   an in-memory database
 //! - chaining multiple update handlers
+//! - diverting updates away from other handlers
 //! - all of the above, but processed by a separate thread
 //!   rather than the differential worker threads that computes
 //!   the update
@@ -25,8 +26,11 @@ use crossbeam_channel::Sender;
 use std::{
     cell::Cell,
     fmt::{self, Debug, Formatter},
-    ptr,
-    sync::{Arc, Barrier, Mutex, MutexGuard},
+    mem, ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Barrier, Mutex, MutexGuard,
+    },
     thread,
 };
 
@@ -439,6 +443,92 @@ impl MTUpdateHandler for MTChainedUpdateHandler {
     }
 }
 
+/// Multi-threaded `UpdateHandler` that forwards updates to another handler,
+/// unless they are diverted (see `divert`), in which case they are
+/// accumulated in a `DeltaMap` instead.  The other handler is not notified
+/// of commits made while updates are diverted.
+#[derive(Clone, Debug)]
+pub struct DivertingUpdateHandler {
+    handler: Arc<dyn IMTUpdateHandler>,
+    /// Set while updates are diverted, so that forwarded updates don't need
+    /// to lock `diverted`.
+    diverting: Arc<AtomicBool>,
+    diverted: Arc<Mutex<DeltaMap<DDValue>>>,
+}
+
+impl DivertingUpdateHandler {
+    pub fn new(handler: Arc<dyn IMTUpdateHandler>) -> Self {
+        Self {
+            handler,
+            diverting: Arc::new(AtomicBool::new(false)),
+            diverted: Arc::new(Mutex::new(DeltaMap::new())),
+        }
+    }
+
+    /// Start diverting updates.
+    pub fn divert(&self) {
+        self.diverting.store(true, Ordering::SeqCst);
+    }
+
+    /// Take the updates diverted so far.
+    pub fn take_diverted(&self) -> DeltaMap<DDValue> {
+        mem::replace(&mut *self.diverted.lock().unwrap(), DeltaMap::new())
+    }
+
+    /// Stop diverting updates, discarding the diverted updates that were not
+    /// taken.
+    pub fn resume(&self) {
+        self.diverting.store(false, Ordering::SeqCst);
+        self.take_diverted();
+    }
+
+    fn is_diverting(&self) -> bool {
+        self.diverting.load(Ordering::SeqCst)
+    }
+}
+
+impl UpdateHandler for DivertingUpdateHandler {
+    fn update_cb(&self) -> Arc<dyn SingleThreadedRelationCallback> {
+        let handler = self.clone();
+        let cb = self.handler.update_cb();
+        Arc::new(move |relid, v, w| {
+            if handler.is_diverting() {
+                handler.diverted.lock().unwrap().update(relid, v, w);
+            } else {
+                cb(relid, v, w);
+            }
+        })
+    }
+    fn before_commit(&self) {
+        if !self.is_diverting() {
+            self.handler.before_commit();
+        }
+    }
+    fn after_commit(&self, success: bool) {
+        if !self.is_diverting() {
+            self.handler.after_commit(success);
+        }
+    }
+}
+
+impl MTUpdateHandler for DivertingUpdateHandler {
+    fn mt_update_cb(&self) -> Arc<dyn RelationCallback> {
+        let handler = self.clone();
+        let cb = self.handler.mt_update_cb();
+        Arc::new(move |relid, v, w| {
+            if handler.is_diverting() {
+                handler
+                    .diverted
+                    .lock()
+                    .unwrap()
+                    .update(relid, v, w as isize);
+            } else {
+                cb(relid, v, w);
+            }
+        })
+    }
+}
+
 /// We use a single mpsc channel to notify worker about
 /// update, start, and commit events.
 enum Msg {
@@ -553,3 +643,36 @@ impl MTUpdateHandler for ThreadUpdateHandler {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ddval::DDValConvert;
+
+    fn values(db: &DeltaMap<DDValue>, relid: RelId) -> Vec<(u64, isize)> {
+        db.get(&relid)
+            .into_iter()
+            .flatten()
+            .map(|(v, w)| (*u64::from_ddvalue_ref(v), *w))
+            .collect()
+    }
+
+    #[test]
+    fn diverted_updates_bypass_handler() {
+        let db = Arc::new(Mutex::new(DeltaMap::new()));
+        let handler = DivertingUpdateHandler::new(Arc::new(MTValMapUpdateHandler::new(db.clone())));
+        let cb = handler.mt_update_cb();
+
+        cb(1, &1u64.into_ddvalue(), 1);
+        handler.divert();
+        cb(1, &2u64.into_ddvalue(), 1);
+        assert_eq!(values(&handler.take_diverted(), 1), vec![(2, 1)]);
+
+        // Updates that are not taken are discarded.
+        cb(1, &3u64.into_ddvalue(), -1);
+        handler.resume();
+        cb(1, &4u64.into_ddvalue(), 1);
+        assert_eq!(values(&handler.take_diverted(), 1), vec![]);
+        assert_eq!(values(&db.lock().unwrap(), 1), vec![(1, 1), (4, 1)]);
+    }
+}
@@ -2,7 +2,9 @@
 
 use dyn_clone::DynClone;
 use fnv::FnvHashMap;
+use num::BigInt;
 use std::any::TypeId;
+use std::borrow::Cow;
 use std::collections::btree_set::BTreeSet;
 use std::collections::BTreeMap;
 #[cfg(feature = "c_api")]
@@ -14,47 +16,58 @@ use std::sync::Arc as StdArc;
 use triomphe::Arc;
 
 use crate::ddval::DDValue;
+use crate::error::DDlogError;
 use crate::program::RelId;
 use crate::program::Update;
-use crate::program::{ArrId, IdxId};
+use crate::program::{ArrId, ArrangementCursor, ArrangementPage, IdxId};
 use crate::record::UpdCmd;
-use crate::record::{Record, RelIdentifier};
+use crate::record::{IntoRecord, Record, RelIdentifier};
 use crate::valmap::DeltaMap;
 
 /// Convert relation and index names to and from numeric id's.
 pub trait DDlogInventory: DynClone {
     /// Convert table name to `RelId`.
-    fn get_table_id(&self, table_name: &str) -> Result<RelId, String>;
+    fn get_table_id(&self, table_name: &str) -> Result<RelId, DDlogError>;
 
     /// Convert a `RelId` into its symbolic name.
-    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, String>;
+    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, DDlogError>;
 
     /// Given a table name, returns the original name (from the 'original' DDlog
     /// relation annotation), if present, or the table name itself otherwise.
     /// If 'tname' is not a legal table name return an Error.
-    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, String>;
+    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, DDlogError>;
 
     /// Get the table original name (see above) but as a C string.
     #[cfg(feature = "c_api")]
-    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, String>;
+    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, DDlogError>;
 
     /// Convert a `RelId` into its symbolic name represented as C string.
     #[cfg(feature = "c_api")]
-    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, String>;
+    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, DDlogError>;
 
     /// Convert index name to `IdxId`.
-    fn get_index_id(&self, index_name: &str) -> Result<IdxId, String>;
+    fn get_index_id(&self, index_name: &str) -> Result<IdxId, DDlogError>;
 
     /// Convert a `IdxId` into its symbolic name.
-    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, String>;
+    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, DDlogError>;
 
     /// Convert a `IdxId` into its symbolic name represented as C string.
     #[cfg(feature = "c_api")]
-    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, String>;
+    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, DDlogError>;
+
+    /// Names of all relations, ordered by `RelId`.
+    fn table_names(&self) -> Vec<&'static str>;
+
+    /// Names of all indexes, ordered by `IdxId`.
+    fn index_names(&self) -> Vec<&'static str>;
+
+    /// The DDlog declaration of a relation, e.g., `input relation Edge(src: u32, dest: u32)`, as
+    /// far as it can be recovered from the relation's type (see `record::relation_declaration`).
+    fn get_table_schema(&self, table_id: RelId) -> Result<String, DDlogError>;
 
     fn input_relation_ids(&self) -> &'static FnvHashMap<RelId, &'static str>;
 
-    fn index_from_record(&self, index: IdxId, key: &Record) -> Result<DDValue, String>;
+    fn index_from_record(&self, index: IdxId, key: &Record) -> Result<DDValue, DDlogError>;
 
     fn relation_type_id(&self, relation: RelId) -> Option<TypeId>;
 
@@ -62,15 +75,35 @@ pub trait DDlogInventory: DynClone {
         &self,
         relation: &RelIdentifier,
         value: &Record,
-    ) -> Result<(RelId, DDValue), String>;
+    ) -> Result<(RelId, DDValue), DDlogError>;
 
     fn relation_key_from_record(
         &self,
         relation: &RelIdentifier,
         key: &Record,
-    ) -> Result<(RelId, DDValue), String>;
+    ) -> Result<(RelId, DDValue), DDlogError>;
 
     fn index_to_arrangement_id(&self, index: IdxId) -> Option<ArrId>;
+
+    /// A default value of relation `table_id`'s type as a record, which names the fields of a
+    /// struct type and tells their kinds apart (see `record::record_from_columns`).  `None` if
+    /// the type is neither a struct named after the relation nor a primitive type.
+    fn relation_template(&self, table_id: RelId) -> Option<Record> {
+        let name = self.get_table_name(table_id).ok()?;
+        let relation = RelIdentifier::RelId(table_id);
+        // Structs fill in missing named fields with their default values.
+        let probes = [
+            Record::NamedStruct(Cow::from(name), Vec::new()),
+            Record::Tuple(Vec::new()),
+            Record::Bool(false),
+            Record::Int(BigInt::from(0)),
+            Record::String(String::new()),
+        ];
+        probes
+            .iter()
+            .find_map(|probe| self.relation_value_from_record(&relation, probe).ok())
+            .map(|(_, value)| value.into_record())
+    }
 }
 
 dyn_clone::clone_trait_object!(DDlogInventory);
@@ -80,46 +113,58 @@ where
     T: DDlogInventory + ?Sized,
     Box<T>: Clone,
 {
-    fn get_table_id(&self, table_name: &str) -> Result<RelId, String> {
+    fn get_table_id(&self, table_name: &str) -> Result<RelId, DDlogError> {
         self.deref().get_table_id(table_name)
     }
 
-    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, String> {
+    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, DDlogError> {
         self.deref().get_table_name(table_id)
     }
 
-    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, String> {
+    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, DDlogError> {
         self.deref().get_table_original_name(table_name)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, String> {
+    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, DDlogError> {
         self.deref().get_table_original_cname(table_name)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, String> {
+    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, DDlogError> {
         self.deref().get_table_cname(table_id)
     }
 
-    fn get_index_id(&self, index_name: &str) -> Result<IdxId, String> {
+    fn get_index_id(&self, index_name: &str) -> Result<IdxId, DDlogError> {
         self.deref().get_index_id(index_name)
     }
 
-    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, String> {
+    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, DDlogError> {
         self.deref().get_index_name(index_id)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, String> {
+    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, DDlogError> {
         self.deref().get_index_cname(index_id)
     }
 
+    fn table_names(&self) -> Vec<&'static str> {
+        self.deref().table_names()
+    }
+
+    fn index_names(&self) -> Vec<&'static str> {
+        self.deref().index_names()
+    }
+
+    fn get_table_schema(&self, table_id: RelId) -> Result<String, DDlogError> {
+        self.deref().get_table_schema(table_id)
+    }
+
     fn input_relation_ids(&self) -> &'static FnvHashMap<RelId, &'static str> {
         self.deref().input_relation_ids()
     }
 
-    fn index_from_record(&self, index: IdxId, key: &Record) -> Result<DDValue, String> {
+    fn index_from_record(&self, index: IdxId, key: &Record) -> Result<DDValue, DDlogError> {
         self.deref().index_from_record(index, key)
     }
 
@@ -131,7 +176,7 @@ where
         &self,
         relation: &RelIdentifier,
         value: &Record,
-    ) -> Result<(RelId, DDValue), String> {
+    ) -> Result<(RelId, DDValue), DDlogError> {
         self.deref().relation_value_from_record(relation, value)
     }
 
@@ -139,13 +184,17 @@ where
         &self,
         relation: &RelIdentifier,
         key: &Record,
-    ) -> Result<(RelId, DDValue), String> {
+    ) -> Result<(RelId, DDValue), DDlogError> {
         self.deref().relation_key_from_record(relation, key)
     }
 
     fn index_to_arrangement_id(&self, index: IdxId) -> Option<ArrId> {
         self.deref().index_to_arrangement_id(index)
     }
+
+    fn relation_template(&self, table_id: RelId) -> Option<Record> {
+        self.deref().relation_template(table_id)
+    }
 }
 
 impl<T> DDlogInventory for StdArc<T>
@@ -153,46 +202,58 @@ where
     T: DDlogInventory + ?Sized,
     StdArc<T>: Clone,
 {
-    fn get_table_id(&self, table_name: &str) -> Result<RelId, String> {
+    fn get_table_id(&self, table_name: &str) -> Result<RelId, DDlogError> {
         self.deref().get_table_id(table_name)
     }
 
-    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, String> {
+    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, DDlogError> {
         self.deref().get_table_name(table_id)
     }
 
-    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, String> {
+    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, DDlogError> {
         self.deref().get_table_original_name(table_name)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, String> {
+    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, DDlogError> {
         self.deref().get_table_original_cname(table_name)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, String> {
+    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, DDlogError> {
         self.deref().get_table_cname(table_id)
     }
 
-    fn get_index_id(&self, index_name: &str) -> Result<IdxId, String> {
+    fn get_index_id(&self, index_name: &str) -> Result<IdxId, DDlogError> {
         self.deref().get_index_id(index_name)
     }
 
-    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, String> {
+    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, DDlogError> {
         self.deref().get_index_name(index_id)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, String> {
+    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, DDlogError> {
         self.deref().get_index_cname(index_id)
     }
 
+    fn table_names(&self) -> Vec<&'static str> {
+        self.deref().table_names()
+    }
+
+    fn index_names(&self) -> Vec<&'static str> {
+        self.deref().index_names()
+    }
+
+    fn get_table_schema(&self, table_id: RelId) -> Result<String, DDlogError> {
+        self.deref().get_table_schema(table_id)
+    }
+
     fn input_relation_ids(&self) -> &'static FnvHashMap<RelId, &'static str> {
         self.deref().input_relation_ids()
     }
 
-    fn index_from_record(&self, index: IdxId, key: &Record) -> Result<DDValue, String> {
+    fn index_from_record(&self, index: IdxId, key: &Record) -> Result<DDValue, DDlogError> {
         self.deref().index_from_record(index, key)
     }
 
@@ -204,7 +265,7 @@ where
         &self,
         relation: &RelIdentifier,
         value: &Record,
-    ) -> Result<(RelId, DDValue), String> {
+    ) -> Result<(RelId, DDValue), DDlogError> {
         self.deref().relation_value_from_record(relation, value)
     }
 
@@ -212,13 +273,17 @@ where
         &self,
         relation: &RelIdentifier,
         key: &Record,
-    ) -> Result<(RelId, DDValue), String> {
+    ) -> Result<(RelId, DDValue), DDlogError> {
         self.deref().relation_key_from_record(relation, key)
     }
 
     fn index_to_arrangement_id(&self, index: IdxId) -> Option<ArrId> {
         self.deref().index_to_arrangement_id(index)
     }
+
+    fn relation_template(&self, table_id: RelId) -> Option<Record> {
+        self.deref().relation_template(table_id)
+    }
 }
 
 impl<T> DDlogInventory for Arc<T>
@@ -226,46 +291,58 @@ where
     T: DDlogInventory + ?Sized,
     Arc<T>: Clone,
 {
-    fn get_table_id(&self, table_name: &str) -> Result<RelId, String> {
+    fn get_table_id(&self, table_name: &str) -> Result<RelId, DDlogError> {
         self.deref().get_table_id(table_name)
     }
 
-    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, String> {
+    fn get_table_name(&self, table_id: RelId) -> Result<&'static str, DDlogError> {
         self.deref().get_table_name(table_id)
     }
 
-    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, String> {
+    fn get_table_original_name(&self, table_name: &str) -> Result<&'static str, DDlogError> {
         self.deref().get_table_original_name(table_name)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, String> {
+    fn get_table_original_cname(&self, table_name: &str) -> Result<&'static CStr, DDlogError> {
         self.deref().get_table_original_cname(table_name)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, String> {
+    fn get_table_cname(&self, table_id: RelId) -> Result<&'static CStr, DDlogError> {
         self.deref().get_table_cname(table_id)
     }
 
-    fn get_index_id(&self, index_name: &str) -> Result<IdxId, String> {
+    fn get_index_id(&self, index_name: &str) -> Result<IdxId, DDlogError> {
         self.deref().get_index_id(index_name)
     }
 
-    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, String> {
+    fn get_index_name(&self, index_id: IdxId) -> Result<&'static str, DDlogError> {
         self.deref().get_index_name(index_id)
     }
 
     #[cfg(feature = "c_api")]
-    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, String> {
+    fn get_index_cname(&self, index_id: IdxId) -> Result<&'static CStr, DDlogError> {
         self.deref().get_index_cname(index_id)
     }
 
+    fn table_names(&self) -> Vec<&'static str> {
+        self.deref().table_names()
+    }
+
+    fn index_names(&self) -> Vec<&'static str> {
+        self.deref().index_names()
+    }
+
+    fn get_table_schema(&self, table_id: RelId) -> Result<String, DDlogError> {
+        self.deref().get_table_schema(table_id)
+    }
+
     fn input_relation_ids(&self) -> &'static FnvHashMap<RelId, &'static str> {
         self.deref().input_relation_ids()
     }
 
-    fn index_from_record(&self, index: IdxId, key: &Record) -> Result<DDValue, String> {
+    fn index_from_record(&self, index: IdxId, key: &Record) -> Result<DDValue, DDlogError> {
         self.deref().index_from_record(index, key)
     }
 
@@ -277,7 +354,7 @@ where
         &self,
         relation: &RelIdentifier,
         value: &Record,
-    ) -> Result<(RelId, DDValue), String> {
+    ) -> Result<(RelId, DDValue), DDlogError> {
         self.deref().relation_value_from_record(relation, value)
     }
 
@@ -285,13 +362,17 @@ where
         &self,
         relation: &RelIdentifier,
         key: &Record,
-    ) -> Result<(RelId, DDValue), String> {
+    ) -> Result<(RelId, DDValue), DDlogError> {
         self.deref().relation_key_from_record(relation, key)
     }
 
     fn index_to_arrangement_id(&self, index: IdxId) -> Option<ArrId> {
         self.deref().index_to_arrangement_id(index)
     }
+
+    fn relation_template(&self, table_id: RelId) -> Option<Record> {
+        self.deref().relation_template(table_id)
+    }
 }
 
 /// Location id in a D3log system.
@@ -343,12 +424,12 @@ pub trait DDlogProfiling {
     ///
     /// Recording CPU events can be expensive in large dataflows and is
     /// therefore disabled by default.
-    fn enable_cpu_profiling(&self, enable: bool) -> Result<(), String>;
+    fn enable_cpu_profiling(&self, enable: bool) -> Result<(), DDlogError>;
 
-    fn enable_timely_profiling(&self, enable: bool) -> Result<(), String>;
+    fn enable_timely_profiling(&self, enable: bool) -> Result<(), DDlogError>;
 
     /// returns DDlog program runtime profile
-    fn profile(&self) -> Result<String, String>;
+    fn profile(&self) -> Result<String, DDlogError>;
 }
 
 /// API to dump DDlog input and output relations.
@@ -359,41 +440,62 @@ pub trait DDlogDump {
         &self,
         table: RelId,
         cb: Option<&dyn Fn(&Record, isize) -> bool>,
-    ) -> Result<(), String>;
+    ) -> Result<(), DDlogError>;
 }
 /// A trait capturing the handling of transactions using the dynamically typed
 /// representation of DDlog values as `enum Record`.
 pub trait DDlogDynamic {
     /// Start a transaction.
-    fn transaction_start(&self) -> Result<(), String>;
+    fn transaction_start(&self) -> Result<(), DDlogError>;
 
     /// Commit a transaction previously started using
     /// `transaction_start`, producing a map of deltas.
     fn transaction_commit_dump_changes_dynamic(
         &self,
-    ) -> Result<BTreeMap<RelId, Vec<(Record, isize)>>, String>;
+    ) -> Result<BTreeMap<RelId, Vec<(Record, isize)>>, DDlogError>;
 
     /// Commit a transaction previously started using
     /// `transaction_start`.
-    fn transaction_commit(&self) -> Result<(), String>;
+    fn transaction_commit(&self) -> Result<(), DDlogError>;
 
     /// Roll back a transaction previously started using
     /// `transaction_start`.
-    fn transaction_rollback(&self) -> Result<(), String>;
+    fn transaction_rollback(&self) -> Result<(), DDlogError>;
 
     /// Apply a set of updates.
-    fn apply_updates_dynamic(&self, upds: &mut dyn Iterator<Item = UpdCmd>) -> Result<(), String>;
+    fn apply_updates_dynamic(
+        &self,
+        upds: &mut dyn Iterator<Item = UpdCmd>,
+    ) -> Result<(), DDlogError>;
 
-    fn clear_relation(&self, table: RelId) -> Result<(), String>;
+    fn clear_relation(&self, table: RelId) -> Result<(), DDlogError>;
 
     /// Query index passing key as a record.  Returns all values associated with the given key in the index.
-    fn query_index_dynamic(&self, index: IdxId, key: &Record) -> Result<Vec<Record>, String>;
+    fn query_index_dynamic(&self, index: IdxId, key: &Record) -> Result<Vec<Record>, DDlogError>;
+
+    /// Query index passing the bounds of the key range `[lo, hi)` as records, where a missing
+    /// bound leaves the range unbounded on that side.  Returns all values associated with keys
+    /// in the range.
+    fn query_index_range_dynamic(
+        &self,
+        index: IdxId,
+        lo: Option<&Record>,
+        hi: Option<&Record>,
+    ) -> Result<Vec<Record>, DDlogError>;
+
+    /// Query index by key prefix.  Returns all values associated with tuple or struct keys
+    /// whose leading fields equal `prefix`.
+    fn query_index_prefix_dynamic(
+        &self,
+        index: IdxId,
+        prefix: &[Record],
+    ) -> Result<Vec<Record>, DDlogError>;
 
     /// Dump all values in an index.
-    fn dump_index_dynamic(&self, index: IdxId) -> Result<Vec<Record>, String>;
+    fn dump_index_dynamic(&self, index: IdxId) -> Result<Vec<Record>, DDlogError>;
 
     /// Stop the program.
-    fn stop(&self) -> Result<(), String>;
+    fn stop(&self) -> Result<(), DDlogError>;
 }
 
 /// Extend `trait DDlogDynamic` with methods that offer a strongly typed interface
@@ -401,14 +503,110 @@ pub trait DDlogDynamic {
 pub trait DDlog: DDlogDynamic {
     /// Commit a transaction previously started using
     /// `transaction_start`, producing a map of deltas.
-    fn transaction_commit_dump_changes(&self) -> Result<DeltaMap<DDValue>, String>;
+    fn transaction_commit_dump_changes(&self) -> Result<DeltaMap<DDValue>, DDlogError>;
 
     /// Apply a set of updates.
-    fn apply_updates(&self, upds: &mut dyn Iterator<Item = Update<DDValue>>) -> Result<(), String>;
+    fn apply_updates(
+        &self,
+        upds: &mut dyn Iterator<Item = Update<DDValue>>,
+    ) -> Result<(), DDlogError>;
 
     /// Query index.  Returns all values associated with the given key in the index.
-    fn query_index(&self, index: IdxId, key: DDValue) -> Result<BTreeSet<DDValue>, String>;
+    fn query_index(&self, index: IdxId, key: DDValue) -> Result<BTreeSet<DDValue>, DDlogError>;
+
+    /// Query index by key range.  Returns all values associated with keys in `[lo, hi)`,
+    /// where a missing bound leaves the range unbounded on that side.
+    fn query_index_range(
+        &self,
+        index: IdxId,
+        lo: Option<DDValue>,
+        hi: Option<DDValue>,
+    ) -> Result<BTreeSet<DDValue>, DDlogError>;
+
+    /// Query index by key prefix.  Returns all values associated with tuple or struct keys
+    /// whose leading fields equal `prefix`.
+    fn query_index_prefix(
+        &self,
+        index: IdxId,
+        prefix: &[Record],
+    ) -> Result<BTreeSet<DDValue>, DDlogError>;
 
     /// Dump all values in an index.
-    fn dump_index(&self, index: IdxId) -> Result<BTreeSet<DDValue>, String>;
+    fn dump_index(&self, index: IdxId) -> Result<BTreeSet<DDValue>, DDlogError>;
+
+    /// Number of distinct values in an input relation or, if the program stores the contents of
+    /// output relations, in an output relation.
+    fn count_relation(&self, table: RelId) -> Result<usize, DDlogError>;
+
+    /// Number of values in an index, or of those associated with `key`.  Values are counted
+    /// by the workers rather than retrieved.
+    fn count_index(&self, index: IdxId, key: Option<DDValue>) -> Result<usize, DDlogError>;
+
+    /// Dump up to `limit` values of an index, starting after the entry at `after`, or at the
+    /// beginning of the index.  Values are ordered by key, then by value.  The returned page
+    /// holds the cursor to pass to fetch the next page, if any.
+    fn dump_index_page(
+        &self,
+        index: IdxId,
+        after: Option<ArrangementCursor>,
+        limit: usize,
+    ) -> Result<ArrangementPage, DDlogError>;
+
+    /// Iterate over the values of an index, fetching `page_size` of them at a time using
+    /// `dump_index_page`.  Unlike `dump_index`, this only holds one page in memory, but pages
+    /// fetched after a transaction commits reflect the new contents of the index.
+    fn dump_index_iter(&self, index: IdxId, page_size: usize) -> IndexValues<'_, Self>
+    where
+        Self: Sized,
+    {
+        IndexValues {
+            prog: self,
+            index,
+            page_size,
+            page: Vec::new().into_iter(),
+            next: None,
+            done: false,
+        }
+    }
+}
+
+/// Iterator over the values of an index returned by `DDlog::dump_index_iter`.  Stops after the
+/// first error.
+pub struct IndexValues<'a, P> {
+    prog: &'a P,
+    index: IdxId,
+    page_size: usize,
+    page: std::vec::IntoIter<DDValue>,
+    next: Option<ArrangementCursor>,
+    done: bool,
+}
+
+impl<P: DDlog> Iterator for IndexValues<'_, P> {
+    type Item = Result<DDValue, DDlogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(v) = self.page.next() {
+                return Some(Ok(v));
+            }
+            if self.done {
+                return None;
+            }
+
+            match self
+                .prog
+                .dump_index_page(self.index, self.next.take(), self.page_size)
+            {
+                Ok(page) => {
+                    self.done = page.next.is_none();
+                    self.next = page.next;
+                    self.page = page.values.into_iter();
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
 }
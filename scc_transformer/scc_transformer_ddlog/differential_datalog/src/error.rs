@@ -0,0 +1,237 @@
+//! Errors reported by the DDlog runtime.
+
+use std::{error::Error, fmt, io, os::raw};
+
+/// Error returned by the DDlog runtime API.
+#[derive(Debug)]
+pub enum DDlogError {
+    /// The program doesn't declare the relation, given by name or id, or the relation doesn't
+    /// support the operation, e.g., updating a relation that is not an input relation.
+    UnknownRelation(String),
+    /// The program doesn't declare the index, given by name or id.
+    UnknownIndex(String),
+    /// The operation requires a transaction, but none is in progress.
+    NoTransaction(&'static str),
+    /// The operation can't be performed while a transaction is in progress.
+    TransactionInProgress(&'static str),
+    /// A worker failed or stopped responding.  `worker` is the index of the worker, if known.
+    WorkerFailure {
+        worker: Option<usize>,
+        message: String,
+    },
+    /// A value or record doesn't have the type expected by the relation or index.
+    TypeMismatch(String),
+    /// An I/O operation, described by `context`, failed.
+    Io { context: String, source: io::Error },
+    /// Any other error, e.g., an update that conflicts with the contents of a relation.
+    Other(String),
+    /// The operation didn't complete before its deadline, so it was aborted.
+    Timeout(&'static str),
+    /// The operation was cancelled with a `CancellationToken`, so it was aborted.
+    Cancelled(&'static str),
+    /// The transaction in progress has no savepoint with the name.
+    UnknownSavepoint(String),
+    /// `HDDlog::evaluate_hypothetical` committed its updates, but failed to undo them because
+    /// of `cause`.  If `restored`, the dataflow was restarted with the updates undone;
+    /// otherwise the input relations may still contain them.
+    HypotheticalUndo {
+        cause: Box<DDlogError>,
+        restored: bool,
+    },
+}
+
+impl DDlogError {
+    pub fn worker_failure<M: Into<String>>(worker: Option<usize>, message: M) -> Self {
+        DDlogError::WorkerFailure {
+            worker,
+            message: message.into(),
+        }
+    }
+
+    pub fn other<M: Into<String>>(message: M) -> Self {
+        DDlogError::Other(message.into())
+    }
+
+    pub fn io<C: Into<String>>(context: C, source: io::Error) -> Self {
+        DDlogError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Error of a CSV reader, described by `context`: failures to read keep the underlying
+    /// `io::Error`, whereas malformed input, e.g., invalid UTF-8, is a type mismatch.
+    pub(crate) fn csv_read<C: Into<String>>(context: C, error: csv::Error) -> Self {
+        let context = context.into();
+        let message = format!("{}: {}", context, error);
+        match error.into_kind() {
+            csv::ErrorKind::Io(source) => DDlogError::io(context, source),
+            _ => DDlogError::TypeMismatch(message),
+        }
+    }
+
+    /// Code returned by the C API for this error: a negative number that identifies the variant,
+    /// see `ddlog_error_code` in `ddlog.h`.
+    pub fn code(&self) -> raw::c_int {
+        match self {
+            DDlogError::Other(_) => -1,
+            DDlogError::UnknownRelation(_) => -2,
+            DDlogError::UnknownIndex(_) => -3,
+            DDlogError::NoTransaction(_) => -4,
+            DDlogError::TransactionInProgress(_) => -5,
+            DDlogError::WorkerFailure { .. } => -6,
+            DDlogError::TypeMismatch(_) => -7,
+            DDlogError::Io { .. } => -8,
+            DDlogError::Timeout(_) => -9,
+            DDlogError::Cancelled(_) => -10,
+            DDlogError::UnknownSavepoint(_) => -11,
+            DDlogError::HypotheticalUndo { .. } => -12,
+        }
+    }
+}
+
+impl fmt::Display for DDlogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DDlogError::UnknownRelation(relation) => write!(f, "unknown relation {}", relation),
+            DDlogError::UnknownIndex(index) => write!(f, "unknown index {}", index),
+            DDlogError::NoTransaction(operation) => {
+                write!(f, "{}: no transaction in progress", operation)
+            }
+            DDlogError::TransactionInProgress(operation) => {
+                write!(f, "{}: transaction in progress", operation)
+            }
+            DDlogError::WorkerFailure {
+                worker: Some(worker),
+                message,
+            } => write!(f, "worker {}: {}", worker, message),
+            DDlogError::WorkerFailure {
+                worker: None,
+                message,
+            } => write!(f, "{}", message),
+            DDlogError::TypeMismatch(message) | DDlogError::Other(message) => {
+                write!(f, "{}", message)
+            }
+            DDlogError::Io { context, source } => write!(f, "{}: {}", context, source),
+            DDlogError::Timeout(operation) => write!(f, "{}: deadline expired", operation),
+            DDlogError::Cancelled(operation) => write!(f, "{}: cancelled", operation),
+            DDlogError::UnknownSavepoint(name) => write!(f, "unknown savepoint {}", name),
+            DDlogError::HypotheticalUndo { cause, restored } => write!(
+                f,
+                "evaluate_hypothetical: failed to undo the updates: {}; {}",
+                cause,
+                if *restored {
+                    "the dataflow was restarted without them"
+                } else {
+                    "the input relations may still contain them"
+                }
+            ),
+        }
+    }
+}
+
+impl Error for DDlogError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DDlogError::Io { source, .. } => Some(source),
+            DDlogError::HypotheticalUndo { cause, .. } => Some(&**cause),
+            _ => None,
+        }
+    }
+}
+
+/// Lets code that reports errors as strings, such as the command line interface, use `?` on the
+/// runtime API.
+impl From<DDlogError> for String {
+    fn from(error: DDlogError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DDlogError;
+    use std::{error::Error, io};
+
+    #[test]
+    fn display_and_source() {
+        let error = DDlogError::NoTransaction("clear_relation");
+        assert_eq!(
+            error.to_string(),
+            "clear_relation: no transaction in progress"
+        );
+        assert!(error.source().is_none());
+
+        let error = DDlogError::io(
+            "failed to read 'x'",
+            io::Error::new(io::ErrorKind::NotFound, "not found"),
+        );
+        assert_eq!(error.to_string(), "failed to read 'x': not found");
+        assert_eq!(error.source().unwrap().to_string(), "not found");
+
+        let error = DDlogError::worker_failure(Some(2), "panicked");
+        assert_eq!(error.to_string(), "worker 2: panicked");
+        assert_eq!(String::from(error), "worker 2: panicked");
+    }
+
+    #[test]
+    fn csv_read_errors() {
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken"))
+            }
+        }
+
+        let error = csv::Reader::from_reader(Failing)
+            .records()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        match DDlogError::csv_read("failed to read CSV file", error) {
+            DDlogError::Io { context, source } => {
+                assert_eq!(context, "failed to read CSV file");
+                assert_eq!(source.kind(), io::ErrorKind::BrokenPipe);
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        let error = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(&b"1,\xff\n"[..])
+            .records()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            DDlogError::csv_read("failed to read CSV file", error),
+            DDlogError::TypeMismatch(_)
+        ));
+    }
+
+    #[test]
+    fn codes_are_distinct() {
+        let errors = [
+            DDlogError::UnknownRelation("R".to_string()),
+            DDlogError::UnknownIndex("I".to_string()),
+            DDlogError::NoTransaction("commit"),
+            DDlogError::TransactionInProgress("checkpoint"),
+            DDlogError::worker_failure(None, "disconnected"),
+            DDlogError::TypeMismatch("expected u32".to_string()),
+            DDlogError::io("write", io::Error::from(io::ErrorKind::Other)),
+            DDlogError::other("other"),
+            DDlogError::Timeout("transaction_commit"),
+            DDlogError::Cancelled("transaction_commit"),
+            DDlogError::UnknownSavepoint("S".to_string()),
+            DDlogError::HypotheticalUndo {
+                cause: Box::new(DDlogError::worker_failure(Some(0), "panicked")),
+                restored: true,
+            },
+        ];
+        let mut codes: Vec<_> = errors.iter().map(DDlogError::code).collect();
+        assert!(codes.iter().all(|code| *code < 0));
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+    }
+}
@@ -93,6 +93,22 @@ impl Profile {
         }
     }
 
+    /// Peak size of every arrangement seen so far, keyed by operator name.
+    pub fn peak_sizes(&self) -> Vec<(String, isize)> {
+        self.peak_sizes
+            .iter()
+            .map(|(operator, size)| {
+                let name = self.names.get(operator).map(AsRef::as_ref).unwrap_or("???");
+                (format!("{} {}", name, operator), *size)
+            })
+            .collect()
+    }
+
+    /// Sum of the peak sizes of all arrangements.
+    pub fn total_peak_size(&self) -> isize {
+        self.peak_sizes.values().sum()
+    }
+
     pub fn fmt_sizes(
         &self,
         sizes: &FnvHashMap<usize, isize>,
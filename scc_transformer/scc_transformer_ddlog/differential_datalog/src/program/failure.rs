@@ -0,0 +1,153 @@
+//! Failures of timely worker threads.
+
+use crate::error::DDlogError;
+use std::{
+    any::Any,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+/// The failure of a worker thread, which stops the dataflow from making progress (see
+/// `RunningProgram::worker_failure`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerFailure {
+    /// Index of the worker that failed first.
+    pub worker: usize,
+    /// The error returned by the worker, or the payload of its panic.
+    pub message: String,
+    /// Whether the worker panicked rather than returning an error.
+    pub panicked: bool,
+}
+
+impl WorkerFailure {
+    /// Failure of worker `worker` that panicked with `payload`.
+    pub(crate) fn panic(worker: usize, payload: &(dyn Any + Send)) -> Self {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            (*message).to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic payload".to_string()
+        };
+
+        Self {
+            worker,
+            message,
+            panicked: true,
+        }
+    }
+}
+
+impl fmt::Display for WorkerFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.panicked {
+            write!(f, "worker {} panicked: {}", self.worker, self.message)
+        } else {
+            write!(f, "worker {} failed: {}", self.worker, self.message)
+        }
+    }
+}
+
+impl From<WorkerFailure> for DDlogError {
+    fn from(failure: WorkerFailure) -> Self {
+        let message = if failure.panicked {
+            format!("panicked: {}", failure.message)
+        } else {
+            failure.message
+        };
+        DDlogError::worker_failure(Some(failure.worker), message)
+    }
+}
+
+/// The first failure of any worker of a running program, shared by the workers and the
+/// `RunningProgram`.
+#[derive(Debug, Default)]
+pub(crate) struct FailureState {
+    /// Set once `failure` is, so that workers can poll it cheaply.
+    failed: AtomicBool,
+    failure: Mutex<Option<WorkerFailure>>,
+    /// Set when the program stops waiting for the workers to make progress, e.g., because a
+    /// commit was aborted, so that they can stop without completing the computation.
+    abandoned: AtomicBool,
+}
+
+impl FailureState {
+    /// Record `failure`, unless another failure was recorded before.  Returns whether it was
+    /// recorded.
+    pub(crate) fn record(&self, failure: WorkerFailure) -> bool {
+        let mut first = self.failure.lock().unwrap_or_else(PoisonError::into_inner);
+        if first.is_some() {
+            return false;
+        }
+
+        *first = Some(failure);
+        self.failed.store(true, Ordering::SeqCst);
+        true
+    }
+
+    /// Whether a failure was recorded.
+    pub(crate) fn failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    /// Let the workers stop without completing the computation in progress.
+    pub(crate) fn abandon(&self) {
+        self.abandoned.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the workers may stop without completing the computation in progress.
+    pub(crate) fn abandoned(&self) -> bool {
+        self.abandoned.load(Ordering::SeqCst)
+    }
+
+    /// The recorded failure, if any.
+    pub(crate) fn get(&self) -> Option<WorkerFailure> {
+        if !self.failed() {
+            return None;
+        }
+        self.failure
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FailureState, WorkerFailure};
+    use crate::error::DDlogError;
+    use std::panic;
+
+    #[test]
+    fn first_failure_wins() {
+        let state = FailureState::default();
+        assert!(!state.failed());
+        assert_eq!(state.get(), None);
+
+        let payload = panic::catch_unwind(|| panic!("index {} out of bounds", 3)).unwrap_err();
+        let failure = WorkerFailure::panic(1, &*payload);
+        assert_eq!(
+            failure.to_string(),
+            "worker 1 panicked: index 3 out of bounds"
+        );
+        assert!(state.record(failure.clone()));
+
+        assert!(!state.record(WorkerFailure {
+            worker: 0,
+            message: "another worker failed".to_string(),
+            panicked: false,
+        }));
+        assert!(state.failed());
+        assert_eq!(state.get(), Some(failure.clone()));
+
+        let error = DDlogError::from(failure);
+        assert_eq!(error.code(), -6);
+        assert_eq!(
+            error.to_string(),
+            "worker 1: panicked: index 3 out of bounds"
+        );
+    }
+}
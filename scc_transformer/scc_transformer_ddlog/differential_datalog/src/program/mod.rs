@@ -13,20 +13,29 @@
 // TODO: single input relation
 
 pub mod arrange;
+mod cancel;
 pub mod config;
+mod failure;
+mod page;
+mod savepoint;
 mod timestamp;
 mod update;
 mod worker;
 
 pub use arrange::diff_distinct;
+pub use cancel::CancellationToken;
 pub use config::{Config, ProfilingConfig};
+pub use failure::WorkerFailure;
+pub use page::{ArrangementCursor, ArrangementPage};
 pub use timestamp::{TSNested, TupleTS, TS};
 pub use update::Update;
 
 use crate::{
+    checkpoint::CheckpointWriter,
     ddval::*,
+    error::DDlogError,
     profile::*,
-    record::Mutator,
+    record::{Mutator, Record},
     render::{
         arrange_by::{ArrangeBy, ArrangementKind},
         RenderContext,
@@ -35,22 +44,29 @@ use crate::{
 use arrange::{
     antijoin_arranged, Arrangement as DataflowArrangement, ArrangementFlavor, Arrangements,
 };
+use cancel::Cancellation;
 use config::SelfProfilingRig;
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{Receiver, RecvError, Sender};
+use failure::FailureState;
 use fnv::{FnvHashMap, FnvHashSet};
+use savepoint::Savepoint;
 use std::{
     any::Any,
     borrow::Cow,
     cmp,
     collections::{hash_map, BTreeSet},
     fmt::{self, Debug, Formatter},
+    io,
     iter::{self, Cycle, Skip},
+    mem,
     ops::Range,
+    panic::{self, AssertUnwindSafe},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 use timestamp::ToTupleTS;
 use triomphe::Arc as ThinArc;
@@ -92,7 +108,7 @@ pub type Weight = i32;
 const PROF_MSG_BUF_SIZE: usize = 10_000;
 
 /// Result type returned by this library
-pub type Response<X> = Result<X, String>;
+pub type Response<X> = Result<X, DDlogError>;
 
 /// Unique identifier of a DDlog relation.
 // TODO: Newtype this for type-safety
@@ -886,6 +902,15 @@ pub struct RunningProgram {
     /// Profiling statistics.
     pub profile: Option<ThinArc<Mutex<Profile>>>,
     worker_round_robbin: Skip<Cycle<Range<usize>>>,
+    /// The program and configuration the dataflow was started with, kept to restart it.
+    program: Arc<Program>,
+    config: Config,
+    /// Failure of a worker thread, shared with the workers.
+    failure: Arc<FailureState>,
+    /// Receives the cancellations of commits.
+    cancellation: Cancellation,
+    /// Savepoints of the transaction in progress, oldest first.
+    savepoints: Vec<Savepoint>,
 }
 
 // Right now this Debug implementation is more or less a short cut.
@@ -906,6 +931,7 @@ impl Debug for RunningProgram {
             .field("profile_timely", &self.profile_timely)
             .field("prof_thread_handle", &self.prof_thread_handle)
             .field("profile", &self.profile)
+            .field("failure", &self.failure)
             .finish()
     }
 }
@@ -958,6 +984,54 @@ impl RelationInstance {
             RelationInstance::Indexed { delta, .. } => delta,
         }
     }
+
+    /// Undo the changes recorded in the delta set by applying their inverse to the contents
+    /// of the relation, without sending them to the workers, and clear the delta set.
+    fn rollback(&mut self) {
+        match self {
+            RelationInstance::Stream { delta } => delta.clear(),
+            RelationInstance::Multiset { elements, delta } => {
+                for (v, w) in delta.drain() {
+                    match elements.entry(v) {
+                        hash_map::Entry::Occupied(mut oe) => {
+                            *oe.get_mut() -= w;
+                            if *oe.get() == 0 {
+                                oe.remove_entry();
+                            }
+                        }
+                        hash_map::Entry::Vacant(ve) => {
+                            ve.insert(-w);
+                        }
+                    }
+                }
+            }
+            RelationInstance::Flat { elements, delta } => {
+                for (v, w) in delta.drain() {
+                    if w > 0 {
+                        elements.remove(&v);
+                    } else if w < 0 {
+                        elements.insert(v);
+                    }
+                }
+            }
+            RelationInstance::Indexed {
+                key_func,
+                elements,
+                delta,
+            } => {
+                // First remove the inserted values, then restore the deleted ones, which may
+                // have the same keys.
+                let (deleted, inserted): (Vec<_>, Vec<_>) =
+                    delta.drain().partition(|(_, w)| *w < 0);
+                for (v, _) in inserted {
+                    elements.remove(&key_func(&v));
+                }
+                for (v, _) in deleted {
+                    elements.insert(key_func(&v), v);
+                }
+            }
+        }
+    }
 }
 
 /// Messages sent to timely worker threads.
@@ -975,14 +1049,49 @@ enum Msg {
         /// The timestamp to advance to.
         advance_to: TS,
     },
-    /// Query arrangement.  If the second argument is `None`, returns
-    /// all values in the collection; otherwise returns values associated
-    /// with the specified key.
-    Query(ArrId, Option<DDValue>),
+    /// Query arrangement, returning the values associated with the selected keys.
+    Query(ArrId, KeyQuery),
+    /// Count the values associated with the selected keys.
+    Count(ArrId, KeyQuery),
+    /// Return up to `limit` entries of the arrangement that follow `after`.
+    QueryPage {
+        arrid: ArrId,
+        after: Option<ArrangementCursor>,
+        limit: usize,
+    },
     /// Stop worker.
     Stop,
 }
 
+/// Keys of an arrangement selected by a query.
+#[derive(Debug, Clone)]
+pub enum KeyQuery {
+    /// All keys.
+    All,
+    /// A single key.
+    Key(DDValue),
+    /// Keys in `[lo, hi)`, where a missing bound leaves the range unbounded on that side.
+    Range {
+        lo: Option<DDValue>,
+        hi: Option<DDValue>,
+    },
+    /// Tuple or struct keys whose leading fields equal the given records (see
+    /// `record::record_has_prefix`).  Such keys are adjacent in the arrangement, so the scan
+    /// seeks to the first one (see `record::prefix_lower_bound`) and stops after the last one.
+    Prefix(Vec<Record>, KeyConverter),
+}
+
+/// Converts records into keys of an arrangement, or returns `None` for records that aren't keys
+/// of its type.
+#[derive(Clone)]
+pub struct KeyConverter(pub Arc<dyn Fn(&Record) -> Option<DDValue> + Send + Sync>);
+
+impl Debug for KeyConverter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("KeyConverter")
+    }
+}
+
 /// Reply messages from timely worker threads.
 #[derive(Debug)]
 enum Reply {
@@ -990,11 +1099,18 @@ enum Reply {
     FlushAck,
     /// Result of a query.
     QueryRes(Option<BTreeSet<DDValue>>),
+    /// Result of a count query.
+    CountRes(Option<usize>),
+    /// Result of a page query: the worker's next entries in order, and whether it has more.
+    QueryPageRes(Option<(Vec<ArrangementCursor>, bool)>),
+    /// A worker failed, so the reply to the last request may never arrive.  Sent to all
+    /// workers' channels by the first worker that fails.
+    WorkerFailed,
 }
 
 impl Program {
     /// Initialize the program with the given configuration
-    pub fn run(&self, config: Config) -> Result<RunningProgram, String> {
+    pub fn run(&self, config: Config) -> Response<RunningProgram> {
         // Setup channels to communicate with the dataflow.
         // We use async channels to avoid deadlocks when workers are parked in
         // `step_or_park`.  This has the downside of introducing an unbounded buffer
@@ -1014,14 +1130,19 @@ impl Program {
 
         // Clone the program so that it can be moved into the timely computation
         let program = Arc::new(self.clone());
-        let timely_config = config.timely_config()?;
+        let timely_config = config.timely_config().map_err(DDlogError::Other)?;
+        let worker_program = Arc::clone(&program);
         let worker_config = config.clone();
         let profiling_data = profiling_rig.profiling_data.clone();
+        let failure = Arc::new(FailureState::default());
+        let worker_failure = Arc::clone(&failure);
 
-        let (builders, others) = timely_config
-            .communication
-            .try_build()
-            .map_err(|err| format!("failed to build timely communication config: {}", err))?;
+        let (builders, others) = timely_config.communication.try_build().map_err(|err| {
+            DDlogError::Other(format!(
+                "failed to build timely communication config: {}",
+                err
+            ))
+        })?;
 
         // Start up timely computation.
         // Note: We use `execute_from()` instead of `timely::execute()` because
@@ -1035,25 +1156,53 @@ impl Program {
             others,
             timely_config.worker,
             move |worker: &mut Worker<Allocator>| -> Result<_, String> {
+                let worker_index = worker.index();
                 let logger = worker.log_register().get("timely");
 
-                let worker = DDlogWorker::new(
-                    worker,
-                    worker_config.clone(),
-                    program.clone(),
-                    profiling_data.clone(),
-                    Arc::clone(&request_recv),
-                    Arc::clone(&reply_send),
-                    logger,
-                );
-
-                worker.run().map_err(|e| {
-                    eprintln!("Worker thread failed: {}", e);
-                    e
-                })
+                // Catch panics, so that they are reported to the client like other failures
+                // rather than leaving it waiting for a reply that never arrives.
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    DDlogWorker::new(
+                        worker,
+                        worker_config.clone(),
+                        worker_program.clone(),
+                        profiling_data.clone(),
+                        Arc::clone(&request_recv),
+                        Arc::clone(&reply_send),
+                        logger,
+                        Arc::clone(&worker_failure),
+                    )
+                    .run()
+                }));
+
+                let failure = match result {
+                    Ok(Ok(())) => return Ok(()),
+                    Ok(Err(message)) => WorkerFailure {
+                        worker: worker_index,
+                        message,
+                        panicked: false,
+                    },
+                    Err(payload) => WorkerFailure::panic(worker_index, &*payload),
+                };
+
+                let message = failure.to_string();
+                // Only the first failure is reported; the other workers fail because of it.
+                // Failures while the program abandons the dataflow don't matter.
+                if !worker_failure.abandoned() && worker_failure.record(failure) {
+                    eprintln!("Worker thread failed: {}", message);
+                    for sender in reply_send.iter() {
+                        let _ = sender.send(Reply::WorkerFailed);
+                    }
+                }
+                Err(message)
             },
         )
-        .map_err(|err| format!("Failed to start timely computation: {:?}", err))?;
+        .map_err(|err| {
+            DDlogError::worker_failure(
+                None,
+                format!("Failed to start timely computation: {:?}", err),
+            )
+        })?;
 
         let mut rels = FnvHashMap::default();
         for relid in self.input_relations() {
@@ -1115,6 +1264,11 @@ impl Program {
             prof_thread_handle: profiling_rig.profile_thread,
             profile: profiling_rig.profile,
             worker_round_robbin: (0..config.num_timely_workers).cycle().skip(0),
+            program,
+            config,
+            failure,
+            cancellation: Cancellation::new(),
+            savepoints: Vec::new(),
         };
         // Wait for the initial transaction to complete.
         running_program.await_flush_ack()?;
@@ -1986,13 +2140,20 @@ impl RunningProgram {
         // TODO: Log warning if self profiling is disabled
     }
 
-    /// Terminate program, killing all worker threads.
+    /// Terminate program, killing all worker threads.  Fails with the worker failure, if a
+    /// worker failed.
     pub fn stop(&mut self) -> Response<()> {
         if self.worker_guards.is_none() {
             // Already stopped.
             return Ok(());
         }
 
+        if let Some(failure) = self.worker_failure() {
+            // The dataflow can't make progress, so stop the remaining workers without flushing.
+            self.stop_workers();
+            return Err(failure.into());
+        }
+
         self.flush()
             .and_then(|_| self.broadcast(Msg::Stop))
             .and_then(|_| {
@@ -2000,8 +2161,13 @@ impl RunningProgram {
                     worker_guards
                         .join()
                         .into_iter()
-                        .filter_map(Result::err)
-                        .next()
+                        .enumerate()
+                        .find_map(|(worker_index, result)| {
+                            result
+                                .and_then(|result| result)
+                                .err()
+                                .map(|e| DDlogError::worker_failure(Some(worker_index), e))
+                        })
                         .map_or(Ok(()), Err)
                 })
             })?;
@@ -2009,41 +2175,250 @@ impl RunningProgram {
         Ok(())
     }
 
+    /// The failure of a worker thread, if one failed.  Once a worker fails, the dataflow can't
+    /// make progress and all operations that involve the workers fail, until it is restarted
+    /// with `restart`.
+    pub fn worker_failure(&self) -> Option<WorkerFailure> {
+        self.failure.get()
+    }
+
+    /// Whether a transaction is in progress.  A commit that fails because a worker failed leaves
+    /// its transaction in progress, whereas an aborted commit rolls it back.
+    pub fn transaction_in_progress(&self) -> bool {
+        self.transaction_in_progress
+    }
+
+    /// Whether the dataflow must be restarted with `restart` before it can be used again,
+    /// because a worker failed, a commit was aborted, or the program was stopped.
+    pub fn needs_restart(&self) -> bool {
+        self.worker_guards.is_none() || self.failure.failed()
+    }
+
+    /// Restart the dataflow after a worker failure or an aborted commit (see
+    /// `needs_restart`).  The remaining workers are stopped, and a new dataflow, with the same
+    /// program and configuration, is fed the contents of all input relations as of the last
+    /// commit, which the program retains.  Stream relations retain nothing, so they start out
+    /// empty.  The transaction in progress, if any, is discarded.
+    ///
+    /// Output relations are computed from scratch, so the updates produced by the restart are
+    /// their complete contents rather than changes.  Fails if the dataflow is running.
+    pub fn restart(&mut self) -> Response<()> {
+        if !self.needs_restart() {
+            return Err(DDlogError::other("restart: the dataflow is running"));
+        }
+
+        self.stop_workers();
+
+        let mut updates = Vec::new();
+        for (relid, rel) in self.relations.iter_mut() {
+            rel.rollback();
+            Self::contents_updates(*relid, rel, &mut updates);
+        }
+
+        let mut restarted = self.program.run(self.config.clone())?;
+        restarted.transaction_start()?;
+        restarted.apply_updates(updates.into_iter(), |_| Ok(()))?;
+        restarted.transaction_commit()?;
+
+        // Tokens handed out so far cancel the commits of the restarted dataflow.
+        mem::swap(&mut restarted.cancellation, &mut self.cancellation);
+        *self = restarted;
+        Ok(())
+    }
+
+    /// Token that cancels commits of this program from other threads (see
+    /// `transaction_commit_with_deadline`).  Tokens remain valid after `restart`.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.token()
+    }
+
     /// Start a transaction. Does not return a transaction handle, as there
     /// can be at most one transaction in progress at any given time. Fails
     /// if there is already a transaction in progress.
     pub fn transaction_start(&mut self) -> Response<()> {
         if self.transaction_in_progress {
-            return Err("transaction already in progress".to_string());
+            return Err(DDlogError::TransactionInProgress("transaction_start"));
         }
 
+        // Cancellations only apply to the transaction in progress.
+        self.cancellation.clear();
         self.transaction_in_progress = true;
         Ok(())
     }
 
-    /// Commit a transaction.
+    /// Commit a transaction.  The commit can be cancelled with a `CancellationToken`, see
+    /// `transaction_commit_with_deadline`.
     pub fn transaction_commit(&mut self) -> Response<()> {
+        self.commit(None, true)
+    }
+
+    /// Commit a transaction, ignoring cancellations, for commits that must not be left
+    /// half-done (see `HDDlog::evaluate_hypothetical`).
+    pub(crate) fn transaction_commit_uncancellable(&mut self) -> Response<()> {
+        self.commit(None, false)
+    }
+
+    /// Commit a transaction, aborting the commit if it doesn't complete within `timeout` or is
+    /// cancelled with a `CancellationToken` (see `cancellation_token`).  An aborted commit
+    /// fails with `DDlogError::Timeout` or `DDlogError::Cancelled`.
+    ///
+    /// Workers can't be interrupted in the middle of a computation, so aborting a commit stops
+    /// them, once they complete their current step, and rolls back the changes of the
+    /// transaction by applying their inverse to the retained contents of input relations.  The
+    /// dataflow must then be restarted with `restart`, which recomputes output relations.
+    pub fn transaction_commit_with_deadline(&mut self, timeout: Duration) -> Response<()> {
+        // A deadline too far in the future to represent never expires.
+        self.commit(Instant::now().checked_add(timeout), true)
+    }
+
+    fn commit(&mut self, deadline: Option<Instant>, cancellable: bool) -> Response<()> {
         if !self.transaction_in_progress {
-            return Err("transaction_commit: no transaction in progress".to_string());
+            return Err(DDlogError::NoTransaction("transaction_commit"));
+        }
+
+        if self.need_to_flush {
+            self.broadcast(Msg::Flush {
+                advance_to: self.timestamp + 1,
+            })?;
+            self.timestamp += 1;
+            self.need_to_flush = false;
+
+            match self.await_flush_ack_until(deadline, cancellable) {
+                Ok(()) => (),
+                Err(e @ DDlogError::Timeout(_)) | Err(e @ DDlogError::Cancelled(_)) => {
+                    self.abort_commit();
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            }
         }
 
-        self.flush()?;
         self.delta_cleanup();
+        self.savepoints.clear();
         self.transaction_in_progress = false;
         Ok(())
     }
 
+    /// Abort the commit in progress: stop the workers without waiting for them to complete it,
+    /// and roll back the changes of the transaction in the contents of input relations.
+    fn abort_commit(&mut self) {
+        self.failure.abandon();
+        self.stop_workers();
+        for rel in self.relations.values_mut() {
+            rel.rollback();
+        }
+        self.savepoints.clear();
+        self.transaction_in_progress = false;
+    }
+
+    /// Write the contents of all input relations to a checkpoint (see `crate::checkpoint`) and
+    /// return the writer.  Fails if a transaction is in progress, as the checkpoint would
+    /// contain uncommitted changes.
+    pub fn checkpoint<W: io::Write>(&self, writer: W) -> Response<W> {
+        if self.transaction_in_progress {
+            return Err(DDlogError::TransactionInProgress("checkpoint"));
+        }
+
+        let mut checkpoint = CheckpointWriter::new(writer)?;
+        let mut relids: Vec<RelId> = self.relations.keys().cloned().collect();
+        relids.sort_unstable();
+
+        for relid in relids {
+            match &self.relations[&relid] {
+                RelationInstance::Stream { .. } => {}
+                RelationInstance::Multiset { elements, .. } => {
+                    for (v, weight) in elements.iter() {
+                        for _ in 0..weight.abs() {
+                            checkpoint.write(*weight > 0, relid, v)?;
+                        }
+                    }
+                }
+                RelationInstance::Flat { elements, .. } => {
+                    for v in elements.iter() {
+                        checkpoint.write(true, relid, v)?;
+                    }
+                }
+                RelationInstance::Indexed { elements, .. } => {
+                    for v in elements.values() {
+                        checkpoint.write(true, relid, v)?;
+                    }
+                }
+            }
+        }
+
+        checkpoint.finish()
+    }
+
     /// Rollback the transaction, undoing all changes.
     pub fn transaction_rollback(&mut self) -> Response<()> {
         if !self.transaction_in_progress {
-            return Err("transaction_rollback: no transaction in progress".to_string());
+            return Err(DDlogError::NoTransaction("transaction_rollback"));
         }
 
         self.flush().and_then(|_| self.delta_undo()).map(|_| {
+            self.savepoints.clear();
             self.transaction_in_progress = false;
         })
     }
 
+    /// Create a savepoint named `name` in the transaction in progress, which `rollback_to` can
+    /// later return to.  Savepoints nest: if several have the same name, the latest one is used.
+    pub fn savepoint(&mut self, name: &str) -> Response<()> {
+        if !self.transaction_in_progress {
+            return Err(DDlogError::NoTransaction("savepoint"));
+        }
+
+        let deltas = self
+            .relations
+            .iter()
+            .map(|(relid, rel)| (*relid, rel.delta()));
+        let savepoint = Savepoint::new(name, deltas);
+        self.savepoints.push(savepoint);
+        Ok(())
+    }
+
+    /// Undo the changes the transaction made to input relations since savepoint `name` was
+    /// created, discarding the savepoints created after it.  The savepoint itself is kept, so
+    /// the transaction can return to it again.
+    pub fn rollback_to(&mut self, name: &str) -> Response<()> {
+        if !self.transaction_in_progress {
+            return Err(DDlogError::NoTransaction("rollback_to"));
+        }
+
+        let position = self
+            .savepoints
+            .iter()
+            .rposition(|savepoint| savepoint.name == name)
+            .ok_or_else(|| DDlogError::UnknownSavepoint(name.to_string()))?;
+        self.savepoints.truncate(position + 1);
+
+        let savepoint = &self.savepoints[position];
+        let mut updates = Vec::new();
+        for (relid, rel) in &self.relations {
+            let changes = savepoint.changes_since(*relid, rel.delta());
+            Self::delta_undo_updates(*relid, &changes, &mut updates);
+        }
+
+        self.apply_updates(updates.into_iter(), |_| Ok(()))
+    }
+
+    /// Updates that restore the contents of input relations as of the start of the transaction
+    /// in progress, to be applied once it is committed.  Changes to stream relations don't
+    /// outlive the commit, so they are not undone.
+    pub fn transaction_undo_updates(&self) -> Response<Vec<Update<DDValue>>> {
+        if !self.transaction_in_progress {
+            return Err(DDlogError::NoTransaction("transaction_undo_updates"));
+        }
+
+        let mut updates = Vec::new();
+        for (relid, rel) in &self.relations {
+            if !matches!(rel, RelationInstance::Stream { .. }) {
+                Self::delta_undo_updates(*relid, rel.delta(), &mut updates);
+            }
+        }
+        Ok(updates)
+    }
+
     /// Insert one record into input relation. Relations have set semantics, i.e.,
     /// adding an existing record is a no-op.
     pub fn insert(&mut self, relid: RelId, v: DDValue) -> Response<()> {
@@ -2084,7 +2459,7 @@ impl RunningProgram {
         let rel = self
             .relations
             .get_mut(&update.relid())
-            .ok_or_else(|| format!("apply_update: unknown input relation {}", update.relid()))?;
+            .ok_or_else(|| DDlogError::UnknownRelation(update.relid().to_string()))?;
 
         match rel {
             RelationInstance::Stream { delta } => {
@@ -2112,7 +2487,7 @@ impl RunningProgram {
         F: Fn(&Update<DDValue>) -> Response<()>,
     {
         if !self.transaction_in_progress {
-            return Err("apply_updates: no transaction in progress".to_string());
+            return Err(DDlogError::NoTransaction("apply_updates"));
         }
 
         // Remove no-op updates to maintain set semantics
@@ -2148,18 +2523,20 @@ impl RunningProgram {
     /// Deletes all values in an input table
     pub fn clear_relation(&mut self, relid: RelId) -> Response<()> {
         if !self.transaction_in_progress {
-            return Err("clear_relation: no transaction in progress".to_string());
+            return Err(DDlogError::NoTransaction("clear_relation"));
         }
 
         let updates = {
             let rel = self
                 .relations
                 .get_mut(&relid)
-                .ok_or_else(|| format!("clear_relation: unknown input relation {}", relid))?;
+                .ok_or_else(|| DDlogError::UnknownRelation(relid.to_string()))?;
 
             match rel {
                 RelationInstance::Stream { .. } => {
-                    return Err("clear_relation: operation not supported for streams".to_string())
+                    return Err(DDlogError::other(
+                        "clear_relation: operation not supported for streams",
+                    ))
                 }
                 RelationInstance::Multiset { elements, .. } => {
                     let mut updates: Vec<Update<DDValue>> = Vec::with_capacity(elements.len());
@@ -2197,32 +2574,124 @@ impl RunningProgram {
 
     /// Returns all values in the arrangement with the specified key.
     pub fn query_arrangement(&mut self, arrid: ArrId, k: DDValue) -> Response<BTreeSet<DDValue>> {
-        self._query_arrangement(arrid, Some(k))
+        self._query_arrangement(arrid, KeyQuery::Key(k))
+    }
+
+    /// Returns all values in the arrangement with keys in `[lo, hi)`.
+    pub fn query_arrangement_range(
+        &mut self,
+        arrid: ArrId,
+        lo: Option<DDValue>,
+        hi: Option<DDValue>,
+    ) -> Response<BTreeSet<DDValue>> {
+        self._query_arrangement(arrid, KeyQuery::Range { lo, hi })
+    }
+
+    /// Returns all values in the arrangement whose keys start with `prefix`.  `key_converter`
+    /// converts records into keys of the arrangement.
+    pub fn query_arrangement_prefix(
+        &mut self,
+        arrid: ArrId,
+        prefix: Vec<Record>,
+        key_converter: KeyConverter,
+    ) -> Response<BTreeSet<DDValue>> {
+        self._query_arrangement(arrid, KeyQuery::Prefix(prefix, key_converter))
     }
 
     /// Returns the entire content of an arrangement.
     pub fn dump_arrangement(&mut self, arrid: ArrId) -> Response<BTreeSet<DDValue>> {
-        self._query_arrangement(arrid, None)
+        self._query_arrangement(arrid, KeyQuery::All)
     }
 
-    fn _query_arrangement(
+    /// Returns the number of values in the arrangement with keys selected by `query`.  The values
+    /// are counted by the workers and never leave them.
+    pub fn count_arrangement(&mut self, arrid: ArrId, query: KeyQuery) -> Response<usize> {
+        self.broadcast(Msg::Count(arrid, query))?;
+
+        let mut count = 0;
+        let mut unknown = false;
+        for worker_index in 0..self.reply_recv.len() {
+            let reply = self.recv_reply(worker_index, "count_arrangement")?;
+
+            match reply {
+                Reply::CountRes(Some(worker_count)) => count += worker_count,
+                Reply::CountRes(None) => {
+                    unknown = true;
+                }
+                repl => {
+                    return Err(DDlogError::worker_failure(
+                        Some(worker_index),
+                        format!("count_arrangement: unexpected reply: {:?}", repl),
+                    ));
+                }
+            }
+        }
+
+        if unknown {
+            Err(DDlogError::UnknownIndex(format!("{:?}", arrid)))
+        } else {
+            Ok(count)
+        }
+    }
+
+    /// Returns up to `limit` values of an arrangement, starting after the entry at `after`, or
+    /// at the beginning of the arrangement.  Pass the returned `next` cursor to fetch the
+    /// following page.
+    ///
+    /// Each worker only sends its next `limit` entries, so the whole arrangement is never held
+    /// in memory at once.
+    pub fn dump_arrangement_page(
         &mut self,
         arrid: ArrId,
-        k: Option<DDValue>,
-    ) -> Response<BTreeSet<DDValue>> {
+        after: Option<ArrangementCursor>,
+        limit: usize,
+    ) -> Response<ArrangementPage> {
+        if limit == 0 {
+            return Err(DDlogError::other(
+                "dump_arrangement_page: page limit must be positive",
+            ));
+        }
+        self.broadcast(Msg::QueryPage {
+            arrid,
+            after,
+            limit,
+        })?;
+
+        let mut replies = Vec::with_capacity(self.reply_recv.len());
+        let mut unknown = false;
+        for worker_index in 0..self.reply_recv.len() {
+            let reply = self.recv_reply(worker_index, "dump_arrangement_page")?;
+
+            match reply {
+                Reply::QueryPageRes(Some(reply)) => replies.push(reply),
+                Reply::QueryPageRes(None) => {
+                    unknown = true;
+                }
+                repl => {
+                    return Err(DDlogError::worker_failure(
+                        Some(worker_index),
+                        format!("dump_arrangement_page: unexpected reply: {:?}", repl),
+                    ));
+                }
+            }
+        }
+
+        if unknown {
+            Err(DDlogError::UnknownIndex(format!("{:?}", arrid)))
+        } else {
+            Ok(ArrangementPage::merge(replies, limit))
+        }
+    }
+
+    fn _query_arrangement(&mut self, arrid: ArrId, query: KeyQuery) -> Response<BTreeSet<DDValue>> {
         // Send query and receive replies from all workers. If a key is specified, then at most
         // one worker will send a non-empty reply.
-        self.broadcast(Msg::Query(arrid, k))?;
+        self.broadcast(Msg::Query(arrid, query))?;
 
         let mut res: BTreeSet<DDValue> = BTreeSet::new();
         let mut unknown = false;
-        for (worker_index, chan) in self.reply_recv.iter().enumerate() {
-            let reply = chan.recv().map_err(|e| {
-                format!(
-                    "query_arrangement: failed to receive reply from worker {}: {:?}",
-                    worker_index, e
-                )
-            })?;
+        for worker_index in 0..self.reply_recv.len() {
+            let reply = self.recv_reply(worker_index, "query_arrangement")?;
 
             match reply {
                 Reply::QueryRes(Some(mut vals)) => {
@@ -2238,16 +2707,16 @@ impl RunningProgram {
                     unknown = true;
                 }
                 repl => {
-                    return Err(format!(
-                        "query_arrangement: unexpected reply from worker {}: {:?}",
-                        worker_index, repl
+                    return Err(DDlogError::worker_failure(
+                        Some(worker_index),
+                        format!("query_arrangement: unexpected reply: {:?}", repl),
                     ));
                 }
             }
         }
 
         if unknown {
-            Err(format!("query_arrangement: unknown index: {:?}", arrid))
+            Err(DDlogError::UnknownIndex(format!("{:?}", arrid)))
         } else {
             Ok(res)
         }
@@ -2311,22 +2780,22 @@ impl RunningProgram {
                 Self::delta_dec(ds, v);
             }
             Update::InsertOrUpdate { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot perform insert_or_update operation on relation {} that does not have a primary key",
                     relid,
-                ));
+                )));
             }
             Update::DeleteKey { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot delete by key from relation {} that does not have a primary key",
                     relid,
-                ));
+                )));
             }
             Update::Modify { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot modify record in relation {} that does not have a primary key",
                     relid,
-                ));
+                )));
             }
         };
         updates.push(update);
@@ -2356,22 +2825,22 @@ impl RunningProgram {
                 Self::delta_dec(ds, v);
             }
             Update::InsertOrUpdate { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot perform insert_or_update operation on relation {} that does not have a primary key",
                     relid
-                ));
+                )));
             }
             Update::DeleteKey { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot delete by key from relation {} that does not have a primary key",
                     relid
-                ));
+                )));
             }
             Update::Modify { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot modify record in relation {} that does not have a primary key",
                     relid
-                ));
+                )));
             }
         };
         updates.push(upd);
@@ -2409,22 +2878,22 @@ impl RunningProgram {
                 present
             }
             Update::InsertOrUpdate { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot perform insert_or_update operation on relation {} that does not have a primary key",
                     relid,
-                ));
+                )));
             }
             Update::DeleteKey { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot delete by key from relation {} that does not have a primary key",
                     relid,
-                ));
+                )));
             }
             Update::Modify { relid, .. } => {
-                return Err(format!(
+                return Err(DDlogError::Other(format!(
                     "Cannot modify record in relation {} that does not have a primary key",
                     relid,
-                ));
+                )));
             }
         };
 
@@ -2456,11 +2925,11 @@ impl RunningProgram {
     ) -> Response<()> {
         match upd {
             Update::Insert { relid, v } => match s.entry(key_func(&v)) {
-                hash_map::Entry::Occupied(_) => Err(format!(
+                hash_map::Entry::Occupied(_) => Err(DDlogError::Other(format!(
                     "Insert: duplicate key '{:?}' in value '{:?}'",
                     key_func(&v),
                     v
-                )),
+                ))),
                 hash_map::Entry::Vacant(ve) => {
                     ve.insert(v.clone());
                     Self::delta_inc(ds, &v);
@@ -2501,7 +2970,7 @@ impl RunningProgram {
             Update::DeleteValue { relid, v } => match s.entry(key_func(&v)) {
                 hash_map::Entry::Occupied(oe) => {
                     if *oe.get() != v {
-                        Err(format!("DeleteValue: key exists but with a different value. Value specified: '{:?}'; existing value: '{:?}'", v, oe.get()))
+                        Err(DDlogError::Other(format!("DeleteValue: key exists but with a different value. Value specified: '{:?}'; existing value: '{:?}'", v, oe.get())))
                     } else {
                         Self::delta_dec(ds, oe.get());
                         oe.remove_entry();
@@ -2509,9 +2978,10 @@ impl RunningProgram {
                         Ok(())
                     }
                 }
-                hash_map::Entry::Vacant(_) => {
-                    Err(format!("DeleteValue: key not found '{:?}'", key_func(&v)))
-                }
+                hash_map::Entry::Vacant(_) => Err(DDlogError::Other(format!(
+                    "DeleteValue: key not found '{:?}'",
+                    key_func(&v)
+                ))),
             },
 
             Update::DeleteKey { relid, k } => match s.entry(k.clone()) {
@@ -2522,14 +2992,17 @@ impl RunningProgram {
                     updates.push(Update::DeleteValue { relid, v: old });
                     Ok(())
                 }
-                hash_map::Entry::Vacant(_) => Err(format!("DeleteKey: key not found '{:?}'", k)),
+                hash_map::Entry::Vacant(_) => Err(DDlogError::Other(format!(
+                    "DeleteKey: key not found '{:?}'",
+                    k
+                ))),
             },
 
             Update::Modify { relid, k, m } => match s.entry(k.clone()) {
                 hash_map::Entry::Occupied(mut oe) => {
                     let new = oe.get_mut();
                     let old: DDValue = (*new).clone();
-                    m.mutate(new)?;
+                    m.mutate(new).map_err(DDlogError::TypeMismatch)?;
                     Self::delta_dec(ds, &old);
                     updates.push(Update::DeleteValue { relid, v: old });
                     Self::delta_inc(ds, &new);
@@ -2540,7 +3013,10 @@ impl RunningProgram {
 
                     Ok(())
                 }
-                hash_map::Entry::Vacant(_) => Err(format!("Modify: key not found '{:?}'", k)),
+                hash_map::Entry::Vacant(_) => Err(DDlogError::Other(format!(
+                    "Modify: key not found '{:?}'",
+                    k
+                ))),
             },
         }
     }
@@ -2550,9 +3026,12 @@ impl RunningProgram {
     /// made by the current transaction.
     pub fn get_input_relation_index(&self, relid: RelId) -> Response<&IndexedValSet> {
         match self.relations.get(&relid) {
-            None => Err(format!("unknown relation {}", relid)),
+            None => Err(DDlogError::UnknownRelation(relid.to_string())),
             Some(RelationInstance::Indexed { elements, .. }) => Ok(elements),
-            Some(_) => Err(format!("not an indexed relation {}", relid)),
+            Some(_) => Err(DDlogError::UnknownRelation(format!(
+                "{} (not an indexed input relation)",
+                relid
+            ))),
         }
     }
 
@@ -2561,9 +3040,12 @@ impl RunningProgram {
     /// made by the current transaction.
     pub fn get_input_relation_data(&self, relid: RelId) -> Response<&ValSet> {
         match self.relations.get(&relid) {
-            None => Err(format!("unknown relation {}", relid)),
+            None => Err(DDlogError::UnknownRelation(relid.to_string())),
             Some(RelationInstance::Flat { elements, .. }) => Ok(elements),
-            Some(_) => Err(format!("not a flat relation {}", relid)),
+            Some(_) => Err(DDlogError::UnknownRelation(format!(
+                "{} (not a flat input relation)",
+                relid
+            ))),
         }
     }
 
@@ -2572,9 +3054,28 @@ impl RunningProgram {
     /// made by the current transaction.
     pub fn get_input_multiset_data(&self, relid: RelId) -> Response<&ValMSet> {
         match self.relations.get(&relid) {
-            None => Err(format!("unknown relation {}", relid)),
+            None => Err(DDlogError::UnknownRelation(relid.to_string())),
             Some(RelationInstance::Multiset { elements, .. }) => Ok(elements),
-            Some(_) => Err(format!("not a flat relation {}", relid)),
+            Some(_) => Err(DDlogError::UnknownRelation(format!(
+                "{} (not an input multiset)",
+                relid
+            ))),
+        }
+    }
+
+    /// Returns the number of distinct values in an input relation.
+    /// If called in the middle of a transaction, includes changes made by the current
+    /// transaction.
+    pub fn input_relation_size(&self, relid: RelId) -> Response<usize> {
+        match self.relations.get(&relid) {
+            None => Err(DDlogError::UnknownRelation(relid.to_string())),
+            Some(RelationInstance::Flat { elements, .. }) => Ok(elements.len()),
+            Some(RelationInstance::Multiset { elements, .. }) => Ok(elements.len()),
+            Some(RelationInstance::Indexed { elements, .. }) => Ok(elements.len()),
+            Some(RelationInstance::Stream { .. }) => Err(DDlogError::Other(format!(
+                "stream relation {} has no contents",
+                relid
+            ))),
         }
     }
 
@@ -2596,24 +3097,84 @@ impl RunningProgram {
 
     /// Send message to a worker thread.
     fn send(&self, worker_index: usize, msg: Msg) -> Response<()> {
+        if let Some(failure) = self.worker_failure() {
+            return Err(failure.into());
+        }
+
         match self.senders[worker_index].send(msg) {
             Ok(()) => {
                 // Worker may be blocked in `step_or_park`. Unpark it to ensure
                 // the message is received.
-                self.worker_guards.as_ref().unwrap().guards()[worker_index]
-                    .thread()
-                    .unpark();
+                let worker_guards = self.worker_guards.as_ref().ok_or_else(|| {
+                    DDlogError::worker_failure(None, "the dataflow is stopped, see `restart`")
+                })?;
+                worker_guards.guards()[worker_index].thread().unpark();
 
                 Ok(())
             }
 
-            Err(_) => Err(format!(
-                "failed to communicate with timely dataflow thread {}",
-                worker_index
+            Err(_) => Err(DDlogError::worker_failure(
+                Some(worker_index),
+                "failed to communicate with timely dataflow thread",
+            )),
+        }
+    }
+
+    /// Receive the next reply of a worker to `operation`.  Fails if any worker fails before the
+    /// reply arrives.
+    fn recv_reply(&self, worker_index: usize, operation: &str) -> Response<Reply> {
+        self.check_reply(
+            worker_index,
+            operation,
+            self.reply_recv[worker_index].recv(),
+        )
+    }
+
+    /// Check a reply received from a worker by `recv_reply` or a `select!`.
+    fn check_reply(
+        &self,
+        worker_index: usize,
+        operation: &str,
+        reply: Result<Reply, RecvError>,
+    ) -> Response<Reply> {
+        match reply {
+            Ok(Reply::WorkerFailed) => {
+                // Wake up the workers waiting for progress, so that they notice the failure.
+                self.unpark_workers();
+                Err(self.worker_failure().map_or_else(
+                    || DDlogError::worker_failure(None, format!("{}: worker failed", operation)),
+                    DDlogError::from,
+                ))
+            }
+            Ok(reply) => Ok(reply),
+            Err(e) => Err(DDlogError::worker_failure(
+                Some(worker_index),
+                format!("{}: failed to receive reply: {:?}", operation, e),
             )),
         }
     }
 
+    /// Stop the workers without flushing, and wait for them to exit.  Workers that are busy
+    /// exit once they notice the failure or abandonment of the dataflow.
+    fn stop_workers(&mut self) {
+        for sender in &self.senders {
+            let _ = sender.send(Msg::Stop);
+        }
+        self.unpark_workers();
+        if let Some(worker_guards) = self.worker_guards.take() {
+            let _ = worker_guards.join();
+        }
+    }
+
+    /// Unpark all worker threads, which may be blocked in `step_or_park`.
+    fn unpark_workers(&self) {
+        if let Some(worker_guards) = self.worker_guards.as_ref() {
+            for guard in worker_guards.guards() {
+                guard.thread().unpark();
+            }
+        }
+    }
+
     /// Broadcast message to all worker threads.
     fn broadcast(&self, msg: Msg) -> Response<()> {
         for worker_index in 0..self.senders.len() {
@@ -2656,6 +3217,42 @@ impl RunningProgram {
         }
     }
 
+    /// Updates that insert the contents of input relation `relid`.
+    fn contents_updates(relid: RelId, rel: &RelationInstance, updates: &mut Vec<Update<DDValue>>) {
+        match rel {
+            RelationInstance::Stream { .. } => {}
+            RelationInstance::Multiset { elements, .. } => {
+                for (v, w) in elements {
+                    for _ in 0..w.abs() {
+                        updates.push(if *w > 0 {
+                            Update::Insert {
+                                relid,
+                                v: v.clone(),
+                            }
+                        } else {
+                            Update::DeleteValue {
+                                relid,
+                                v: v.clone(),
+                            }
+                        });
+                    }
+                }
+            }
+            RelationInstance::Flat { elements, .. } => {
+                updates.extend(elements.iter().map(|v| Update::Insert {
+                    relid,
+                    v: v.clone(),
+                }));
+            }
+            RelationInstance::Indexed { elements, .. } => {
+                updates.extend(elements.values().map(|v| Update::Insert {
+                    relid,
+                    v: v.clone(),
+                }));
+            }
+        }
+    }
+
     /// Reverse all changes recorded in delta sets to rollback the transaction.
     fn delta_undo(&mut self) -> Response<()> {
         let mut updates = Vec::with_capacity(self.relations.len());
@@ -2695,19 +3292,33 @@ impl RunningProgram {
     /// that all outputs have been produced and we have successfully committed
     /// the current transaction.
     fn await_flush_ack(&self) -> Response<()> {
-        for (worker_index, receiver) in self.reply_recv.iter().enumerate() {
-            match receiver.recv() {
-                Err(_) => {
-                    return Err(format!(
-                        "failed to receive flush ack message from worker {}",
-                        worker_index
-                    ))
-                }
-                Ok(Reply::FlushAck) => (),
-                Ok(msg) => {
-                    return Err(format!(
-                        "received unexpected reply to flush request from worker {}: {:?}",
-                        worker_index, msg,
+        self.await_flush_ack_until(None, false)
+    }
+
+    /// Like `await_flush_ack`, but fails with `DDlogError::Timeout` once `deadline` expires,
+    /// and, if `cancellable` is true, with `DDlogError::Cancelled` once the commit is
+    /// cancelled.
+    fn await_flush_ack_until(&self, deadline: Option<Instant>, cancellable: bool) -> Response<()> {
+        let deadline = deadline.map_or_else(crossbeam_channel::never, crossbeam_channel::at);
+        let cancelled = if cancellable {
+            self.cancellation.receiver().clone()
+        } else {
+            crossbeam_channel::never()
+        };
+
+        for worker_index in 0..self.reply_recv.len() {
+            let reply = crossbeam_channel::select! {
+                recv(self.reply_recv[worker_index]) -> reply => reply,
+                recv(cancelled) -> _ => return Err(DDlogError::Cancelled("transaction_commit")),
+                recv(deadline) -> _ => return Err(DDlogError::Timeout("transaction_commit")),
+            };
+
+            match self.check_reply(worker_index, "flush", reply)? {
+                Reply::FlushAck => (),
+                msg => {
+                    return Err(DDlogError::worker_failure(
+                        Some(worker_index),
+                        format!("received unexpected reply to flush request: {:?}", msg),
                     ))
                 }
             }
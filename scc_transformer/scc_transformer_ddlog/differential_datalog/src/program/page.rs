@@ -0,0 +1,112 @@
+//! Pages of the contents of arrangements, used to dump large arrangements piece by piece.
+
+use crate::ddval::DDValue;
+use std::vec;
+
+/// Position of an entry in an arrangement.  Entries are ordered by key, then by value.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArrangementCursor {
+    pub key: DDValue,
+    pub val: DDValue,
+}
+
+/// A page of the contents of an arrangement returned by `RunningProgram::dump_arrangement_page`.
+#[derive(Debug, Clone, Default)]
+pub struct ArrangementPage {
+    /// Values in the order of their entries in the arrangement.
+    pub values: Vec<DDValue>,
+    /// Where the next page starts, or `None` if this is the last page.
+    pub next: Option<ArrangementCursor>,
+}
+
+impl ArrangementPage {
+    /// Merge the replies of workers to a page query into a page of up to `limit` values.  Each
+    /// reply holds the worker's next entries in order, and whether the worker has more.
+    ///
+    /// Entries are taken from the replies in order until the page is full, so that the ones
+    /// that don't make it into the page are never copied.
+    pub(crate) fn merge(replies: Vec<(Vec<ArrangementCursor>, bool)>, limit: usize) -> Self {
+        let mut more = false;
+        let mut entries: Vec<vec::IntoIter<ArrangementCursor>> = replies
+            .into_iter()
+            .map(|(worker_entries, worker_more)| {
+                more |= worker_more;
+                worker_entries.into_iter()
+            })
+            .collect();
+
+        let mut page = Self::default();
+        let mut last = None;
+        while page.values.len() < limit {
+            let next = entries
+                .iter_mut()
+                .filter(|worker_entries| !worker_entries.as_slice().is_empty())
+                .min_by(|x, y| x.as_slice()[0].cmp(&y.as_slice()[0]))
+                .and_then(Iterator::next);
+            match next {
+                Some(entry) => {
+                    page.values.push(entry.val.clone());
+                    last = Some(entry);
+                }
+                None => break,
+            }
+        }
+
+        more |= entries
+            .iter()
+            .any(|worker_entries| !worker_entries.as_slice().is_empty());
+        if more {
+            page.next = last;
+        }
+
+        page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ddval::DDValConvert;
+
+    fn entries(entries: &[(u64, u64)]) -> Vec<ArrangementCursor> {
+        entries
+            .iter()
+            .map(|&(key, val)| ArrangementCursor {
+                key: key.into_ddvalue(),
+                val: val.into_ddvalue(),
+            })
+            .collect()
+    }
+
+    fn values(page: &ArrangementPage) -> Vec<u64> {
+        page.values
+            .iter()
+            .map(|v| *u64::from_ddvalue_ref(v))
+            .collect()
+    }
+
+    #[test]
+    fn merge() {
+        let replies = || {
+            vec![
+                (entries(&[(1, 10), (3, 30)]), false),
+                (entries(&[(1, 5), (2, 20), (4, 40)]), true),
+                (Vec::new(), false),
+            ]
+        };
+
+        let page = ArrangementPage::merge(replies(), 3);
+        assert_eq!(values(&page), vec![5, 10, 20]);
+        assert_eq!(page.next, Some(entries(&[(2, 20)]).remove(0)));
+
+        // A worker with more entries than it sent means that there is a next page, even if all
+        // the entries that were sent fit.
+        let page = ArrangementPage::merge(replies(), 10);
+        assert_eq!(values(&page), vec![5, 10, 20, 30, 40]);
+        assert_eq!(page.next, Some(entries(&[(4, 40)]).remove(0)));
+
+        let page = ArrangementPage::merge(vec![(entries(&[(1, 1), (2, 2)]), false)], 2);
+        assert_eq!(values(&page), vec![1, 2]);
+        assert_eq!(page.next, None);
+    }
+}
@@ -0,0 +1,70 @@
+//! Savepoints that part of a transaction can be rolled back to.
+
+use super::{DeltaSet, RelId};
+use fnv::FnvHashMap;
+
+/// A savepoint of the transaction in progress (see `RunningProgram::savepoint`): the delta sets
+/// of input relations when it was created.
+pub(crate) struct Savepoint {
+    pub(crate) name: String,
+    /// Non-empty delta sets by relation.
+    deltas: FnvHashMap<RelId, DeltaSet>,
+}
+
+impl Savepoint {
+    pub(crate) fn new<'a, I>(name: &str, deltas: I) -> Self
+    where
+        I: IntoIterator<Item = (RelId, &'a DeltaSet)>,
+    {
+        Self {
+            name: name.to_string(),
+            deltas: deltas
+                .into_iter()
+                .filter(|(_, delta)| !delta.is_empty())
+                .map(|(relid, delta)| (relid, delta.clone()))
+                .collect(),
+        }
+    }
+
+    /// Changes to relation `relid` since the savepoint was created, given its current delta set
+    /// `delta`.
+    pub(crate) fn changes_since(&self, relid: RelId, delta: &DeltaSet) -> DeltaSet {
+        let mut changes = delta.clone();
+        for (v, w) in self.deltas.get(&relid).into_iter().flatten() {
+            *changes.entry(v.clone()).or_insert(0) -= w;
+        }
+        changes.retain(|_, w| *w != 0);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeltaSet, Savepoint};
+    use crate::ddval::DDValConvert;
+
+    fn delta_set(weights: &[(u64, isize)]) -> DeltaSet {
+        weights
+            .iter()
+            .map(|&(v, w)| (v.into_ddvalue(), w))
+            .collect()
+    }
+
+    #[test]
+    fn changes_since_savepoint() {
+        let before = delta_set(&[(1, 1), (2, -1)]);
+        let savepoint = Savepoint::new("sp", vec![(0, &before), (1, &DeltaSet::default())]);
+
+        // Value 1 was deleted again, value 3 inserted, and value 2 left alone.
+        let after = delta_set(&[(2, -1), (3, 1)]);
+        assert_eq!(
+            savepoint.changes_since(0, &after),
+            delta_set(&[(1, -1), (3, 1)])
+        );
+        assert_eq!(savepoint.changes_since(0, &before), DeltaSet::default());
+        assert_eq!(
+            savepoint.changes_since(1, &delta_set(&[(4, 1)])),
+            delta_set(&[(4, 1)])
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Converting between `Record`s and rows of text columns, e.g., CSV files.
+
+use crate::record::{Name, Record};
+use num::BigInt;
+use ordered_float::OrderedFloat;
+use std::borrow::Cow;
+
+/// Convert a row of text columns to a value shaped like `template`, a value of the relation's
+/// type (see `DDlogInventory::relation_template`).
+///
+/// Columns map onto the fields of the template the way `flatten_record` names them: by name
+/// if `header` is given, and by position otherwise.  Each column is parsed according to the
+/// kind of the field it maps onto, so a string field keeps a column like `007` as is.  Fields
+/// without a column in `header` keep the template's default value.
+pub fn record_from_columns(
+    template: &Record,
+    header: Option<&[String]>,
+    columns: &[&str],
+) -> Result<Record, String> {
+    let fields = flatten_record(template);
+    match header {
+        Some(names) => {
+            if let Some(name) = names
+                .iter()
+                .find(|name| !fields.iter().any(|(field, _)| field == *name))
+            {
+                return Err(format!("unknown column '{}'", name));
+            }
+            let mut column = |field: &str| {
+                names
+                    .iter()
+                    .position(|name| name == field)
+                    .map(|idx| columns.get(idx).copied().unwrap_or_default())
+            };
+            fill_template(template, "", &mut column)
+        }
+        None => {
+            if columns.len() != fields.len() {
+                return Err(format!(
+                    "expected {} columns, found {}",
+                    fields.len(),
+                    columns.len()
+                ));
+            }
+            let mut columns = columns.iter();
+            fill_template(template, "", &mut |_| columns.next().copied())
+        }
+    }
+}
+
+/// Interpretations of a row of text columns as a value of relation `relation`, from the most
+/// to the least specific one, for relations without a template (see `record_from_columns`).
+/// The caller should use the first one that converts to the relation's type.
+///
+/// Columns map onto the fields of the relation's record: by name if `header` is given, and
+/// by position otherwise.  Since columns are untyped, the first candidate turns every column
+/// that looks like a number or a boolean into one, and the second one keeps all columns as
+/// strings.  A single column may also be the value itself, for relations whose type isn't a
+/// struct.
+pub fn records_from_columns(
+    relation: &str,
+    header: Option<&[String]>,
+    columns: &[&str],
+) -> Vec<Record> {
+    let constructor: Name = Cow::from(relation.to_string());
+    let to_struct = |field: fn(&str) -> Record| match header {
+        Some(names) => Record::NamedStruct(
+            constructor.clone(),
+            names
+                .iter()
+                .zip(columns.iter())
+                .map(|(name, column)| (Cow::from(name.clone()), field(column)))
+                .collect(),
+        ),
+        None => Record::PosStruct(
+            constructor.clone(),
+            columns.iter().map(|column| field(column)).collect(),
+        ),
+    };
+
+    let mut candidates = vec![to_struct(typed_column), to_struct(string_column)];
+    if let [column] = columns {
+        candidates.push(typed_column(column));
+        candidates.push(string_column(column));
+    }
+    candidates.dedup();
+    candidates
+}
+
+/// Flatten a record into `(name, value)` columns.
+///
+/// Tuples and structs are flattened recursively: fields of named structs are named after the
+/// field, other fields after their position, and nested fields are prefixed with the name of
+/// the enclosing field, e.g., `addr.0`.  Collections are kept in a single column in DDlog
+/// syntax, strings are written unquoted, and a record that is neither a tuple nor a struct
+/// becomes a single `value` column.
+pub fn flatten_record(record: &Record) -> Vec<(String, String)> {
+    let mut columns = Vec::new();
+    match record {
+        Record::Tuple(_) | Record::PosStruct(..) | Record::NamedStruct(..) => {
+            flatten_fields(record, "", &mut columns)
+        }
+        _ => columns.push(("value".to_string(), column_value(record))),
+    }
+    columns
+}
+
+fn flatten_fields(record: &Record, prefix: &str, columns: &mut Vec<(String, String)>) {
+    let mut flatten = |name: &str, field: &Record| {
+        let name = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", prefix, name)
+        };
+        match field {
+            Record::Tuple(_) | Record::PosStruct(..) | Record::NamedStruct(..) => {
+                flatten_fields(field, &name, columns)
+            }
+            _ => columns.push((name, column_value(field))),
+        }
+    };
+
+    match record {
+        Record::Tuple(fields) | Record::PosStruct(_, fields) => {
+            for (idx, field) in fields.iter().enumerate() {
+                flatten(&idx.to_string(), field);
+            }
+        }
+        Record::NamedStruct(_, fields) => {
+            for (name, field) in fields.iter() {
+                flatten(name, field);
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Rebuild `template`, replacing every field that `column` returns a column for (given the
+/// field's name as in `flatten_record`) with the column's value.
+fn fill_template<'a>(
+    template: &Record,
+    prefix: &str,
+    column: &mut dyn FnMut(&str) -> Option<&'a str>,
+) -> Result<Record, String> {
+    let name = |field: &str| {
+        if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        }
+    };
+
+    match template {
+        Record::Tuple(fields) => Ok(Record::Tuple(
+            fields
+                .iter()
+                .enumerate()
+                .map(|(idx, field)| fill_field(field, &name(&idx.to_string()), column))
+                .collect::<Result<_, _>>()?,
+        )),
+        Record::PosStruct(constructor, fields) if !fields.is_empty() => Ok(Record::PosStruct(
+            constructor.clone(),
+            fields
+                .iter()
+                .enumerate()
+                .map(|(idx, field)| fill_field(field, &name(&idx.to_string()), column))
+                .collect::<Result<_, _>>()?,
+        )),
+        Record::NamedStruct(constructor, fields) => Ok(Record::NamedStruct(
+            constructor.clone(),
+            fields
+                .iter()
+                .map(|(field_name, field)| {
+                    Ok((field_name.clone(), fill_field(field, &name(field_name), column)?))
+                })
+                .collect::<Result<_, String>>()?,
+        )),
+        _ => fill_field(template, "value", column),
+    }
+}
+
+fn fill_field<'a>(
+    field: &Record,
+    name: &str,
+    column: &mut dyn FnMut(&str) -> Option<&'a str>,
+) -> Result<Record, String> {
+    match field {
+        Record::Tuple(_) | Record::NamedStruct(..) => fill_template(field, name, column),
+        Record::PosStruct(_, fields) if !fields.is_empty() => fill_template(field, name, column),
+        _ => match column(name) {
+            Some(value) => parse_column(field, value)
+                .ok_or_else(|| format!("column '{}': cannot convert '{}'", name, value)),
+            None => Ok(field.clone()),
+        },
+    }
+}
+
+/// Parse `column` as a value of the same kind as `field`.
+fn parse_column(field: &Record, column: &str) -> Option<Record> {
+    let trimmed = column.trim();
+    match field {
+        Record::Bool(_) => trimmed.parse().ok().map(Record::Bool),
+        Record::Int(_) => trimmed.parse().ok().map(Record::Int),
+        Record::Float(_) => trimmed.parse().ok().map(|f| Record::Float(OrderedFloat(f))),
+        Record::Double(_) => trimmed.parse().ok().map(|d| Record::Double(OrderedFloat(d))),
+        Record::String(_) => Some(string_column(column)),
+        // A constructor without arguments, e.g., a variant of an enum.
+        Record::PosStruct(..) => Some(Record::PosStruct(
+            Cow::from(trimmed.to_string()),
+            Vec::new(),
+        )),
+        _ => Some(typed_column(column)),
+    }
+}
+
+fn column_value(record: &Record) -> String {
+    match record {
+        Record::String(s) | Record::Serialized(_, s) => s.clone(),
+        record => record.to_string(),
+    }
+}
+
+fn typed_column(column: &str) -> Record {
+    let trimmed = column.trim();
+    if let Ok(b) = trimmed.parse::<bool>() {
+        Record::Bool(b)
+    } else if let Ok(i) = trimmed.parse::<BigInt>() {
+        Record::Int(i)
+    } else if let (true, Ok(d)) = (
+        trimmed.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.'),
+        trimmed.parse::<f64>(),
+    ) {
+        Record::Double(OrderedFloat(d))
+    } else {
+        string_column(column)
+    }
+}
+
+fn string_column(column: &str) -> Record {
+    Record::String(column.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_to_records() {
+        let header = vec!["src".to_string(), "dest".to_string()];
+        let candidates = records_from_columns("Edge", Some(&header), &["1", " 2"]);
+        assert_eq!(
+            candidates[0],
+            Record::NamedStruct(
+                Cow::from("Edge"),
+                vec![
+                    (Cow::from("src"), Record::Int(BigInt::from(1))),
+                    (Cow::from("dest"), Record::Int(BigInt::from(2))),
+                ]
+            )
+        );
+        assert_eq!(
+            candidates[1],
+            Record::NamedStruct(
+                Cow::from("Edge"),
+                vec![
+                    (Cow::from("src"), Record::String("1".to_string())),
+                    (Cow::from("dest"), Record::String(" 2".to_string())),
+                ]
+            )
+        );
+        assert_eq!(candidates.len(), 2);
+
+        let candidates = records_from_columns("Weight", None, &["-1.5e3"]);
+        assert_eq!(
+            candidates,
+            vec![
+                Record::PosStruct(
+                    Cow::from("Weight"),
+                    vec![Record::Double(OrderedFloat(-1500.0))]
+                ),
+                Record::PosStruct(
+                    Cow::from("Weight"),
+                    vec![Record::String("-1.5e3".to_string())]
+                ),
+                Record::Double(OrderedFloat(-1500.0)),
+                Record::String("-1.5e3".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            records_from_columns("Name", None, &["nan", "true"])[0],
+            Record::PosStruct(
+                Cow::from("Name"),
+                vec![Record::String("nan".to_string()), Record::Bool(true)]
+            )
+        );
+    }
+
+    #[test]
+    fn columns_to_template() {
+        let template = Record::NamedStruct(
+            Cow::from("Agent"),
+            vec![
+                (Cow::from("name"), Record::String(String::new())),
+                (
+                    Cow::from("addr"),
+                    Record::Tuple(vec![Record::Int(BigInt::from(0)), Record::Bool(false)]),
+                ),
+            ],
+        );
+        let agent = |name: &str, port: i64, up: bool| {
+            Record::NamedStruct(
+                Cow::from("Agent"),
+                vec![
+                    (Cow::from("name"), Record::String(name.to_string())),
+                    (
+                        Cow::from("addr"),
+                        Record::Tuple(vec![Record::Int(BigInt::from(port)), Record::Bool(up)]),
+                    ),
+                ],
+            )
+        };
+
+        // A numeric-looking string column stays a string, leading zeros included.
+        assert_eq!(
+            record_from_columns(&template, None, &["007", " 80", "true"]),
+            Ok(agent("007", 80, true))
+        );
+        let header = vec!["addr.1".to_string(), "name".to_string()];
+        assert_eq!(
+            record_from_columns(&template, Some(&header), &["true", "007"]),
+            Ok(agent("007", 0, true))
+        );
+
+        assert!(record_from_columns(&template, None, &["007", "x", "true"]).is_err());
+        assert!(record_from_columns(&template, None, &["007"]).is_err());
+        let header = vec!["nickname".to_string()];
+        assert!(record_from_columns(&template, Some(&header), &["bond"]).is_err());
+
+        assert_eq!(
+            record_from_columns(&Record::String(String::new()), None, &["0.5"]),
+            Ok(Record::String("0.5".to_string()))
+        );
+    }
+
+    #[test]
+    fn record_to_columns() {
+        let record = Record::NamedStruct(
+            Cow::from("Host"),
+            vec![
+                (Cow::from("name"), Record::String("a, b".to_string())),
+                (
+                    Cow::from("addr"),
+                    Record::Tuple(vec![
+                        Record::Int(BigInt::from(10)),
+                        Record::PosStruct(Cow::from("Port"), vec![Record::Bool(false)]),
+                    ]),
+                ),
+            ],
+        );
+        assert_eq!(
+            flatten_record(&record),
+            vec![
+                ("name".to_string(), "a, b".to_string()),
+                ("addr.0".to_string(), "10".to_string()),
+                ("addr.1.0".to_string(), "false".to_string()),
+            ]
+        );
+        assert_eq!(
+            flatten_record(&Record::Int(BigInt::from(-3))),
+            vec![("value".to_string(), "-3".to_string())]
+        );
+    }
+}
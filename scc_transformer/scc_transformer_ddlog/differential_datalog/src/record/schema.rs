@@ -0,0 +1,191 @@
+//! Describing the types of relations as DDlog declarations, e.g.,
+//! `input relation Edge(src: u32, dest: u32)`.
+
+use crate::record::{prefix::field_at, CollectionKind, Record};
+use num::BigInt;
+
+/// Widths of fixed-size integer types.
+const INT_WIDTHS: [usize; 5] = [8, 16, 32, 64, 128];
+
+/// The declaration of relation `name` of the given kind (e.g., `input relation`), given
+/// `template`, a default value of its type (see `DDlogInventory::relation_template`), and
+/// `is_value`, which tells if a record converts to a value of that type.
+///
+/// The widths of integers are found by probing `is_value`.  Structs are described by their
+/// default constructor, which, for a type with several constructors, is the first one.  The
+/// types of the elements of collections are unknown and shown as `_`, as is the whole type
+/// without a template.
+pub fn relation_declaration<F>(
+    kind: &str,
+    name: &str,
+    template: Option<&Record>,
+    is_value: F,
+) -> String
+where
+    F: Fn(&Record) -> bool,
+{
+    let mut template = match template {
+        Some(template) => template.clone(),
+        None => return format!("{} {}[_]", kind, name),
+    };
+    let mut path = Vec::new();
+    match &template {
+        Record::NamedStruct(cons, _) if cons == name => format!(
+            "{} {}({})",
+            kind,
+            name,
+            fields(&mut template, &mut path, &is_value)
+        ),
+        _ => format!(
+            "{} {}[{}]",
+            kind,
+            name,
+            type_name(&mut template, &mut path, &is_value)
+        ),
+    }
+}
+
+/// The type of the field at `path` in `value`.
+fn type_name(
+    value: &mut Record,
+    path: &mut Vec<usize>,
+    is_value: &dyn Fn(&Record) -> bool,
+) -> String {
+    let field = match field_at(value, path) {
+        Some(field) => field,
+        None => return "_".to_string(),
+    };
+    match field {
+        Record::Bool(_) => "bool".to_string(),
+        Record::Int(_) => int_type(value, path, is_value),
+        Record::Float(_) => "float".to_string(),
+        Record::Double(_) => "double".to_string(),
+        Record::String(_) => "string".to_string(),
+        Record::Serialized(..) => "_".to_string(),
+        Record::Tuple(_) => format!("({})", fields(value, path, is_value)),
+        Record::Array(CollectionKind::Set, _) => "Set<_>".to_string(),
+        Record::Array(CollectionKind::Map, _) => "Map<_, _>".to_string(),
+        Record::Array(..) => "Vec<_>".to_string(),
+        Record::PosStruct(cons, _) | Record::NamedStruct(cons, _) => {
+            let cons = cons.to_string();
+            format!("{}{{{}}}", cons, fields(value, path, is_value))
+        }
+    }
+}
+
+/// The types of the fields of the tuple or struct at `path` in `value`, separated by commas and
+/// preceded by their names in named structs.
+fn fields(value: &mut Record, path: &mut Vec<usize>, is_value: &dyn Fn(&Record) -> bool) -> String {
+    let names: Vec<Option<String>> = match field_at(value, path) {
+        Some(Record::Tuple(fields)) | Some(Record::PosStruct(_, fields)) => {
+            fields.iter().map(|_| None).collect()
+        }
+        Some(Record::NamedStruct(_, fields)) => fields
+            .iter()
+            .map(|(name, _)| Some(name.to_string()))
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let mut types = Vec::with_capacity(names.len());
+    for (i, name) in names.into_iter().enumerate() {
+        path.push(i);
+        let ty = type_name(value, path, is_value);
+        path.pop();
+        types.push(match name {
+            Some(name) => format!("{}: {}", name, ty),
+            None => ty,
+        });
+    }
+    types.join(", ")
+}
+
+/// The type of the integer at `path` in `value`: the narrowest signed or unsigned type that
+/// accepts the values accepted by `is_value`, or `bigint`.
+fn int_type(value: &mut Record, path: &[usize], is_value: &dyn Fn(&Record) -> bool) -> String {
+    let original = field_at(value, path).cloned();
+    let mut accepts = |i: BigInt| {
+        if let Some(field) = field_at(value, path) {
+            *field = Record::Int(i);
+        }
+        is_value(value)
+    };
+
+    let signed = accepts(BigInt::from(-1));
+    let ty = INT_WIDTHS
+        .iter()
+        .find(|&&width| {
+            let bits = if signed { width - 1 } else { width };
+            !accepts(BigInt::from(1) << bits)
+        })
+        .map_or_else(
+            || "bigint".to_string(),
+            |width| format!("{}{}", if signed { "s" } else { "u" }, width),
+        );
+
+    if let (Some(field), Some(original)) = (field_at(value, path), original) {
+        *field = original;
+    }
+    ty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::relation_declaration;
+    use crate::record::{CollectionKind, FromRecord, Record};
+    use num::BigInt;
+    use std::borrow::Cow;
+
+    fn int(i: i64) -> Record {
+        Record::Int(BigInt::from(i))
+    }
+
+    #[test]
+    fn declarations() {
+        // `Edge{src: u32, dest: s16, label: (bool, string, bigint)}`.
+        let template = Record::NamedStruct(
+            Cow::from("Edge"),
+            vec![
+                (Cow::from("src"), int(0)),
+                (Cow::from("dest"), int(0)),
+                (
+                    Cow::from("label"),
+                    Record::Tuple(vec![
+                        Record::Bool(false),
+                        Record::String(String::new()),
+                        int(0),
+                    ]),
+                ),
+            ],
+        );
+        let is_edge = |record: &Record| match record {
+            Record::NamedStruct(_, fields) => {
+                u32::from_record(&fields[0].1).is_ok() && i16::from_record(&fields[1].1).is_ok()
+            }
+            _ => false,
+        };
+        assert_eq!(
+            relation_declaration("input relation", "Edge", Some(&template), is_edge),
+            "input relation Edge(src: u32, dest: s16, label: (bool, string, bigint))"
+        );
+
+        let template = Record::PosStruct(
+            Cow::from("Some"),
+            vec![Record::Array(CollectionKind::Set, Vec::new())],
+        );
+        let any = |_: &Record| true;
+        assert_eq!(
+            relation_declaration("relation", "Options", Some(&template), any),
+            "relation Options[Some{Set<_>}]"
+        );
+        let unit = Record::Tuple(Vec::new());
+        assert_eq!(
+            relation_declaration("relation", "Unit", Some(&unit), any),
+            "relation Unit[()]"
+        );
+        assert_eq!(
+            relation_declaration("output relation", "Opaque", None, any),
+            "output relation Opaque[_]"
+        );
+    }
+}
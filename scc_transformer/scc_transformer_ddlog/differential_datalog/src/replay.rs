@@ -1,15 +1,21 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::convert::AsRef;
 use std::fmt::{Debug, Display, Formatter};
+use std::io::Error as IOError;
 use std::io::Result as IOResult;
 use std::io::Write;
 use std::iter::Peekable;
+use std::mem;
 use std::ops::Deref;
 use std::string::ToString;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use crate::ddlog::{DDlog, DDlogDump, DDlogDynamic, DDlogInventory, DDlogProfiling};
 use crate::ddval::DDValue;
+use crate::error::DDlogError;
+use crate::program::ArrangementCursor;
+use crate::program::ArrangementPage;
 use crate::program::IdxId;
 use crate::program::RelId;
 use crate::program::Update;
@@ -54,6 +60,27 @@ where
     }
 }
 
+/// First line of the annotation that follows a commit recorded with
+/// `CommandRecorder::set_record_changes`.  The annotation is a comment, so that
+/// recordings with annotations can be replayed like any other.
+pub const EXPECTED_CHANGES_HEADER: &str = "#> expected changes:";
+
+/// Prefix of the remaining lines of the annotation, one per changed value.
+pub const EXPECTED_CHANGE_PREFIX: &str = "#> ";
+
+/// Prefix of the comment that precedes every transaction recorded with
+/// `CommandRecorder::set_record_timestamps`, followed by the number of microseconds since
+/// recording started.
+pub const TIMESTAMP_PREFIX: &str = "#@ ";
+
+/// Parse a line written by a recorder with timestamps enabled, returning the time at which the
+/// transaction that follows it was started, relative to the start of the recording.
+pub fn parse_timestamp(line: &str) -> Option<Duration> {
+    line.strip_prefix(TIMESTAMP_PREFIX)
+        .and_then(|micros| micros.trim().parse().ok())
+        .map(Duration::from_micros)
+}
+
 /// DDlog API implementation that records each command passing through it and
 /// forwards it to the next handler in the chain.
 ///
@@ -66,6 +93,11 @@ pub struct CommandRecorder<W, I> {
     // Typically, `I` is `Box<dyn DDlogInventory + Send + Sync>` or
     // `Arc<dyn DDlogInventory + Send + Sync>`
     inventory: I,
+    // Annotate commits with their expected changes.
+    record_changes: bool,
+    // Stamp transactions with the time elapsed since `start`.
+    record_timestamps: bool,
+    start: Instant,
 }
 
 impl<W, B> Debug for CommandRecorder<W, B> {
@@ -79,13 +111,40 @@ impl<W, B> CommandRecorder<W, B> {
         CommandRecorder {
             writer: Mutex::new(writer),
             inventory,
+            record_changes: false,
+            record_timestamps: false,
+            start: Instant::now(),
         }
     }
 
+    /// Precede every transaction with a comment holding the time at which it was started,
+    /// measured with a monotonic clock since the recorder was created (see `parse_timestamp`).
+    pub fn set_record_timestamps(&mut self, enable: bool) {
+        self.record_timestamps = enable;
+    }
+
+    /// Annotate every commit with the changes to output relations that it produced, so that
+    /// the recording can be used to verify a later run of the program (see `diff_changes`).
+    pub fn set_record_changes(&mut self, enable: bool) {
+        self.record_changes = enable;
+    }
+
+    pub fn records_changes(&self) -> bool {
+        self.record_changes
+    }
+
     pub fn release_writer(self) -> W {
         self.writer.into_inner().unwrap()
     }
 
+    /// Take everything recorded so far, leaving an empty writer behind.
+    pub fn take_writer(&self) -> W
+    where
+        W: Default,
+    {
+        mem::take(&mut *self.writer.lock().unwrap())
+    }
+
     /// Convert a `RelIdentifier` into its symbolic name.
     fn relident2name<'a>(
         inventory: &dyn DDlogInventory,
@@ -119,12 +178,123 @@ where
     write!(writer, "insert_or_update {}[{}]", name, value)
 }
 
+/// Format a bound of a `query_index_range` command: `(<key>)`, or `_` if unbounded.
+fn format_bound<V>(bound: Option<&V>) -> String
+where
+    V: Display,
+{
+    bound.map_or_else(|| "_".to_string(), |key| format!("({})", key))
+}
+
+/// Format the fields of a `query_index_prefix` command.
+fn format_prefix(prefix: &[Record]) -> String {
+    prefix
+        .iter()
+        .map(|field| field.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format the changes produced by a commit, one `<relation>: <value>: <weight>` line per
+/// changed value.
+pub fn format_changes(inventory: &dyn DDlogInventory, changes: &DeltaMap<DDValue>) -> Vec<String> {
+    changes
+        .iter()
+        .flat_map(|(relid, rel)| {
+            let name = inventory.get_table_name(*relid).unwrap_or("???");
+            rel.iter()
+                .map(move |(value, weight)| format!("{}: {}: {:+}", name, value, weight))
+        })
+        .collect()
+}
+
+/// Compare the changes of a commit, as formatted by `format_changes`, with the expected ones.
+/// Returns the differences in the style of a unified diff: expected lines that are missing are
+/// prefixed with `-`, and unexpected lines with `+`.  An empty result means that the changes
+/// match.
+pub fn diff_changes(expected: &[String], actual: &[String]) -> Vec<String> {
+    let expected_set: BTreeSet<&String> = expected.iter().collect();
+    let actual_set: BTreeSet<&String> = actual.iter().collect();
+
+    let missing = expected
+        .iter()
+        .filter(|line| !actual_set.contains(line))
+        .map(|line| format!("- {}", line));
+    let unexpected = actual
+        .iter()
+        .filter(|line| !expected_set.contains(line))
+        .map(|line| format!("+ {}", line));
+    missing.chain(unexpected).collect()
+}
+
+fn record_error(e: IOError) -> DDlogError {
+    DDlogError::io("failed to record command", e)
+}
+
 impl<W, I> CommandRecorder<W, I>
 where
     W: Write,
     I: Deref<Target = dyn DDlogInventory + Send + Sync>,
 {
-    fn do_record_updates<It, U, F>(&self, updates: It, mut record: F) -> Result<(), String>
+    /// Annotate the last recorded commit with the changes it produced, if enabled with
+    /// `set_record_changes`.
+    pub fn record_commit_changes(&self, changes: &DeltaMap<DDValue>) -> Result<(), DDlogError> {
+        if !self.record_changes {
+            return Ok(());
+        }
+
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(&mut writer, "{}", EXPECTED_CHANGES_HEADER)
+            .and_then(|_| {
+                format_changes(&*self.inventory, changes)
+                    .iter()
+                    .try_for_each(|line| {
+                        writeln!(&mut writer, "{}{}", EXPECTED_CHANGE_PREFIX, line)
+                    })
+            })
+            .map_err(record_error)
+    }
+
+    /// Record a commit, as `commit [dump_changes] [timeout <ms>ms];`.
+    pub fn record_commit(
+        &self,
+        dump_changes: bool,
+        timeout: Option<Duration>,
+    ) -> Result<(), DDlogError> {
+        let dump_changes = if dump_changes { " dump_changes" } else { "" };
+        let timeout = timeout
+            .map(|timeout| format!(" timeout {}ms", timeout.as_millis()))
+            .unwrap_or_default();
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(&mut writer, "commit{}{};", dump_changes, timeout).map_err(record_error)
+    }
+
+    /// Record the creation of a savepoint, as `savepoint <name>;`.
+    pub fn record_savepoint(&self, name: &str) -> Result<(), DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(&mut writer, "savepoint {};", name).map_err(record_error)
+    }
+
+    /// Record a hypothetical evaluation of `updates` (see `HDDlog::evaluate_hypothetical`), as
+    /// `what_if <updates>;`.
+    pub fn record_what_if(&self, updates: &[Update<DDValue>]) -> Result<(), DDlogError> {
+        {
+            let mut writer = self.writer.lock().unwrap();
+            if updates.is_empty() {
+                return writeln!(&mut writer, "what_if;").map_err(record_error);
+            }
+            writeln!(&mut writer, "what_if").map_err(record_error)?;
+        }
+        self.do_record_updates(updates.iter(), |i, w, u| Self::record_val_upd(i, w, u))
+    }
+
+    /// Record a rollback to a savepoint, as `rollback_to <name>;`.
+    pub fn record_rollback_to(&self, name: &str) -> Result<(), DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(&mut writer, "rollback_to {};", name).map_err(record_error)
+    }
+
+    fn do_record_updates<It, U, F>(&self, updates: It, mut record: F) -> Result<(), DDlogError>
     where
         W: Write,
         It: Iterator<Item = U>,
@@ -142,7 +312,7 @@ where
                     }
                 })
             })
-            .map_err(|e| e.to_string())
+            .map_err(record_error)
     }
 
     /// Record an `UpdCmd`.
@@ -222,45 +392,57 @@ where
     W: Write,
     I: Deref<Target = dyn DDlogInventory + Send + Sync>,
 {
-    fn transaction_start(&self) -> Result<(), String> {
+    fn transaction_start(&self) -> Result<(), DDlogError> {
         let mut writer = self.writer.lock().unwrap();
-        writeln!(&mut writer, "start;").map_err(|e| e.to_string())
+        if self.record_timestamps {
+            writeln!(
+                &mut writer,
+                "{}{}",
+                TIMESTAMP_PREFIX,
+                self.start.elapsed().as_micros()
+            )
+            .map_err(record_error)?;
+        }
+        writeln!(&mut writer, "start;").map_err(record_error)
     }
 
-    fn transaction_commit(&self) -> Result<(), String> {
+    fn transaction_commit(&self) -> Result<(), DDlogError> {
         let mut writer = self.writer.lock().unwrap();
-        writeln!(&mut writer, "commit;").map_err(|e| e.to_string())
+        writeln!(&mut writer, "commit;").map_err(record_error)
     }
 
     fn transaction_commit_dump_changes_dynamic(
         &self,
-    ) -> Result<BTreeMap<RelId, Vec<(Record, isize)>>, String> {
+    ) -> Result<BTreeMap<RelId, Vec<(Record, isize)>>, DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(&mut writer, "commit dump_changes;")
             .map(|_| BTreeMap::new())
-            .map_err(|e| e.to_string())
+            .map_err(record_error)
     }
 
-    fn transaction_rollback(&self) -> Result<(), String> {
+    fn transaction_rollback(&self) -> Result<(), DDlogError> {
         let mut writer = self.writer.lock().unwrap();
-        writeln!(&mut writer, "rollback;").map_err(|e| e.to_string())
+        writeln!(&mut writer, "rollback;").map_err(record_error)
     }
 
-    fn apply_updates_dynamic(&self, upds: &mut dyn Iterator<Item = UpdCmd>) -> Result<(), String> {
+    fn apply_updates_dynamic(
+        &self,
+        upds: &mut dyn Iterator<Item = UpdCmd>,
+    ) -> Result<(), DDlogError> {
         self.do_record_updates(upds, |i, w, u| Self::record_upd_cmd(i, w, &u))
     }
 
-    fn clear_relation(&self, rid: RelId) -> Result<(), String> {
+    fn clear_relation(&self, rid: RelId) -> Result<(), DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(
             &mut writer,
             "clear {};",
             self.inventory.get_table_name(rid).unwrap_or(&"???")
         )
-        .map_err(|e| e.to_string())
+        .map_err(record_error)
     }
 
-    fn query_index_dynamic(&self, iid: IdxId, key: &Record) -> Result<Vec<Record>, String> {
+    fn query_index_dynamic(&self, iid: IdxId, key: &Record) -> Result<Vec<Record>, DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(
             &mut writer,
@@ -269,10 +451,44 @@ where
             key
         )
         .and(Ok(vec![]))
-        .map_err(|e| e.to_string())
+        .map_err(record_error)
     }
 
-    fn dump_index_dynamic(&self, iid: IdxId) -> Result<Vec<Record>, String> {
+    fn query_index_range_dynamic(
+        &self,
+        iid: IdxId,
+        lo: Option<&Record>,
+        hi: Option<&Record>,
+    ) -> Result<Vec<Record>, DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(
+            &mut writer,
+            "query_index_range {} {}, {};",
+            self.inventory.get_index_name(iid).unwrap_or(&"???"),
+            format_bound(lo),
+            format_bound(hi)
+        )
+        .and(Ok(vec![]))
+        .map_err(record_error)
+    }
+
+    fn query_index_prefix_dynamic(
+        &self,
+        iid: IdxId,
+        prefix: &[Record],
+    ) -> Result<Vec<Record>, DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(
+            &mut writer,
+            "query_index_prefix {}({});",
+            self.inventory.get_index_name(iid).unwrap_or(&"???"),
+            format_prefix(prefix)
+        )
+        .and(Ok(vec![]))
+        .map_err(record_error)
+    }
+
+    fn dump_index_dynamic(&self, iid: IdxId) -> Result<Vec<Record>, DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(
             &mut writer,
@@ -280,10 +496,10 @@ where
             self.inventory.get_index_name(iid).unwrap_or(&"???")
         )
         .and(Ok(vec![]))
-        .map_err(|e| e.to_string())
+        .map_err(record_error)
     }
 
-    fn stop(&self) -> Result<(), String> {
+    fn stop(&self) -> Result<(), DDlogError> {
         Ok(())
     }
 }
@@ -293,18 +509,21 @@ where
     W: Write,
     I: Deref<Target = dyn DDlogInventory + Send + Sync>,
 {
-    fn transaction_commit_dump_changes(&self) -> Result<DeltaMap<DDValue>, String> {
+    fn transaction_commit_dump_changes(&self) -> Result<DeltaMap<DDValue>, DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(&mut writer, "commit dump_changes;")
             .map(|_| DeltaMap::new())
-            .map_err(|e| e.to_string())
+            .map_err(record_error)
     }
 
-    fn apply_updates(&self, upds: &mut dyn Iterator<Item = Update<DDValue>>) -> Result<(), String> {
+    fn apply_updates(
+        &self,
+        upds: &mut dyn Iterator<Item = Update<DDValue>>,
+    ) -> Result<(), DDlogError> {
         self.do_record_updates(upds, |i, w, u| Self::record_val_upd(i, w, &u))
     }
 
-    fn query_index(&self, iid: IdxId, key: DDValue) -> Result<BTreeSet<DDValue>, String> {
+    fn query_index(&self, iid: IdxId, key: DDValue) -> Result<BTreeSet<DDValue>, DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(
             &mut writer,
@@ -313,10 +532,44 @@ where
             key
         )
         .map(|_| BTreeSet::new())
-        .map_err(|e| e.to_string())
+        .map_err(record_error)
+    }
+
+    fn query_index_range(
+        &self,
+        iid: IdxId,
+        lo: Option<DDValue>,
+        hi: Option<DDValue>,
+    ) -> Result<BTreeSet<DDValue>, DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(
+            &mut writer,
+            "query_index_range {} {}, {};",
+            self.inventory.get_index_name(iid).unwrap_or(&"???"),
+            format_bound(lo.as_ref()),
+            format_bound(hi.as_ref())
+        )
+        .map(|_| BTreeSet::new())
+        .map_err(record_error)
     }
 
-    fn dump_index(&self, iid: IdxId) -> Result<BTreeSet<DDValue>, String> {
+    fn query_index_prefix(
+        &self,
+        iid: IdxId,
+        prefix: &[Record],
+    ) -> Result<BTreeSet<DDValue>, DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(
+            &mut writer,
+            "query_index_prefix {}({});",
+            self.inventory.get_index_name(iid).unwrap_or(&"???"),
+            format_prefix(prefix)
+        )
+        .map(|_| BTreeSet::new())
+        .map_err(record_error)
+    }
+
+    fn dump_index(&self, iid: IdxId) -> Result<BTreeSet<DDValue>, DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(
             &mut writer,
@@ -324,7 +577,51 @@ where
             self.inventory.get_index_name(iid).unwrap_or(&"???")
         )
         .map(|_| BTreeSet::new())
-        .map_err(|e| e.to_string())
+        .map_err(record_error)
+    }
+
+    fn count_relation(&self, table: RelId) -> Result<usize, DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(
+            &mut writer,
+            "count {};",
+            self.inventory.get_table_name(table).unwrap_or(&"???")
+        )
+        .map(|_| 0)
+        .map_err(record_error)
+    }
+
+    fn count_index(&self, iid: IdxId, key: Option<DDValue>) -> Result<usize, DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(
+            &mut writer,
+            "count_index {}{};",
+            self.inventory.get_index_name(iid).unwrap_or(&"???"),
+            key.map_or_else(String::new, |key| format!("({})", key))
+        )
+        .map(|_| 0)
+        .map_err(record_error)
+    }
+
+    fn dump_index_page(
+        &self,
+        iid: IdxId,
+        after: Option<ArrangementCursor>,
+        limit: usize,
+    ) -> Result<ArrangementPage, DDlogError> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(
+            &mut writer,
+            "dump_index {} limit {}{};",
+            self.inventory.get_index_name(iid).unwrap_or(&"???"),
+            limit,
+            after.map_or_else(String::new, |after| format!(
+                " after ({}), ({})",
+                after.key, after.val
+            ))
+        )
+        .map(|_| ArrangementPage::default())
+        .map_err(record_error)
     }
 }
 
@@ -337,14 +634,14 @@ where
         &self,
         rid: RelId,
         _cb: Option<&dyn Fn(&Record, isize) -> bool>,
-    ) -> Result<(), String> {
+    ) -> Result<(), DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(
             &mut writer,
             "dump {};",
             self.inventory.get_table_name(rid).unwrap_or(&"???")
         )
-        .map_err(|e| e.to_string())
+        .map_err(record_error)
     }
 
     fn dump_input_snapshot(&self, _w: &mut dyn Write) -> IOResult<()> {
@@ -357,30 +654,30 @@ where
     W: Write,
     I: Deref<Target = dyn DDlogInventory + Send + Sync>,
 {
-    fn enable_cpu_profiling(&self, enable: bool) -> Result<(), String> {
+    fn enable_cpu_profiling(&self, enable: bool) -> Result<(), DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(
             &mut writer,
             "profile cpu {};",
             if enable { "on" } else { "off" }
         )
-        .map_err(|e| e.to_string())
+        .map_err(record_error)
     }
 
-    fn enable_timely_profiling(&self, enable: bool) -> Result<(), String> {
+    fn enable_timely_profiling(&self, enable: bool) -> Result<(), DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(
             &mut writer,
             "profile timely {};",
             if enable { "on" } else { "off" }
         )
-        .map_err(|e| e.to_string())
+        .map_err(record_error)
     }
 
-    fn profile(&self) -> Result<String, String> {
+    fn profile(&self) -> Result<String, DDlogError> {
         let mut writer = self.writer.lock().unwrap();
         writeln!(&mut writer, "profile;")
-            .map_err(|e| e.to_string())
+            .map_err(record_error)
             .map(|_| "".to_string())
     }
 }
@@ -400,43 +697,55 @@ mod tests {
     struct DummyInventory;
 
     impl DDlogInventory for DummyInventory {
-        fn get_table_id(&self, _tname: &str) -> Result<RelId, String> {
+        fn get_table_id(&self, _tname: &str) -> Result<RelId, DDlogError> {
             unimplemented!()
         }
 
-        fn get_table_name(&self, _tid: RelId) -> Result<&'static str, String> {
+        fn get_table_name(&self, _tid: RelId) -> Result<&'static str, DDlogError> {
             unimplemented!()
         }
 
-        fn get_table_original_name(&self, _tname: &str) -> Result<&'static str, String> {
+        fn get_table_original_name(&self, _tname: &str) -> Result<&'static str, DDlogError> {
             unimplemented!()
         }
 
-        fn get_index_id(&self, _iname: &str) -> Result<IdxId, String> {
+        fn get_index_id(&self, _iname: &str) -> Result<IdxId, DDlogError> {
             unimplemented!()
         }
 
-        fn get_index_name(&self, _iid: IdxId) -> Result<&'static str, String> {
+        fn get_index_name(&self, _iid: IdxId) -> Result<&'static str, DDlogError> {
             unimplemented!()
         }
 
         #[cfg(feature = "c_api")]
-        fn get_table_original_cname(&self, _tname: &str) -> Result<&'static CStr, String> {
+        fn get_table_original_cname(&self, _tname: &str) -> Result<&'static CStr, DDlogError> {
             unimplemented!()
         }
 
         #[cfg(feature = "c_api")]
-        fn get_table_cname(&self, _tid: RelId) -> Result<&'static CStr, String> {
+        fn get_table_cname(&self, _tid: RelId) -> Result<&'static CStr, DDlogError> {
             unimplemented!()
         }
 
         #[cfg(feature = "c_api")]
-        fn get_index_cname(&self, _iid: IdxId) -> Result<&'static CStr, String> {
+        fn get_index_cname(&self, _iid: IdxId) -> Result<&'static CStr, DDlogError> {
             unimplemented!()
         }
 
         #[cfg(feature = "c_api")]
-        fn get_table_original_cname(&self, _tname: &str) -> Result<&'static CStr, String> {
+        fn get_table_original_cname(&self, _tname: &str) -> Result<&'static CStr, DDlogError> {
+            unimplemented!()
+        }
+
+        fn table_names(&self) -> Vec<&'static str> {
+            unimplemented!()
+        }
+
+        fn index_names(&self) -> Vec<&'static str> {
+            unimplemented!()
+        }
+
+        fn get_table_schema(&self, _tid: RelId) -> Result<String, DDlogError> {
             unimplemented!()
         }
 
@@ -444,7 +753,7 @@ mod tests {
             unimplemented!()
         }
 
-        fn index_from_record(&self, _index: IdxId, _key: &Record) -> Result<DDValue, String> {
+        fn index_from_record(&self, _index: IdxId, _key: &Record) -> Result<DDValue, DDlogError> {
             unimplemented!()
         }
 
@@ -456,7 +765,7 @@ mod tests {
             &self,
             _relation: &RelIdentifier,
             _value: &Record,
-        ) -> Result<(RelId, DDValue), String> {
+        ) -> Result<(RelId, DDValue), DDlogError> {
             unimplemented!()
         }
 
@@ -464,7 +773,7 @@ mod tests {
             &self,
             _relation: &RelIdentifier,
             _key: &Record,
-        ) -> Result<(RelId, DDValue), String> {
+        ) -> Result<(RelId, DDValue), DDlogError> {
             unimplemented!()
         }
 
@@ -504,4 +813,62 @@ update 10;
 "#;
         test(updates, expected);
     }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            parse_timestamp("#@ 1500000"),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(parse_timestamp("#@ soon"), None);
+        assert_eq!(parse_timestamp("start;"), None);
+
+        let mut buf = Vec::new();
+        let mut recorder = CommandRecorder::new(
+            &mut buf,
+            Box::new(DummyInventory) as Box<dyn DDlogInventory + Send + Sync>,
+        );
+        recorder.set_record_timestamps(true);
+        recorder.transaction_start().unwrap();
+        recorder.transaction_commit().unwrap();
+        drop(recorder);
+
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(parse_timestamp(lines[0]).is_some());
+        assert_eq!(&lines[1..], &["start;", "commit;"]);
+    }
+
+    #[test]
+    fn commits() {
+        let mut buf = Vec::new();
+        let recorder = CommandRecorder::new(
+            &mut buf,
+            Box::new(DummyInventory) as Box<dyn DDlogInventory + Send + Sync>,
+        );
+        recorder.record_commit(false, None).unwrap();
+        recorder.record_commit(true, None).unwrap();
+        recorder
+            .record_commit(false, Some(Duration::from_secs(5)))
+            .unwrap();
+        drop(recorder);
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "commit;\ncommit dump_changes;\ncommit timeout 5000ms;\n"
+        );
+    }
+
+    #[test]
+    fn changes_diff() {
+        let lines =
+            |lines: &[&str]| -> Vec<String> { lines.iter().map(|l| l.to_string()).collect() };
+        let expected = lines(&["R: 1: +1", "R: 2: +1", "S: (1, 2): -1"]);
+
+        assert!(diff_changes(&expected, &expected).is_empty());
+        assert_eq!(
+            diff_changes(&expected, &lines(&["R: 1: +1", "S: (1, 2): +1"])),
+            lines(&["- R: 2: +1", "- S: (1, 2): -1", "+ S: (1, 2): +1"])
+        );
+    }
 }
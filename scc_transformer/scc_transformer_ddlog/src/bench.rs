@@ -0,0 +1,251 @@
+//! Benchmark the generated program on a seeded `Edge` workload.
+//!
+//! Runs the same workload once per requested worker count and prints one line of
+//! `key=value` statistics for each run.  The final `StronglyConnected` contents are
+//! normalized so that every component is labelled by its smallest node, which makes the
+//! labelling comparable with other SCC implementations (`cargo xtask bench` uses this to
+//! check `scc` against `scc_transformer`).
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use differential_datalog::{
+    ddval::*,
+    program::{
+        config::{Config, ProfilingConfig},
+        *,
+    },
+    record::*,
+    DDlog, DDlogDynamic, DeltaMap,
+};
+use num_traits::cast::ToPrimitive;
+use rustop::opts;
+use scc_transformer_ddlog::*;
+use scc_workload::{EdgeUpdate, GraphKind};
+
+/// How long to give the profiling thread to catch up with the workers before reading
+/// arrangement sizes.
+const PROFILE_SETTLE_MILLIS: u64 = 200;
+
+/// Node to component label.
+type Labelling = BTreeMap<u32, u32>;
+
+struct RunStats {
+    commit_latencies: Vec<Duration>,
+    updates: usize,
+    elapsed: Duration,
+    peak_arrangement_size: isize,
+    labelling: Labelling,
+}
+
+fn main() -> Result<(), String> {
+    let parser = opts! {
+        synopsis "Benchmark the DDlog program on a generated graph.";
+        auto_shorts false;
+        opt kind:String=String::from("erdos-renyi"), desc:"Graph family: erdos-renyi, power-law, grid, chain, ring or planted-scc.";
+        opt nodes:u32=1000, desc:"The number of nodes in the graph. Rounded up to a multiple of ceil(sqrt(nodes)) by grid and planted-scc.";
+        opt edges:usize=10000, desc:"The number of edges to generate.";
+        opt seed:u64=0, desc:"Random seed.";
+        opt transactions:usize=1, desc:"Split the edges into this many incremental transactions.";
+        opt delete_ratio:f64=0.0, desc:"Probability that an update in an incremental transaction deletes a previously inserted edge.";
+        opt workers:String=String::from("1,2,4"), desc:"Comma-separated list of worker counts to benchmark.";
+        opt labels:Option<String>, desc:"Write the normalized 'node label' pairs of the final 'StronglyConnected' contents to this file.";
+    };
+    let (args, rest) = parser.parse_or_exit();
+
+    if !rest.is_empty() {
+        return Err("Invalid command line arguments; try -h for help".to_string());
+    }
+
+    let workers = args
+        .workers
+        .split(',')
+        .map(|workers| match workers.trim().parse::<usize>() {
+            Ok(0) => Err("Invalid number of workers: 0".to_string()),
+            Ok(workers) => Ok(workers),
+            Err(e) => Err(format!("Invalid number of workers '{}': {}", workers, e)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let kind = GraphKind::from_name(&args.kind, args.nodes, args.edges)?;
+    let edges = kind.generate(args.seed);
+    let transactions =
+        scc_workload::split_transactions(&edges, args.transactions, args.delete_ratio, args.seed);
+
+    let mut labelling: Option<Labelling> = None;
+    for &workers in workers.iter() {
+        let stats = bench(workers, &transactions)?;
+        print_stats(workers, &stats);
+
+        match &labelling {
+            Some(expected) if *expected != stats.labelling => {
+                return Err(format!(
+                    "StronglyConnected differs between worker counts ({} workers)",
+                    workers
+                ));
+            }
+            Some(_) => {}
+            None => labelling = Some(stats.labelling),
+        }
+    }
+
+    if let (Some(path), Some(labelling)) = (&args.labels, &labelling) {
+        write_labelling(path, labelling)
+            .map_err(|e| format!("failed to write '{}': {}", path, e))?;
+    }
+
+    Ok(())
+}
+
+fn bench(workers: usize, transactions: &[Vec<EdgeUpdate>]) -> Result<RunStats, String> {
+    let config = Config::new()
+        .with_timely_workers(workers)
+        .with_profiling_config(ProfilingConfig::SelfProfiling);
+    let (hddlog, init_state) = run_with_config(config, false)?;
+
+    let mut labelling = Labelling::new();
+    apply_delta(&mut labelling, &init_state);
+
+    let mut commit_latencies = Vec::with_capacity(transactions.len());
+    let mut updates = 0;
+    let start = Instant::now();
+
+    for transaction in transactions {
+        hddlog.transaction_start()?;
+        hddlog.apply_updates(&mut transaction.iter().map(|update| match *update {
+            EdgeUpdate::Insert((src, dest)) => Update::Insert {
+                relid: Relations::Edge as RelId,
+                v: types::Edge { src, dest }.into_ddvalue(),
+            },
+            EdgeUpdate::Delete((src, dest)) => Update::DeleteValue {
+                relid: Relations::Edge as RelId,
+                v: types::Edge { src, dest }.into_ddvalue(),
+            },
+        }))?;
+
+        let commit_start = Instant::now();
+        let delta = hddlog.transaction_commit_dump_changes()?;
+        commit_latencies.push(commit_start.elapsed());
+
+        updates += transaction.len();
+        apply_delta(&mut labelling, &delta);
+    }
+    let elapsed = start.elapsed();
+
+    // Arrangement sizes are collected by the profiling thread asynchronously.
+    thread::sleep(Duration::from_millis(PROFILE_SETTLE_MILLIS));
+    let peak_arrangement_size = hddlog
+        .prog
+        .lock()
+        .unwrap()
+        .profile
+        .as_ref()
+        .map(|profile| profile.lock().unwrap().total_peak_size())
+        .unwrap_or(0);
+
+    hddlog.stop()?;
+
+    Ok(RunStats {
+        commit_latencies,
+        updates,
+        elapsed,
+        peak_arrangement_size,
+        labelling: normalize(&labelling),
+    })
+}
+
+/// Extract `(node, label)` from either a `StronglyConnected{node, regime}` struct or a
+/// `(node, label)` tuple.
+fn node_and_label(record: &Record) -> Option<(u32, u32)> {
+    let fields: Vec<&Record> = match record {
+        Record::Tuple(fields) | Record::PosStruct(_, fields) => fields.iter().collect(),
+        Record::NamedStruct(_, fields) => fields.iter().map(|(_, field)| field).collect(),
+        _ => return None,
+    };
+
+    match fields.as_slice() {
+        [node, label] => Some((node.as_int()?.to_u32()?, label.as_int()?.to_u32()?)),
+        _ => None,
+    }
+}
+
+fn apply_delta(labelling: &mut Labelling, delta: &DeltaMap<DDValue>) {
+    let changes = match delta.try_get_rel(Relations::StronglyConnected as RelId) {
+        Some(changes) => changes,
+        None => return,
+    };
+
+    // Apply retractions first so that a relabelled node isn't removed after being re-added.
+    let mut changes: Vec<(&DDValue, &isize)> = changes.iter().collect();
+    changes.sort_by_key(|(_, weight)| **weight > 0);
+
+    for (value, weight) in changes {
+        if let Some((node, label)) = node_and_label(&value.clone().into_record()) {
+            if *weight > 0 {
+                labelling.insert(node, label);
+            } else if labelling.get(&node) == Some(&label) {
+                labelling.remove(&node);
+            }
+        }
+    }
+}
+
+/// Relabel every component by its smallest node.
+fn normalize(labelling: &Labelling) -> Labelling {
+    let mut min_node: BTreeMap<u32, u32> = BTreeMap::new();
+    for (&node, &label) in labelling.iter() {
+        min_node.entry(label).or_insert(node);
+    }
+
+    labelling
+        .iter()
+        .map(|(&node, label)| (node, min_node[label]))
+        .collect()
+}
+
+fn print_stats(workers: usize, stats: &RunStats) {
+    let mut latencies = stats.commit_latencies.clone();
+    latencies.sort();
+
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let percentile = |p: usize| {
+        latencies
+            .get((latencies.len() * p / 100).min(latencies.len().saturating_sub(1)))
+            .copied()
+            .unwrap_or_default()
+    };
+    let mean = latencies.iter().sum::<Duration>() / latencies.len().max(1) as u32;
+
+    println!(
+        "workers={} commits={} updates={} total_ms={:.3} commit_mean_ms={:.3} commit_p50_ms={:.3} commit_p99_ms={:.3} commit_max_ms={:.3} throughput_upd_per_s={:.0} peak_arrangement_size={} components={}",
+        workers,
+        latencies.len(),
+        stats.updates,
+        millis(stats.elapsed),
+        millis(mean),
+        millis(percentile(50)),
+        millis(percentile(99)),
+        millis(latencies.last().copied().unwrap_or_default()),
+        stats.updates as f64 / stats.elapsed.as_secs_f64().max(f64::EPSILON),
+        stats.peak_arrangement_size,
+        stats
+            .labelling
+            .iter()
+            .filter(|(node, label)| node == label)
+            .count(),
+    );
+}
+
+fn write_labelling(path: &str, labelling: &Labelling) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    for (node, label) in labelling.iter() {
+        writeln!(out, "{} {}", node, label)?;
+    }
+
+    out.flush()
+}
@@ -22,6 +22,8 @@
 
 mod inventory;
 pub mod ovsdb_api;
+#[cfg(test)]
+mod tests;
 
 pub use inventory::{D3logInventory, Inventory};
 
@@ -279,7 +281,7 @@ pub fn run_with_config(
         ::differential_datalog::api::HDDlog,
         ::differential_datalog::DeltaMap<DDValue>,
     ),
-    String,
+    ::differential_datalog::DDlogError,
 > {
     #[cfg(feature = "flatbuf")]
     let flatbuf_converter = Box::new(crate::flatbuf::DDlogFlatbufConverter);
@@ -314,7 +316,7 @@ pub fn run(
         ::differential_datalog::api::HDDlog,
         ::differential_datalog::DeltaMap<DDValue>,
     ),
-    String,
+    ::differential_datalog::DDlogError,
 > {
     let config =
         ::differential_datalog::program::config::Config::new().with_timely_workers(workers);
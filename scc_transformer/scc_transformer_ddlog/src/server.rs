@@ -0,0 +1,497 @@
+//! `--listen` mode: serve the command language to multiple clients over TCP or a Unix domain
+//! socket.
+//!
+//! Clients send commands in the same syntax as stdin, or one JSON object per line with
+//! `--input-format json`.  The result of every command is sent back as a frame: a header line,
+//! `ok <len>` or `error <len>`, followed by `<len>` bytes holding the command's output (e.g., the
+//! changes printed by `commit dump_changes`) or the error message.
+//!
+//! Commands of different clients are executed one at a time.  Once a client starts a
+//! transaction, the commands of other clients wait until the transaction ends, i.e., the client
+//! commits or rolls it back or its commit times out, so that transactions are serialized.  A
+//! transaction left open by a client that disconnects is rolled back.
+//!
+//! The `shutdown` command stops the server: it disconnects all clients, rolling back a
+//! transaction left open by any of them, and stops the program.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use cmd_parser::{parse_json_command, Command, CommandParser, InputFormat};
+use differential_datalog::{api::HDDlog, ddval::DDValue, program::Update, DDlogDynamic};
+
+use crate::{handle_cmd, CsvDeltas, OutputFormat};
+
+/// Prefix of addresses of Unix domain sockets; other addresses are TCP socket addresses.
+const UNIX_PREFIX: &str = "unix:";
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    fn bind(addr: &str) -> Result<Self, String> {
+        let listener = match addr.strip_prefix(UNIX_PREFIX) {
+            #[cfg(unix)]
+            Some(path) => UnixListener::bind(path).map(Listener::Unix),
+            #[cfg(not(unix))]
+            Some(_) => {
+                return Err("Unix domain sockets are not supported on this platform".to_string())
+            }
+            None => TcpListener::bind(addr).map(Listener::Tcp),
+        };
+        listener.map_err(|e| format!("Failed to listen on '{}': {}", addr, e))
+    }
+
+    fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().map(|(stream, _)| Stream::Unix(stream)),
+        }
+    }
+
+    /// Connect to the listener, so that a thread blocked in `accept` returns.
+    fn wake(&self) -> io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => {
+                let mut addr = listener.local_addr()?;
+                if addr.ip().is_unspecified() {
+                    addr = match addr {
+                        SocketAddr::V4(_) => SocketAddr::from(([127, 0, 0, 1], addr.port())),
+                        SocketAddr::V6(_) => {
+                            SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], addr.port()))
+                        }
+                    };
+                }
+                TcpStream::connect(addr).map(drop)
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => match listener.local_addr()?.as_pathname() {
+                Some(path) => UnixStream::connect(path).map(drop),
+                None => Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "the socket has no path",
+                )),
+            },
+        }
+    }
+}
+
+/// A client's connection.
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+struct Server<'a> {
+    hddlog: &'a HDDlog,
+    listener: Listener,
+    print_deltas: bool,
+    input: InputFormat,
+    format: OutputFormat,
+    start_time: Instant,
+    /// The client whose transaction is in progress, if any.
+    owner: Mutex<Option<usize>>,
+    released: Condvar,
+    /// Set by the `shutdown` command.
+    shutting_down: AtomicBool,
+    /// Connections of the clients being served, which are shut down to disconnect the clients
+    /// when the server stops.
+    connections: Mutex<HashMap<usize, Stream>>,
+}
+
+/// Accept clients on `addr`, either a TCP socket address or `unix:<path>`, and execute their
+/// commands until a client sends `shutdown`.
+pub fn listen(
+    hddlog: HDDlog,
+    addr: &str,
+    print_deltas: bool,
+    input: InputFormat,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let listener = Listener::bind(addr)?;
+    eprintln!("Listening on {}", addr);
+
+    let result = serve(hddlog, listener, print_deltas, input, format);
+    if let Some(path) = addr.strip_prefix(UNIX_PREFIX) {
+        let _ = std::fs::remove_file(path);
+    }
+    result
+}
+
+/// Execute the commands of the clients of `listener` until a client sends `shutdown`, and then
+/// stop the program.
+fn serve(
+    hddlog: HDDlog,
+    listener: Listener,
+    print_deltas: bool,
+    input: InputFormat,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let server = Server {
+        hddlog: &hddlog,
+        listener,
+        print_deltas,
+        input,
+        format,
+        start_time: Instant::now(),
+        owner: Mutex::new(None),
+        released: Condvar::new(),
+        shutting_down: AtomicBool::new(false),
+        connections: Mutex::new(HashMap::new()),
+    };
+    thread::scope(|scope| {
+        for client in 0.. {
+            let stream = server.listener.accept();
+            if server.shutting_down.load(Ordering::SeqCst) {
+                break;
+            }
+
+            match stream.and_then(|stream| Ok((stream.try_clone()?, stream))) {
+                Ok((connection, stream)) => {
+                    server
+                        .connections
+                        .lock()
+                        .unwrap()
+                        .insert(client, connection);
+                    let server = &server;
+                    scope.spawn(move || {
+                        if let Err(e) = server.serve(client, stream) {
+                            eprintln!("Client {}: {}", client, e);
+                        }
+                    });
+                }
+                Err(e) => eprintln!("Failed to accept connection: {}", e),
+            }
+        }
+
+        // Disconnect the remaining clients, whose threads the scope waits for.
+        for connection in server.connections.lock().unwrap().values() {
+            let _ = connection.shutdown();
+        }
+    });
+
+    hddlog.stop().map_err(String::from)
+}
+
+impl Server<'_> {
+    fn serve(&self, client: usize, stream: Stream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        let result = self.serve_commands(client, BufReader::new(stream), &mut writer);
+        self.connections.lock().unwrap().remove(&client);
+
+        // Roll back the transaction left open by the client.
+        let mut owner = self.owner.lock().unwrap();
+        if *owner == Some(client) {
+            let _ = self.hddlog.transaction_rollback();
+            *owner = None;
+            self.released.notify_all();
+        }
+
+        result
+    }
+
+    fn serve_commands(
+        &self,
+        client: usize,
+        mut reader: impl BufRead,
+        writer: &mut dyn Write,
+    ) -> io::Result<()> {
+        let mut parser = CommandParser::default();
+        let mut upds = Vec::new();
+        let mut csv_deltas = Vec::new();
+        let mut line = String::new();
+        let mut lineno: usize = 0;
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            lineno += 1;
+
+            let cmds = match self.input {
+                InputFormat::Text => parser.push(&line),
+                InputFormat::Json if line.trim().is_empty() => continue,
+                InputFormat::Json => vec![parse_json_command(line.trim())
+                    .map_err(|e| format!("Invalid input at line {}: {}", lineno, e))],
+            };
+            for cmd in cmds {
+                let done = matches!(cmd, Ok(Command::Exit) | Ok(Command::Shutdown));
+                let shutdown = cmd == Ok(Command::Shutdown);
+                let (result, output) = match cmd {
+                    Ok(cmd) => {
+                        let mut output = Vec::new();
+                        let result =
+                            self.execute(client, cmd, &mut upds, &mut csv_deltas, &mut output);
+                        (result, output)
+                    }
+                    Err(e) => (Err(e), Vec::new()),
+                };
+                write_frame(writer, result, output)?;
+
+                if shutdown {
+                    self.shut_down();
+                }
+                if done {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Execute a command of `client` once no other client has a transaction in progress.
+    fn execute(
+        &self,
+        client: usize,
+        cmd: Command,
+        upds: &mut Vec<Update<DDValue>>,
+        csv_deltas: &mut Vec<CsvDeltas>,
+        output: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        let mut owner = self.owner.lock().unwrap();
+        while owner.is_some_and(|other| other != client) {
+            owner = self.released.wait(owner).unwrap();
+        }
+
+        let (result, _) = handle_cmd(
+            self.start_time,
+            self.hddlog,
+            self.print_deltas,
+            self.format,
+            false,
+            upds,
+            csv_deltas,
+            output,
+            cmd,
+        );
+
+        // The client owns the server while its transaction is in progress.  Failed commits may
+        // end the transaction, e.g., when they time out.
+        if self.hddlog.transaction_in_progress() {
+            *owner = Some(client);
+        } else if owner.take().is_some() {
+            self.released.notify_all();
+        }
+
+        result
+    }
+
+    /// Make the accept loop stop accepting clients.
+    fn shut_down(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        if let Err(e) = self.listener.wake() {
+            eprintln!("Failed to stop accepting connections: {}", e);
+        }
+    }
+}
+
+fn write_frame(
+    writer: &mut dyn Write,
+    result: Result<(), String>,
+    output: Vec<u8>,
+) -> io::Result<()> {
+    let (status, body) = match result {
+        Ok(()) => ("ok", output),
+        Err(e) => ("error", e.into_bytes()),
+    };
+
+    writeln!(writer, "{} {}", status, body.len())?;
+    writer.write_all(&body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{serve, Listener};
+    use crate::{run_with_config, OutputFormat};
+    use cmd_parser::InputFormat;
+    use differential_datalog::program::config::Config;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+        thread,
+    };
+
+    /// Send `cmd` and return the status and body of the reply.
+    fn request(reader: &mut impl BufRead, writer: &mut TcpStream, cmd: &str) -> (String, String) {
+        writeln!(writer, "{}", cmd).unwrap();
+
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let (status, len) = header.trim_end().split_once(' ').unwrap();
+        let mut body = vec![0; len.parse().unwrap()];
+        reader.read_exact(&mut body).unwrap();
+        (status.to_string(), String::from_utf8(body).unwrap())
+    }
+
+    #[test]
+    fn serve_commands_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (hddlog, _) = run_with_config(Config::new(), true).unwrap();
+            serve(
+                hddlog,
+                Listener::Tcp(listener),
+                true,
+                InputFormat::Text,
+                OutputFormat::Text,
+            )
+        });
+
+        let mut writer = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(writer.try_clone().unwrap());
+        assert_eq!(
+            request(&mut reader, &mut writer, "start;"),
+            ("ok".to_string(), String::new())
+        );
+        assert_eq!(
+            request(
+                &mut reader,
+                &mut writer,
+                "insert Edge(1, 2), insert Edge(2, 1);"
+            )
+            .0,
+            "ok"
+        );
+        let (status, changes) = request(&mut reader, &mut writer, "commit dump_changes;");
+        assert_eq!(status, "ok");
+        assert!(changes.contains("StronglyConnected{.node = 1, .regime = 1}: +1"));
+        assert_eq!(
+            request(&mut reader, &mut writer, "commit;").0,
+            "error",
+            "commit without a transaction"
+        );
+
+        // A second client sees the committed state, and stops the server.
+        let mut writer = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(writer.try_clone().unwrap());
+        assert_eq!(
+            request(&mut reader, &mut writer, "count StronglyConnected;"),
+            ("ok".to_string(), "2\n".to_string())
+        );
+        assert_eq!(
+            request(&mut reader, &mut writer, "shutdown;"),
+            ("ok".to_string(), String::new())
+        );
+        assert_eq!(server.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn cancelled_commit_releases_the_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (token_send, token_recv) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let (hddlog, _) = run_with_config(Config::new(), true).unwrap();
+            token_send.send(hddlog.cancellation_token()).unwrap();
+            serve(
+                hddlog,
+                Listener::Tcp(listener),
+                false,
+                InputFormat::Text,
+                OutputFormat::Text,
+            )
+        });
+        let token = token_recv.recv().unwrap();
+
+        // The commit of the first client is cancelled, which rolls back its transaction.
+        let mut writer = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(writer.try_clone().unwrap());
+        assert_eq!(request(&mut reader, &mut writer, "start;").0, "ok");
+        assert_eq!(
+            request(&mut reader, &mut writer, "insert Edge(1, 2);").0,
+            "ok"
+        );
+        token.cancel();
+        let (status, error) = request(&mut reader, &mut writer, "commit timeout 5s;");
+        assert_eq!(status, "error");
+        assert!(error.contains("cancelled"), "{}", error);
+
+        // A second client can start a transaction while the first one is still connected.
+        let mut other_writer = TcpStream::connect(addr).unwrap();
+        let mut other_reader = BufReader::new(other_writer.try_clone().unwrap());
+        assert_eq!(
+            request(&mut other_reader, &mut other_writer, "start;").0,
+            "ok"
+        );
+        assert_eq!(
+            request(&mut other_reader, &mut other_writer, "insert Edge(2, 1);").0,
+            "ok"
+        );
+        assert_eq!(
+            request(&mut other_reader, &mut other_writer, "commit;").0,
+            "ok"
+        );
+        assert_eq!(
+            request(&mut reader, &mut writer, "count Edge;"),
+            ("ok".to_string(), "1\n".to_string())
+        );
+        assert_eq!(request(&mut reader, &mut writer, "shutdown;").0, "ok");
+        assert_eq!(server.join().unwrap(), Ok(()));
+    }
+}
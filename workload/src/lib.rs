@@ -0,0 +1,443 @@
+//! Seeded graph workloads for the SCC programs.
+//!
+//! Graphs are generated as plain edge lists and then written out in the
+//! `cmd_parser` text format as one or more `insert Edge(..)` transactions, so
+//! the output can be piped straight into `scc_cli`.  Splitting a graph into
+//! several transactions with a non-zero delete ratio exercises the incremental
+//! path of `StronglyConnected` instead of only the initial bulk load.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt,
+    io::{self, Write},
+};
+
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// A directed edge `(from, to)`.
+pub type Edge = (u32, u32);
+
+/// Graph families supported by the generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphKind {
+    /// `G(n, m)`: `edges` distinct directed edges picked uniformly at random.
+    ErdosRenyi { nodes: u32, edges: usize },
+    /// Barabási–Albert preferential attachment, every new node attaches
+    /// `edges_per_node` edges in a random direction.
+    PowerLaw { nodes: u32, edges_per_node: usize },
+    /// A `width` x `height` lattice with edges in both directions between
+    /// neighbours.
+    Grid { width: u32, height: u32 },
+    /// `0 -> 1 -> ... -> nodes - 1`, optionally closed into a ring.
+    Chain { nodes: u32, cycle: bool },
+    /// `components` strongly connected components of `component_size` nodes
+    /// each (a ring plus `chords` random extra edges), linked by `bridges`
+    /// edges that never close a cycle between components.
+    PlantedScc {
+        components: u32,
+        component_size: u32,
+        chords: usize,
+        bridges: usize,
+    },
+}
+
+/// Names accepted by [`GraphKind::from_name`].
+pub const GRAPH_KINDS: &[&str] = &[
+    "erdos-renyi",
+    "power-law",
+    "grid",
+    "chain",
+    "ring",
+    "planted-scc",
+];
+
+impl GraphKind {
+    /// Build a graph kind from its command-line name, deriving the shape
+    /// parameters from a node and edge budget.
+    ///
+    /// `grid` and `planted-scc` lay the nodes out in rows (or components) of
+    /// `ceil(sqrt(nodes))` nodes, so their node count is rounded up to the next
+    /// multiple of that width.
+    pub fn from_name(name: &str, nodes: u32, edges: usize) -> Result<Self, String> {
+        if nodes == 0 {
+            return Err("the number of nodes must be non-zero".to_string());
+        }
+
+        match name {
+            "erdos-renyi" | "er" | "gnm" => {
+                let max_edges = nodes as usize * (nodes as usize - 1);
+                if edges > max_edges {
+                    return Err(format!(
+                        "cannot fit {} distinct edges into a graph with {} nodes",
+                        edges, nodes
+                    ));
+                }
+                Ok(GraphKind::ErdosRenyi { nodes, edges })
+            }
+            "power-law" | "ba" => Ok(GraphKind::PowerLaw {
+                nodes,
+                edges_per_node: (edges / nodes as usize).max(1),
+            }),
+            "grid" => {
+                let width = (nodes as f64).sqrt().ceil() as u32;
+                Ok(GraphKind::Grid {
+                    width,
+                    height: nodes.div_ceil(width),
+                })
+            }
+            "chain" => Ok(GraphKind::Chain {
+                nodes,
+                cycle: false,
+            }),
+            "ring" => Ok(GraphKind::Chain { nodes, cycle: true }),
+            "planted-scc" | "scc" => {
+                let component_size = (nodes as f64).sqrt().ceil() as u32;
+                let components = nodes.div_ceil(component_size);
+                let spare = edges.saturating_sub(nodes as usize);
+                Ok(GraphKind::PlantedScc {
+                    components,
+                    component_size,
+                    chords: spare / 2,
+                    bridges: spare - spare / 2,
+                })
+            }
+            _ => Err(format!(
+                "unknown graph kind '{}', expected one of: {}",
+                name,
+                GRAPH_KINDS.join(", ")
+            )),
+        }
+    }
+
+    /// Generate the edges of this graph.  The same seed always produces the
+    /// same edge list, in the same order.
+    pub fn generate(&self, seed: u64) -> Vec<Edge> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let mut edges = match *self {
+            GraphKind::ErdosRenyi { nodes, edges } => erdos_renyi(&mut rng, nodes, edges),
+            GraphKind::PowerLaw {
+                nodes,
+                edges_per_node,
+            } => power_law(&mut rng, nodes, edges_per_node),
+            GraphKind::Grid { width, height } => grid(width, height),
+            GraphKind::Chain { nodes, cycle } => chain(nodes, cycle),
+            GraphKind::PlantedScc {
+                components,
+                component_size,
+                chords,
+                bridges,
+            } => planted_scc(&mut rng, components, component_size, chords, bridges),
+        };
+
+        edges.shuffle(&mut rng);
+        edges
+    }
+}
+
+fn erdos_renyi(rng: &mut impl Rng, nodes: u32, edges: usize) -> Vec<Edge> {
+    let mut seen = HashSet::with_capacity(edges);
+    let mut result = Vec::with_capacity(edges);
+
+    if nodes < 2 {
+        return result;
+    }
+
+    while result.len() < edges {
+        let from = rng.gen_range(0..nodes);
+        let to = rng.gen_range(0..nodes);
+
+        if from != to && seen.insert((from, to)) {
+            result.push((from, to));
+        }
+    }
+
+    result
+}
+
+fn power_law(rng: &mut impl Rng, nodes: u32, edges_per_node: usize) -> Vec<Edge> {
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+
+    // Every node appears here once per incident edge, so picking a uniform
+    // element picks a node with probability proportional to its degree.
+    let mut endpoints: Vec<u32> = Vec::new();
+    let seed_nodes = (edges_per_node as u32 + 1).min(nodes);
+
+    for from in 0..seed_nodes {
+        for to in 0..from {
+            let edge = if rng.gen() { (from, to) } else { (to, from) };
+            seen.insert(edge);
+            result.push(edge);
+            endpoints.extend_from_slice(&[from, to]);
+        }
+    }
+
+    for node in seed_nodes..nodes {
+        let mut targets = BTreeSet::new();
+        while targets.len() < edges_per_node.min(node as usize) {
+            targets.insert(*endpoints.choose(rng).unwrap_or(&0));
+        }
+
+        for target in targets {
+            let edge = if rng.gen() {
+                (node, target)
+            } else {
+                (target, node)
+            };
+
+            if seen.insert(edge) {
+                result.push(edge);
+                endpoints.extend_from_slice(&[node, target]);
+            }
+        }
+    }
+
+    result
+}
+
+fn grid(width: u32, height: u32) -> Vec<Edge> {
+    let mut result = Vec::new();
+    let node = |x: u32, y: u32| y * width + x;
+
+    for y in 0..height {
+        for x in 0..width {
+            if x + 1 < width {
+                result.push((node(x, y), node(x + 1, y)));
+                result.push((node(x + 1, y), node(x, y)));
+            }
+            if y + 1 < height {
+                result.push((node(x, y), node(x, y + 1)));
+                result.push((node(x, y + 1), node(x, y)));
+            }
+        }
+    }
+
+    result
+}
+
+fn chain(nodes: u32, cycle: bool) -> Vec<Edge> {
+    let mut result: Vec<Edge> = (1..nodes).map(|to| (to - 1, to)).collect();
+    if cycle && nodes > 1 {
+        result.push((nodes - 1, 0));
+    }
+
+    result
+}
+
+fn planted_scc(
+    rng: &mut impl Rng,
+    components: u32,
+    component_size: u32,
+    chords: usize,
+    bridges: usize,
+) -> Vec<Edge> {
+    // Relabel nodes so that components aren't contiguous id ranges.
+    let mut labels: Vec<u32> = (0..components * component_size).collect();
+    labels.shuffle(rng);
+    let label =
+        |component: u32, offset: u32| labels[(component * component_size + offset) as usize];
+
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    let mut push = |edge: Edge, result: &mut Vec<Edge>| {
+        if edge.0 != edge.1 && seen.insert(edge) {
+            result.push(edge);
+        }
+    };
+
+    for component in 0..components {
+        for offset in 0..component_size {
+            let next = (offset + 1) % component_size;
+            push(
+                (label(component, offset), label(component, next)),
+                &mut result,
+            );
+        }
+    }
+
+    if component_size > 1 {
+        for _ in 0..chords {
+            let component = rng.gen_range(0..components);
+            let from = rng.gen_range(0..component_size);
+            let to = rng.gen_range(0..component_size);
+            push((label(component, from), label(component, to)), &mut result);
+        }
+    }
+
+    // Bridges only ever go from a lower to a higher component, so the
+    // condensation stays acyclic and the planted components are exactly the
+    // strongly connected components of the graph.
+    if components > 1 {
+        for _ in 0..bridges {
+            let from = rng.gen_range(0..components - 1);
+            let to = rng.gen_range(from + 1..components);
+            push(
+                (
+                    label(from, rng.gen_range(0..component_size)),
+                    label(to, rng.gen_range(0..component_size)),
+                ),
+                &mut result,
+            );
+        }
+    }
+
+    result
+}
+
+/// A single update to the `Edge` relation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeUpdate {
+    Insert(Edge),
+    Delete(Edge),
+}
+
+impl fmt::Display for EdgeUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdgeUpdate::Insert((from, to)) => write!(f, "insert Edge({}, {})", from, to),
+            EdgeUpdate::Delete((from, to)) => write!(f, "delete Edge({}, {})", from, to),
+        }
+    }
+}
+
+/// Split `edges` into `transactions` incremental transactions.
+///
+/// With a `delete_ratio` of zero every edge is inserted exactly once.  With a
+/// non-zero ratio each update deletes a currently present edge with that
+/// probability; deleted edges are queued up to be re-inserted later, so the
+/// stream never deletes an edge that isn't there.
+pub fn split_transactions(
+    edges: &[Edge],
+    transactions: usize,
+    delete_ratio: f64,
+    seed: u64,
+) -> Vec<Vec<EdgeUpdate>> {
+    let transactions = transactions.max(1);
+    let delete_ratio = delete_ratio.clamp(0.0, 1.0);
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    // Keep the total number of updates proportional to the graph so that
+    // deletions don't starve out the inserts.
+    let total = if delete_ratio < 1.0 {
+        (edges.len() as f64 / (1.0 - delete_ratio)).ceil() as usize
+    } else {
+        edges.len()
+    };
+    let per_transaction = total.div_ceil(transactions).max(1);
+
+    let mut pending: Vec<Edge> = edges.iter().rev().copied().collect();
+    let mut present: Vec<Edge> = Vec::with_capacity(edges.len());
+    let mut result = Vec::with_capacity(transactions);
+
+    for _ in 0..transactions {
+        let mut transaction = Vec::with_capacity(per_transaction);
+        let mut touched = HashSet::new();
+
+        for _ in 0..per_transaction {
+            let delete = !present.is_empty() && (pending.is_empty() || rng.gen_bool(delete_ratio));
+
+            if delete {
+                let idx = rng.gen_range(0..present.len());
+                if !touched.insert(present[idx]) {
+                    continue;
+                }
+
+                let edge = present.swap_remove(idx);
+                transaction.push(EdgeUpdate::Delete(edge));
+                pending.insert(rng.gen_range(0..=pending.len()), edge);
+            } else if let Some(edge) = pending.pop() {
+                if !touched.insert(edge) {
+                    pending.push(edge);
+                    continue;
+                }
+
+                transaction.push(EdgeUpdate::Insert(edge));
+                present.push(edge);
+            }
+        }
+
+        result.push(transaction);
+    }
+
+    result
+}
+
+/// Write transactions in the `cmd_parser` text format, each one followed by
+/// `commit dump_changes;` and the whole stream terminated by `timestamp;`.
+pub fn write_transactions<W: Write>(
+    out: &mut W,
+    transactions: &[Vec<EdgeUpdate>],
+) -> io::Result<()> {
+    for transaction in transactions {
+        writeln!(out, "start;")?;
+
+        for (idx, update) in transaction.iter().enumerate() {
+            let terminator = if idx + 1 == transaction.len() {
+                ';'
+            } else {
+                ','
+            };
+            writeln!(out, "{}{}", update, terminator)?;
+        }
+
+        writeln!(out, "commit dump_changes;")?;
+    }
+
+    writeln!(out, "timestamp;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_is_deterministic() {
+        for name in GRAPH_KINDS {
+            let kind = GraphKind::from_name(name, 100, 400).unwrap();
+            assert_eq!(kind.generate(7), kind.generate(7), "{}", name);
+        }
+    }
+
+    #[test]
+    fn split_transactions_replays_to_a_valid_set() {
+        let edges = GraphKind::from_name("erdos-renyi", 50, 200)
+            .unwrap()
+            .generate(1);
+        let transactions = split_transactions(&edges, 10, 0.3, 1);
+        assert_eq!(transactions.len(), 10);
+
+        let mut present = HashSet::new();
+        for update in transactions.iter().flatten() {
+            match *update {
+                EdgeUpdate::Insert(edge) => assert!(present.insert(edge)),
+                EdgeUpdate::Delete(edge) => assert!(present.remove(&edge)),
+            }
+        }
+
+        let bulk = split_transactions(&edges, 1, 0.0, 1);
+        assert_eq!(bulk[0].len(), edges.len());
+    }
+
+    #[test]
+    fn unknown_kind_lists_every_kind() {
+        let err = GraphKind::from_name("hypercube", 10, 10).unwrap_err();
+        for name in GRAPH_KINDS {
+            assert!(err.contains(name), "{}", err);
+        }
+    }
+
+    #[test]
+    fn square_kinds_round_nodes_up() {
+        // 10 nodes are laid out in rows of 4.
+        for name in &["grid", "planted-scc"] {
+            let edges = GraphKind::from_name(name, 10, 10).unwrap().generate(0);
+            let nodes: HashSet<u32> = edges
+                .iter()
+                .flat_map(|&(from, to)| vec![from, to])
+                .collect();
+            assert_eq!(nodes.len(), 12, "{}", name);
+        }
+    }
+}
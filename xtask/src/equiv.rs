@@ -0,0 +1,325 @@
+//! Differential equivalence checking between `scc` and `scc_transformer`.
+//!
+//! The two programs can't be linked into the same binary (their runtime crates collide), so
+//! both CLIs are driven as subprocesses with the same stream of `Edge` transactions.  After
+//! every commit the `StronglyConnected` deltas of both programs are normalized so that every
+//! component is labelled by its smallest node and then compared.  When they differ, the stream
+//! is shrunk to a minimal one that still makes the programs disagree.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// A stream of transactions, each a list of update commands such as `insert Edge(1, 2)`.
+pub type Stream = Vec<Vec<String>>;
+
+/// Changes to the normalized `(node, label)` pairs of `StronglyConnected`.
+type Delta = BTreeMap<(u32, u32), isize>;
+
+const COMMIT_MARKER: &str = "xtask-equiv-commit";
+
+/// Parse a stream of transactions in the `cmd_parser` text format written by `scc_gen`.
+/// Only `insert`/`delete` updates inside `start; ... commit;` blocks are kept.
+pub fn parse_stream(text: &str) -> Result<Stream, String> {
+    let mut stream = Vec::new();
+    let mut transaction: Option<Vec<String>> = None;
+
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line == "start;" {
+            if transaction.is_some() {
+                return Err(format!("line {}: nested 'start'", lineno + 1));
+            }
+            transaction = Some(Vec::new());
+        } else if line.starts_with("commit") {
+            stream.push(transaction.take().ok_or_else(|| {
+                format!("line {}: 'commit' outside of a transaction", lineno + 1)
+            })?);
+        } else if line.starts_with("insert ") || line.starts_with("delete ") {
+            transaction
+                .as_mut()
+                .ok_or_else(|| format!("line {}: update outside of a transaction", lineno + 1))?
+                .push(line.trim_end_matches([',', ';']).to_string());
+        }
+    }
+
+    if transaction.is_some() {
+        return Err("unterminated transaction at the end of the stream".to_string());
+    }
+
+    Ok(stream)
+}
+
+/// Render a stream as a replayable `.dat` file.
+pub fn render_stream(stream: &Stream) -> String {
+    render(stream, false)
+}
+
+fn render(stream: &Stream, markers: bool) -> String {
+    let mut out = String::new();
+    for transaction in stream {
+        out.push_str("start;\n");
+        for (idx, update) in transaction.iter().enumerate() {
+            let terminator = if idx + 1 == transaction.len() {
+                ';'
+            } else {
+                ','
+            };
+            let _ = writeln!(out, "{}{}", update, terminator);
+        }
+        out.push_str("commit dump_changes;\n");
+        if markers {
+            let _ = writeln!(out, "echo {};", COMMIT_MARKER);
+        }
+    }
+
+    out
+}
+
+/// Outcome of running a stream through both programs.
+pub enum Outcome {
+    Agree,
+    /// The programs disagree on the normalized deltas of this commit.
+    Mismatch {
+        commit: usize,
+        scc: Vec<((u32, u32), isize)>,
+        transformer: Vec<((u32, u32), isize)>,
+    },
+}
+
+/// Feed `stream` to both executables and compare their normalized deltas commit by commit.
+pub fn compare(
+    scc_cli: &Path,
+    transformer_cli: &Path,
+    workers: usize,
+    stream: &Stream,
+) -> Result<Outcome, String> {
+    let scc = normalized_deltas(scc_cli, workers, stream)?;
+    let transformer = normalized_deltas(transformer_cli, workers, stream)?;
+
+    for (commit, (scc, transformer)) in scc.into_iter().zip(transformer).enumerate() {
+        if scc != transformer {
+            return Ok(Outcome::Mismatch {
+                commit,
+                scc: scc.into_iter().collect(),
+                transformer: transformer.into_iter().collect(),
+            });
+        }
+    }
+
+    Ok(Outcome::Agree)
+}
+
+/// Shrink a failing stream to a (locally) minimal one on which the programs still disagree,
+/// first by dropping whole transactions and then individual updates.
+pub fn shrink(
+    scc_cli: &Path,
+    transformer_cli: &Path,
+    workers: usize,
+    stream: &Stream,
+    failing_commit: usize,
+) -> Stream {
+    // A candidate that makes either program error out (e.g., by deleting an edge it never
+    // inserted) isn't a useful reproducer.
+    let still_fails = |candidate: &Stream| {
+        matches!(
+            compare(scc_cli, transformer_cli, workers, candidate),
+            Ok(Outcome::Mismatch { .. })
+        )
+    };
+
+    // Nothing after the failing commit can matter.
+    let mut stream: Stream = stream[..=failing_commit].to_vec();
+
+    let mut idx = 0;
+    while idx < stream.len() {
+        let mut candidate = stream.clone();
+        candidate.remove(idx);
+        if !candidate.is_empty() && still_fails(&candidate) {
+            stream = candidate;
+        } else {
+            idx += 1;
+        }
+    }
+
+    for transaction in 0..stream.len() {
+        let mut idx = 0;
+        while idx < stream[transaction].len() {
+            let mut candidate = stream.clone();
+            candidate[transaction].remove(idx);
+            if still_fails(&candidate) {
+                stream = candidate;
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    stream.retain(|transaction| !transaction.is_empty());
+    stream
+}
+
+/// Run `stream` through a CLI and return the normalized `StronglyConnected` delta of every
+/// commit.
+fn normalized_deltas(cli: &Path, workers: usize, stream: &Stream) -> Result<Vec<Delta>, String> {
+    let mut child = Command::new(cli)
+        .arg("--workers")
+        .arg(workers.to_string())
+        .args(["--no-store", "--no-init-snapshot"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run '{}': {}", cli.display(), e))?;
+
+    let input = render(stream, true);
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run '{}': {}", cli.display(), e))?;
+    let _ = writer.join();
+
+    if !output.status.success() {
+        return Err(format!(
+            "'{}' exited with {}: {}",
+            cli.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut labelling: BTreeMap<u32, u32> = BTreeMap::new();
+    let mut normalized = normalize(&labelling);
+    let mut changes: Vec<(u32, u32, isize)> = Vec::new();
+    let mut deltas = Vec::with_capacity(stream.len());
+    let mut in_scc = false;
+
+    for line in stdout.lines() {
+        if line.trim() == COMMIT_MARKER {
+            // Apply retractions first so that a relabelled node isn't removed after being
+            // re-added.
+            changes.sort_by_key(|(_, _, weight)| *weight > 0);
+            for (node, label, weight) in changes.drain(..) {
+                if weight > 0 {
+                    labelling.insert(node, label);
+                } else if labelling.get(&node) == Some(&label) {
+                    labelling.remove(&node);
+                }
+            }
+
+            let next = normalize(&labelling);
+            deltas.push(diff(&normalized, &next));
+            normalized = next;
+            in_scc = false;
+        } else if let Some(relation) = line.strip_suffix(':') {
+            in_scc = relation == "StronglyConnected";
+        } else if in_scc {
+            changes.push(parse_change(line).ok_or_else(|| {
+                format!("'{}' printed an unexpected delta: {}", cli.display(), line)
+            })?);
+        }
+    }
+
+    if deltas.len() != stream.len() {
+        return Err(format!(
+            "'{}' only completed {} of {} commits",
+            cli.display(),
+            deltas.len(),
+            stream.len()
+        ));
+    }
+
+    Ok(deltas)
+}
+
+/// Parse a `dump_changes` line, e.g. `StronglyConnected{.node = 1, .regime = 0}: +1` or
+/// `(1, 0): -1`, into `(node, label, weight)`.
+fn parse_change(line: &str) -> Option<(u32, u32, isize)> {
+    let (record, weight) = line.rsplit_once(": ")?;
+    let mut numbers = record
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|number| !number.is_empty())
+        .map(|number| number.parse::<u32>());
+
+    match (numbers.next(), numbers.next(), numbers.next()) {
+        (Some(Ok(node)), Some(Ok(label)), None) => Some((node, label, weight.parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// Relabel every component by its smallest node.
+fn normalize(labelling: &BTreeMap<u32, u32>) -> BTreeMap<u32, u32> {
+    let mut min_node: BTreeMap<u32, u32> = BTreeMap::new();
+    for (&node, &label) in labelling.iter() {
+        min_node.entry(label).or_insert(node);
+    }
+
+    labelling
+        .iter()
+        .map(|(&node, label)| (node, min_node[label]))
+        .collect()
+}
+
+fn diff(before: &BTreeMap<u32, u32>, after: &BTreeMap<u32, u32>) -> Delta {
+    let mut delta = Delta::new();
+    for (&node, &label) in before.iter() {
+        if after.get(&node) != Some(&label) {
+            delta.insert((node, label), -1);
+        }
+    }
+    for (&node, &label) in after.iter() {
+        if before.get(&node) != Some(&label) {
+            delta.insert((node, label), 1);
+        }
+    }
+
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_round_trip() {
+        let text = "start;\ninsert Edge(0, 1),\ninsert Edge(1, 0);\ncommit dump_changes;\nstart;\ndelete Edge(0, 1);\ncommit dump_changes;\ntimestamp;\n";
+        let stream = parse_stream(text).unwrap();
+        assert_eq!(
+            stream,
+            vec![
+                vec![
+                    "insert Edge(0, 1)".to_string(),
+                    "insert Edge(1, 0)".to_string()
+                ],
+                vec!["delete Edge(0, 1)".to_string()],
+            ]
+        );
+        assert_eq!(parse_stream(&render_stream(&stream)).unwrap(), stream);
+    }
+
+    #[test]
+    fn normalized_changes() {
+        assert_eq!(
+            parse_change("StronglyConnected{.node = 12, .regime = 3}: +1"),
+            Some((12, 3, 1))
+        );
+        assert_eq!(parse_change("(4, 4): -1"), Some((4, 4, -1)));
+
+        // Relabelling a component from one of its nodes to another is not a change.
+        let before: BTreeMap<u32, u32> = vec![(1, 2), (2, 2)].into_iter().collect();
+        let after = normalize(&before);
+        assert_eq!(after, vec![(1, 1), (2, 1)].into_iter().collect());
+        assert!(diff(&after, &normalize(&after)).is_empty());
+    }
+}
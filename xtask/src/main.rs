@@ -0,0 +1,655 @@
+//! Cross-platform task runner for the demo, the equivalent of `scc.ps1`.
+//!
+//! Run it from the repository root with `cargo xtask <command> [flags]`.
+
+mod equiv;
+
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+const USAGE: &str = "\
+Usage: cargo xtask <command> [flags]
+
+Commands:
+    check          Validate the DDlog program
+    build          Compile the DDlog program and build its CLI executable
+    run            Run the CLI executable on the data file and open ddshow on the traces
+    regen          Regenerate the input data file
+    ovn-reviews    Run ddshow on the ovn-reviews traces
+    bench          Benchmark scc.dl against scc_transformer.dl and check that they agree,
+                   arguments after '--' are passed to both benchmark binaries
+    equiv          Feed the same random Edge stream to both programs and compare their
+                   StronglyConnected deltas after every commit, arguments after '--' are
+                   passed to scc_gen; on a mismatch a minimal reproducer is written to
+                   equiv-repro.dat
+
+Flags:
+    --release            Build in release mode
+    --transformer        Use scc_transformer.dl instead of scc.dl
+    --output-internal    Pass '--output-internal-relations' to ddlog
+    --workers <N>        The number of worker threads (default: 4)
+    --data-file <PATH>   The input data file (default: scc.dat)
+    --no-ddshow          Don't run ddshow after 'run'
+    --rebuild            Rebuild the executable before 'run'
+    --seeds <N>          The number of random streams 'equiv' checks (default: 10)
+    -h, --help           Print this message";
+
+const OLD_DDSHOW_SOURCE: &str = r#""https://github.com/ddlog-dev/ddshow", branch = "ddlog-4""#;
+const NEW_DDSHOW_SOURCE: &str = r#""https://github.com/Kixiron/ddshow", branch = "ddlog-5""#;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Task {
+    Check,
+    Build,
+    Run,
+    Regen,
+    OvnReviews,
+    Bench,
+    Equiv,
+}
+
+#[derive(Debug)]
+struct Args {
+    task: Task,
+    release: bool,
+    transformer: bool,
+    output_internal: bool,
+    workers: usize,
+    data_file: Option<PathBuf>,
+    no_ddshow: bool,
+    rebuild: bool,
+    forwarded: Vec<String>,
+    seeds: u64,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut task = None;
+        let mut parsed = Args {
+            task: Task::Check,
+            release: false,
+            transformer: false,
+            output_internal: false,
+            workers: 4,
+            data_file: None,
+            no_ddshow: false,
+            rebuild: false,
+            forwarded: Vec::new(),
+            seeds: 10,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                "--release" => parsed.release = true,
+                "--transformer" => parsed.transformer = true,
+                "--output-internal" => parsed.output_internal = true,
+                "--no-ddshow" => parsed.no_ddshow = true,
+                "--rebuild" => parsed.rebuild = true,
+                "--workers" | "-w" => {
+                    let workers = args.next().ok_or("--workers requires a value")?;
+                    parsed.workers = workers
+                        .parse()
+                        .map_err(|e| format!("invalid number of workers '{}': {}", workers, e))?;
+                }
+                "--" => parsed.forwarded.extend(&mut args),
+                "--seeds" => {
+                    let seeds = args.next().ok_or("--seeds requires a value")?;
+                    parsed.seeds = seeds
+                        .parse()
+                        .map_err(|e| format!("invalid number of seeds '{}': {}", seeds, e))?;
+                }
+                "--data-file" => {
+                    let path = args.next().ok_or("--data-file requires a value")?;
+                    parsed.data_file = Some(PathBuf::from(path));
+                }
+                command if task.is_none() && !command.starts_with('-') => {
+                    task = Some(match command {
+                        "check" => Task::Check,
+                        "build" => Task::Build,
+                        "run" => Task::Run,
+                        "regen" => Task::Regen,
+                        "ovn-reviews" => Task::OvnReviews,
+                        "bench" => Task::Bench,
+                        "equiv" => Task::Equiv,
+                        _ => return Err(format!("unknown command '{}'", command)),
+                    });
+                }
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+
+        parsed.task = task.ok_or("missing command")?;
+
+        if parsed.workers == 0 {
+            return Err("workers must be a non-zero integer".to_string());
+        }
+        if parsed.task != Task::Run && parsed.rebuild {
+            return Err("can only pass --rebuild with the 'run' subcommand".to_string());
+        }
+        if parsed.task != Task::Run && parsed.no_ddshow {
+            return Err("can only pass --no-ddshow with the 'run' subcommand".to_string());
+        }
+        if parsed.task != Task::Bench && parsed.task != Task::Equiv && !parsed.forwarded.is_empty()
+        {
+            return Err(
+                "can only pass arguments after '--' to the 'bench' and 'equiv' subcommands"
+                    .to_string(),
+            );
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Paths derived from the command line, mirroring the variables at the top
+/// of `scc.ps1`.
+struct Project {
+    root: PathBuf,
+    prefix: &'static str,
+    code_dir: PathBuf,
+    rust_dir: PathBuf,
+    trace_dir: PathBuf,
+    data_file: PathBuf,
+    executable: PathBuf,
+}
+
+impl Project {
+    fn new(args: &Args, transformer: bool) -> Self {
+        // `xtask` lives one directory below the repository root.
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .expect("xtask must live inside the repository")
+            .to_path_buf();
+
+        let prefix = if transformer {
+            "scc_transformer"
+        } else {
+            "scc"
+        };
+        let code_dir = root.join(prefix);
+        let rust_dir = code_dir.join(format!("{}_ddlog", prefix));
+        let executable = rust_dir
+            .join("target")
+            .join(if args.release { "release" } else { "debug" })
+            .join(format!("{}_cli{}", prefix, env::consts::EXE_SUFFIX));
+
+        // Relative data files are resolved against the caller's directory, since `regen`
+        // runs the generator from inside the crate directory.
+        let data_file = match &args.data_file {
+            Some(path) => env::current_dir()
+                .map(|cwd| cwd.join(path))
+                .unwrap_or_else(|_| path.clone()),
+            None => root.join("scc.dat"),
+        };
+
+        Project {
+            data_file,
+            trace_dir: code_dir.join("traces"),
+            root,
+            prefix,
+            code_dir,
+            rust_dir,
+            executable,
+        }
+    }
+
+    fn ddlog_file(&self) -> String {
+        format!("{}.dl", self.prefix)
+    }
+}
+
+fn main() {
+    let result = Args::parse(env::args().skip(1)).and_then(|args| {
+        let project = Project::new(&args, args.transformer);
+        match args.task {
+            Task::Check => check(&project),
+            Task::Build => build(&project, &args),
+            Task::Run => run(&project, &args),
+            Task::Regen => regen(&project, &args),
+            Task::OvnReviews => ovn_reviews(&project, &args),
+            Task::Bench => bench(&args),
+            Task::Equiv => equiv(&args),
+        }
+    });
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn check(project: &Project) -> Result<(), String> {
+    require_tool("ddlog")?;
+
+    let ddlog_file = project.ddlog_file();
+    exec(
+        Command::new("ddlog")
+            .args(["-i", &ddlog_file, "--action", "validate"])
+            .current_dir(&project.code_dir),
+    )
+}
+
+fn build(project: &Project, args: &Args) -> Result<(), String> {
+    require_tool("ddlog")?;
+    require_tool("cargo")?;
+
+    let ddlog_file = project.ddlog_file();
+    let mut ddlog = Command::new("ddlog");
+    ddlog
+        .args([
+            "-i",
+            &ddlog_file,
+            "--omit-profile",
+            "--omit-workspace",
+            "--no-staticlib",
+        ])
+        .current_dir(&project.code_dir);
+    if args.output_internal {
+        ddlog.arg("--output-internal-relations");
+    }
+    exec(&mut ddlog).map_err(|e| format!("failed to compile {}: {}", ddlog_file, e))?;
+
+    // Rewrite all the dependencies on `ddlog-dev/ddshow` to point at `Kixiron/ddshow` since
+    // the ddlog-dev repo is behind on ddshow releases
+    rewrite_ddshow_sources(&project.rust_dir)?;
+
+    let bin = format!("{}_cli", project.prefix);
+    let mut cargo = Command::new("cargo");
+    cargo
+        .args(["build", "--bin", &bin])
+        .current_dir(&project.rust_dir);
+    if args.release {
+        cargo.arg("--release");
+    }
+
+    // If the rustflags aren't set, use lld for linking (when it's available) and target the
+    // native cpu
+    if env::var_os("RUSTFLAGS").is_none_or(|flags| flags.is_empty()) {
+        let rustflags = if find_tool("ld.lld").is_some() || find_tool("lld").is_some() {
+            "-C link-arg=-fuse-ld=lld -C target-cpu=native"
+        } else {
+            "-C target-cpu=native"
+        };
+        cargo.env("RUSTFLAGS", rustflags);
+    }
+
+    exec(&mut cargo)
+        .map_err(|e| format!("failed to build the executable for {}: {}", ddlog_file, e))
+}
+
+fn run(project: &Project, args: &Args) -> Result<(), String> {
+    if !args.no_ddshow {
+        require_tool("ddshow")?;
+    }
+
+    // If `--rebuild` was passed or there's not yet a built executable, build it
+    if args.rebuild || !project.executable.is_file() {
+        build(project, args)?;
+    }
+
+    // Remove the old traces if there are any
+    if project.trace_dir.is_dir() {
+        fs::remove_dir_all(&project.trace_dir)
+            .map_err(|e| format!("failed to remove '{}': {}", project.trace_dir.display(), e))?;
+    }
+
+    // If there's no input file then regenerate it
+    if !project.data_file.is_file() {
+        regen(project, args)?;
+    }
+
+    println!("Loading data file...");
+    let data = fs::File::open(&project.data_file)
+        .map_err(|e| format!("failed to open '{}': {}", project.data_file.display(), e))?;
+
+    println!(
+        "Running {} with {} workers...",
+        project.prefix, args.workers
+    );
+    let workers = args.workers.to_string();
+    exec(
+        Command::new(&project.executable)
+            .arg("--workers")
+            .arg(&workers)
+            .arg("--timely-trace-dir")
+            .arg(&project.trace_dir)
+            .arg("--differential-trace-dir")
+            .arg(&project.trace_dir)
+            .stdin(Stdio::from(data)),
+    )?;
+
+    if !args.no_ddshow {
+        println!("Running ddshow...");
+        ddshow(&project.code_dir, &project.trace_dir, args.workers)?;
+    }
+
+    Ok(())
+}
+
+fn regen(project: &Project, args: &Args) -> Result<(), String> {
+    require_tool("cargo")?;
+    println!("Generating {}...", project.data_file.display());
+
+    // The defaults match the old networkx `gnm_random_graph(1000, 10000)` workload
+    let mut cargo = Command::new("cargo");
+    cargo
+        .args(["run", "--bin", "scc_gen"])
+        .current_dir(project.root.join("scc").join("scc_ddlog"));
+    if args.release {
+        cargo.arg("--release");
+    }
+    cargo
+        .args(["--", "--nodes", "1000", "--edges", "10000", "--output"])
+        .arg(&project.data_file);
+
+    exec(&mut cargo).map_err(|e| format!("failed to run the workload generator: {}", e))
+}
+
+fn ovn_reviews(project: &Project, args: &Args) -> Result<(), String> {
+    require_tool("ddshow")?;
+    println!("Running ddshow...");
+
+    ddshow(
+        &project.root,
+        &project.root.join("ovn-reviews-trace"),
+        args.workers,
+    )
+}
+
+fn bench(args: &Args) -> Result<(), String> {
+    require_tool("cargo")?;
+
+    let mut labellings = Vec::new();
+    for project in [Project::new(args, false), Project::new(args, true)].iter() {
+        println!("Benchmarking {}...", project.ddlog_file());
+
+        let labels = env::temp_dir().join(format!("{}_bench_labels.txt", project.prefix));
+        let bin = format!("{}_bench", project.prefix);
+        let mut cargo = Command::new("cargo");
+        cargo
+            .args(["run", "--bin", &bin])
+            .current_dir(&project.rust_dir);
+        if args.release {
+            cargo.arg("--release");
+        }
+        cargo
+            .arg("--")
+            .args(&args.forwarded)
+            .arg("--labels")
+            .arg(&labels);
+
+        exec(&mut cargo).map_err(|e| format!("failed to benchmark {}: {}", project.prefix, e))?;
+        labellings.push(
+            fs::read_to_string(&labels)
+                .map_err(|e| format!("failed to read '{}': {}", labels.display(), e))?,
+        );
+    }
+
+    // Both labellings are normalized to the smallest node of every component, one
+    // `node label` pair per line, so they must match line by line
+    let scc: Vec<&str> = labellings[0].lines().collect();
+    let transformer: Vec<&str> = labellings[1].lines().collect();
+    if let Some(line) =
+        (0..scc.len().max(transformer.len())).find(|&i| scc.get(i) != transformer.get(i))
+    {
+        return Err(format!(
+            "scc and scc_transformer disagree on StronglyConnected: scc has {:?}, scc_transformer has {:?}",
+            scc.get(line).unwrap_or(&"<nothing>"),
+            transformer.get(line).unwrap_or(&"<nothing>"),
+        ));
+    }
+
+    println!(
+        "scc and scc_transformer agree on {} labelled nodes",
+        scc.len()
+    );
+    Ok(())
+}
+
+fn equiv(args: &Args) -> Result<(), String> {
+    require_tool("cargo")?;
+
+    let (scc, transformer) = (Project::new(args, false), Project::new(args, true));
+    for project in [&scc, &transformer].iter() {
+        let bin = format!("{}_cli", project.prefix);
+        let mut cargo = Command::new("cargo");
+        cargo
+            .args(["build", "--bin", &bin])
+            .current_dir(&project.rust_dir);
+        if args.release {
+            cargo.arg("--release");
+        }
+        exec(&mut cargo).map_err(|e| format!("failed to build {}: {}", bin, e))?;
+    }
+
+    // Either check the given data file or a batch of freshly generated streams
+    let streams: Vec<(String, equiv::Stream)> = match &args.data_file {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+            vec![(path.display().to_string(), equiv::parse_stream(&text)?)]
+        }
+        None => (0..args.seeds)
+            .map(|seed| Ok((format!("seed {}", seed), generate_stream(&scc, args, seed)?)))
+            .collect::<Result<_, String>>()?,
+    };
+
+    for (name, stream) in streams.iter() {
+        println!("Checking {} ({} transactions)...", name, stream.len());
+
+        let (commit, scc_delta, transformer_delta) = match equiv::compare(
+            &scc.executable,
+            &transformer.executable,
+            args.workers,
+            stream,
+        )? {
+            equiv::Outcome::Agree => continue,
+            equiv::Outcome::Mismatch {
+                commit,
+                scc,
+                transformer,
+            } => (commit, scc, transformer),
+        };
+
+        println!(
+            "Mismatch at commit {}:\n  scc:             {:?}\n  scc_transformer: {:?}\nShrinking...",
+            commit, scc_delta, transformer_delta
+        );
+        let repro = equiv::shrink(
+            &scc.executable,
+            &transformer.executable,
+            args.workers,
+            stream,
+            commit,
+        );
+
+        let path = scc.root.join("equiv-repro.dat");
+        fs::write(&path, equiv::render_stream(&repro))
+            .map_err(|e| format!("failed to write '{}': {}", path.display(), e))?;
+
+        return Err(format!(
+            "scc and scc_transformer disagree on {}, a {} transaction reproducer was written to '{}'",
+            name,
+            repro.len(),
+            path.display()
+        ));
+    }
+
+    println!("scc and scc_transformer agree on all streams");
+    Ok(())
+}
+
+/// Generate a random `Edge` stream with `scc_gen`, small and incremental by default so that
+/// every commit (including deletions) gets compared.
+fn generate_stream(scc: &Project, args: &Args, seed: u64) -> Result<equiv::Stream, String> {
+    let path = env::temp_dir().join(format!("xtask_equiv_{}.dat", seed));
+
+    let mut cargo = Command::new("cargo");
+    cargo
+        .args(["run", "--quiet", "--bin", "scc_gen"])
+        .current_dir(&scc.rust_dir);
+    if args.release {
+        cargo.arg("--release");
+    }
+    cargo.arg("--");
+    if args.forwarded.is_empty() {
+        cargo.args([
+            "--kind",
+            "planted-scc",
+            "--nodes",
+            "40",
+            "--edges",
+            "80",
+            "--transactions",
+            "20",
+            "--delete-ratio",
+            "0.3",
+        ]);
+    } else {
+        cargo.args(&args.forwarded);
+    }
+    cargo
+        .arg("--seed")
+        .arg(seed.to_string())
+        .arg("--output")
+        .arg(&path);
+    exec(&mut cargo).map_err(|e| format!("failed to run the workload generator: {}", e))?;
+
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+    equiv::parse_stream(&text)
+}
+
+fn ddshow(dir: &Path, trace_dir: &Path, workers: usize) -> Result<(), String> {
+    exec(
+        Command::new("ddshow")
+            .arg("--workers")
+            .arg(workers.to_string())
+            .arg("--replay-logs")
+            .arg(trace_dir)
+            .args(["--differential", "--disable-timeline"])
+            .current_dir(dir),
+    )
+}
+
+/// Point every `ddshow-sink` dependency in the `Cargo.toml` files below `dir`
+/// at the `Kixiron/ddshow` fork.
+fn rewrite_ddshow_sources(dir: &Path) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("failed to read '{}': {}", dir.display(), e))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("failed to read '{}': {}", dir.display(), e))?
+            .path();
+
+        if path.is_dir() {
+            if path.file_name() != Some(OsStr::new("target")) {
+                rewrite_ddshow_sources(&path)?;
+            }
+        } else if path.file_name() == Some(OsStr::new("Cargo.toml")) {
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+
+            if let Some(rewritten) = rewrite_ddshow_source(&contents) {
+                fs::write(&path, rewritten)
+                    .map_err(|e| format!("failed to write '{}': {}", path.display(), e))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the rewritten manifest, or `None` if it doesn't need rewriting.
+fn rewrite_ddshow_source(manifest: &str) -> Option<String> {
+    if manifest.contains("ddshow-sink") && manifest.contains(OLD_DDSHOW_SOURCE) {
+        Some(manifest.replace(OLD_DDSHOW_SOURCE, NEW_DDSHOW_SOURCE))
+    } else {
+        None
+    }
+}
+
+fn exec(command: &mut Command) -> Result<(), String> {
+    let status = command
+        .status()
+        .map_err(|e| format!("failed to run {:?}: {}", command.get_program(), e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{:?} exited with {}",
+            command.get_program(),
+            status
+        ))
+    }
+}
+
+fn require_tool(name: &str) -> Result<PathBuf, String> {
+    find_tool(name).ok_or_else(|| format!("could not find {} executable on the current path", name))
+}
+
+fn find_tool(name: &str) -> Option<PathBuf> {
+    let file = format!("{}{}", name, env::consts::EXE_SUFFIX);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&file))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddshow_source_rewrite() {
+        let manifest = "[dependencies]\nddshow-sink =  { git = \"https://github.com/ddlog-dev/ddshow\", branch = \"ddlog-4\" }\n";
+        assert_eq!(
+            rewrite_ddshow_source(manifest).as_deref(),
+            Some("[dependencies]\nddshow-sink =  { git = \"https://github.com/Kixiron/ddshow\", branch = \"ddlog-5\" }\n"),
+        );
+        assert_eq!(rewrite_ddshow_source(NEW_DDSHOW_SOURCE), None);
+    }
+
+    #[test]
+    fn parse_args() {
+        let args = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+
+        let parsed = args(&["run", "--transformer", "--workers", "2", "--no-ddshow"]).unwrap();
+        assert_eq!(parsed.task, Task::Run);
+        assert!(parsed.transformer && parsed.no_ddshow);
+        assert_eq!(parsed.workers, 2);
+
+        assert!(args(&["build", "--rebuild"]).is_err());
+        assert!(args(&["run", "--workers", "0"]).is_err());
+        assert!(args(&["frobnicate"]).is_err());
+
+        let parsed = args(&["bench", "--release", "--", "--workers", "1,8"]).unwrap();
+        assert_eq!(parsed.task, Task::Bench);
+        assert_eq!(parsed.forwarded, ["--workers", "1,8"]);
+        assert!(args(&["run", "--", "--workers", "1"]).is_err());
+    }
+
+    #[test]
+    fn data_file_is_absolute() {
+        let args = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+
+        let project = Project::new(&args(&["regen", "--data-file", "graph.dat"]), false);
+        assert_eq!(
+            project.data_file,
+            env::current_dir().unwrap().join("graph.dat")
+        );
+
+        let project = Project::new(&args(&["run"]), false);
+        assert_eq!(project.data_file, project.root.join("scc.dat"));
+    }
+}